@@ -2,28 +2,41 @@ use anyhow::Context;
 use anyhow::Result;
 use heleny_proto::AnyMessage;
 use heleny_proto::KERNEL_SERVICE;
+use heleny_proto::LatencyTable;
+use heleny_proto::Priority;
+use heleny_proto::Receipt;
 use heleny_proto::SignedMessage;
 use heleny_proto::TokenMessage;
+use std::fmt;
+use std::time::Duration;
 use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+use tokio::time::timeout;
+use tracing::debug;
 use uuid::Uuid;
 
+use crate::lane::BusReceiver;
+use crate::trace;
+
 pub type SubEndpoint = mpsc::Sender<Box<dyn AnyMessage>>;
 
 #[derive(Debug)]
 pub struct Endpoint {
     token: Uuid,
     to_bus: mpsc::Sender<TokenMessage>,
-    from_bus: Option<mpsc::Receiver<SignedMessage>>,
+    from_bus: Option<BusReceiver>,
     to_self: Option<SubEndpoint>,
     from_sub_endpoint: Option<mpsc::Receiver<Box<dyn AnyMessage>>>,
+    latency: Option<LatencyTable>,
 }
 
 impl Endpoint {
     pub fn new(
         token: Uuid,
         to_bus: mpsc::Sender<TokenMessage>,
-        from_bus: mpsc::Receiver<SignedMessage>,
+        from_bus: BusReceiver,
         sub_buffer: usize,
+        latency: LatencyTable,
     ) -> Self {
         let (to_self, from_sub_endpoint) = mpsc::channel(sub_buffer);
         Self {
@@ -32,6 +45,7 @@ impl Endpoint {
             from_bus: Some(from_bus),
             to_self: Some(to_self),
             from_sub_endpoint: Some(from_sub_endpoint),
+            latency: Some(latency),
         }
     }
 
@@ -42,6 +56,7 @@ impl Endpoint {
             from_bus: None,
             to_self: None,
             from_sub_endpoint: None,
+            latency: None,
         }
     }
 
@@ -50,7 +65,7 @@ impl Endpoint {
         target: &str,
         payload: Box<dyn AnyMessage + 'static>,
     ) -> Result<()> {
-        let msg = TokenMessage::new(target.to_string(), self.token, payload);
+        let msg = self.message(target, payload);
         self.to_bus
             .send(msg)
             .await
@@ -61,30 +76,144 @@ impl Endpoint {
         self.send_box(target, Box::new(payload)).await
     }
 
+    /// 以控制优先级发送, 目标会先于数据消息处理它
+    pub async fn send_control<T: AnyMessage>(&self, target: &str, payload: T) -> Result<()> {
+        let msg = self
+            .message(target, Box::new(payload))
+            .with_priority(Priority::Control);
+        self.to_bus
+            .send(msg)
+            .await
+            .map_err(|e| anyhow::anyhow!("发送消息到 Kernel 失败: {}", e))
+    }
+
+    /// 请求/响应调用: 用 `req` 把回复通道装进消息, 发送给 `target` 并在 `limit` 内等待回复
+    pub async fn call<Req, Resp>(
+        &self,
+        target: &str,
+        req: impl FnOnce(oneshot::Sender<Resp>) -> Req,
+        limit: Duration,
+    ) -> Result<Resp, CallError>
+    where
+        Req: AnyMessage,
+        Resp: Send + 'static,
+    {
+        self.call_with_priority(target, req, limit, Priority::Data)
+            .await
+    }
+
+    /// 以控制优先级发起调用
+    pub async fn call_control<Req, Resp>(
+        &self,
+        target: &str,
+        req: impl FnOnce(oneshot::Sender<Resp>) -> Req,
+        limit: Duration,
+    ) -> Result<Resp, CallError>
+    where
+        Req: AnyMessage,
+        Resp: Send + 'static,
+    {
+        self.call_with_priority(target, req, limit, Priority::Control)
+            .await
+    }
+
+    async fn call_with_priority<Req, Resp>(
+        &self,
+        target: &str,
+        req: impl FnOnce(oneshot::Sender<Resp>) -> Req,
+        limit: Duration,
+        priority: Priority,
+    ) -> Result<Resp, CallError>
+    where
+        Req: AnyMessage,
+        Resp: Send + 'static,
+    {
+        let correlation = Uuid::new_v4();
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let (receipt_tx, receipt_rx) = oneshot::channel();
+        let msg = self
+            .message(target, Box::new(req(reply_tx)))
+            .with_correlation(correlation)
+            .with_receipt(receipt_tx)
+            .with_priority(priority);
+        debug!("调用 {}, 关联 id: {}", target, correlation);
+        let error = |kind| CallError {
+            target: target.to_string(),
+            correlation,
+            kind,
+        };
+        self.to_bus
+            .send(msg)
+            .await
+            .map_err(|_| error(CallErrorKind::BusClosed))?;
+        let wait = async {
+            let address = match receipt_rx.await {
+                Ok(Receipt::Delivered(address)) => address,
+                Ok(Receipt::NotFound) => return Err(CallErrorKind::NotFound),
+                Ok(Receipt::Closed) => return Err(CallErrorKind::Crashed),
+                Ok(Receipt::Full) => return Err(CallErrorKind::Overloaded),
+                Err(_) => return Err(CallErrorKind::Rejected),
+            };
+            match reply_rx.await {
+                Ok(resp) => Ok(resp),
+                // 服务崩溃时回复通道和接收端一起被丢弃, 稍等一下接收端关闭再判断
+                Err(_) => match timeout(CRASH_GRACE, address.closed()).await {
+                    Ok(_) => Err(CallErrorKind::Crashed),
+                    Err(_) => Err(CallErrorKind::ReplyDropped),
+                },
+            }
+        };
+        match timeout(limit, wait).await {
+            Ok(result) => result.map_err(error),
+            Err(_) => Err(error(CallErrorKind::Timeout(limit))),
+        }
+    }
+
+    /// 签名前的消息, 附带当前的 trace
+    fn message(&self, target: &str, payload: Box<dyn AnyMessage>) -> TokenMessage {
+        TokenMessage::new(target.to_string(), self.token, payload).with_trace(trace::outgoing())
+    }
+
     pub fn create_sub_endpoint(&self) -> Result<SubEndpoint> {
         self.to_self
             .clone()
             .context("最小化启动的 Endpoint 不能使用 SubEndpoint")
     }
 
+    /// 记录服务处理一条消息的耗时
+    pub fn record_latency(&self, name: &str, elapsed: Duration) {
+        if let Some(latency) = &self.latency
+            && let Ok(mut table) = latency.lock()
+        {
+            table.entry(name.to_string()).or_default().record(elapsed);
+        }
+    }
+
+    /// 只能发送的 Endpoint, 与本 Endpoint 共用耗时统计
     pub fn create_sender_endpoint(&self) -> Endpoint {
-        Endpoint::new_minimal(self.token, self.to_bus.clone())
+        let mut endpoint = Endpoint::new_minimal(self.token, self.to_bus.clone());
+        endpoint.latency = self.latency.clone();
+        endpoint
+    }
+
+    /// 用另一个 token (如登录时签发的用户 token) 发送的 Endpoint
+    pub fn create_token_endpoint(&self, token: Uuid) -> Endpoint {
+        let mut endpoint = Endpoint::new_minimal(token, self.to_bus.clone());
+        endpoint.latency = self.latency.clone();
+        endpoint
     }
 
     pub fn send_once(
         &self,
         payload: Box<dyn AnyMessage>,
     ) -> (mpsc::Sender<TokenMessage>, TokenMessage) {
-        let msg = TokenMessage::new(KERNEL_SERVICE.to_string(), self.token, payload);
+        let msg = self
+            .message(KERNEL_SERVICE, payload)
+            .with_priority(Priority::Control);
         (self.to_bus.clone(), msg)
     }
 
-    pub fn get_rx(
-        &mut self,
-    ) -> Result<(
-        mpsc::Receiver<SignedMessage>,
-        mpsc::Receiver<Box<dyn AnyMessage>>,
-    )> {
+    pub fn get_rx(&mut self) -> Result<(BusReceiver, mpsc::Receiver<Box<dyn AnyMessage>>)> {
         let from_bus = self.from_bus.take().context("没有来自 Bus 消息的接收端")?;
         let from_sub_endpoint = self
             .from_sub_endpoint
@@ -100,3 +229,53 @@ impl Endpoint {
         msg
     }
 }
+
+/// 判断目标是否崩溃时, 等待其接收端关闭的时间
+const CRASH_GRACE: Duration = Duration::from_millis(50);
+
+/// `Endpoint::call` 的错误
+#[derive(Debug)]
+pub struct CallError {
+    pub target: String,
+    pub correlation: Uuid,
+    pub kind: CallErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallErrorKind {
+    /// 无法把消息交给 Bus
+    BusClosed,
+    /// Bus 没有投递这条消息, 例如 token 无效
+    Rejected,
+    /// 没有找到目标服务
+    NotFound,
+    /// 目标服务已崩溃或已停止
+    Crashed,
+    /// 目标服务积压的消息太多, Bus 丢弃了这条消息
+    Overloaded,
+    /// 目标服务收到了消息, 但丢弃了回复通道
+    ReplyDropped,
+    /// 等待回复超时
+    Timeout(Duration),
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match &self.kind {
+            CallErrorKind::BusClosed => "发送消息到 Bus 失败".to_string(),
+            CallErrorKind::Rejected => "Bus 拒绝投递".to_string(),
+            CallErrorKind::NotFound => "未找到服务".to_string(),
+            CallErrorKind::Crashed => "服务已崩溃或已停止".to_string(),
+            CallErrorKind::Overloaded => "服务积压的消息过多".to_string(),
+            CallErrorKind::ReplyDropped => "服务丢弃了回复".to_string(),
+            CallErrorKind::Timeout(limit) => format!("{:?} 内未收到回复", limit),
+        };
+        write!(
+            f,
+            "调用 {} 失败: {} (关联 id: {})",
+            self.target, reason, self.correlation
+        )
+    }
+}
+
+impl std::error::Error for CallError {}
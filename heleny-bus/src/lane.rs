@@ -0,0 +1,65 @@
+use heleny_proto::Priority;
+use heleny_proto::SignedMessage;
+use tokio::sync::mpsc;
+
+/// 控制消息队列的长度, 与数据消息分开计算
+pub const CONTROL_BUFFER: usize = 16;
+
+/// 服务在 Bus 上的地址, 控制消息和数据消息分开排队
+#[derive(Debug, Clone)]
+pub struct Address {
+    control: mpsc::Sender<SignedMessage>,
+    data: mpsc::Sender<SignedMessage>,
+}
+
+impl Address {
+    pub fn lane(&self, priority: Priority) -> &mpsc::Sender<SignedMessage> {
+        match priority {
+            Priority::Control => &self.control,
+            Priority::Data => &self.data,
+        }
+    }
+
+    /// 两条队列里还未被取走的消息数
+    pub fn queued(&self) -> usize {
+        [&self.control, &self.data]
+            .iter()
+            .map(|lane| lane.max_capacity() - lane.capacity())
+            .sum()
+    }
+}
+
+/// Endpoint 从 Bus 接收消息的一端, 总是先取控制消息
+#[derive(Debug)]
+pub struct BusReceiver {
+    control: mpsc::Receiver<SignedMessage>,
+    data: mpsc::Receiver<SignedMessage>,
+}
+
+impl BusReceiver {
+    /// 两条队列都关闭时返回 None
+    pub async fn recv(&mut self) -> Option<SignedMessage> {
+        tokio::select! {
+            biased;
+            Some(msg) = self.control.recv() => Some(msg),
+            Some(msg) = self.data.recv() => Some(msg),
+            else => None,
+        }
+    }
+}
+
+/// 新建一对地址和接收端, `buffer` 为数据消息队列长度
+pub fn channel(buffer: usize) -> (Address, BusReceiver) {
+    let (control_tx, control_rx) = mpsc::channel(CONTROL_BUFFER);
+    let (data_tx, data_rx) = mpsc::channel(buffer);
+    (
+        Address {
+            control: control_tx,
+            data: data_tx,
+        },
+        BusReceiver {
+            control: control_rx,
+            data: data_rx,
+        },
+    )
+}
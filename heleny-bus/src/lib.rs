@@ -1,30 +1,57 @@
 pub mod endpoint;
+pub mod lane;
 pub mod midware;
+pub mod recorder;
+pub mod trace;
 
 use anyhow::Context;
 use anyhow::Result;
+use chrono::Local;
+use heleny_proto::BUS_NAME;
+use heleny_proto::BusPolicy;
+use heleny_proto::DeadLetter;
+use heleny_proto::DeliveryFailure;
+use heleny_proto::LatencyTable;
+use heleny_proto::PolicyAction;
+use heleny_proto::Priority;
+use heleny_proto::Receipt;
+use heleny_proto::ResourcePayload;
+use heleny_proto::RouteEvent;
 use heleny_proto::ServiceRole;
 use heleny_proto::SignedMessage;
 use heleny_proto::TokenMessage;
+use heleny_proto::short_type_name;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::time::Duration;
 use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::oneshot;
+use tokio::sync::watch;
 use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio::time::MissedTickBehavior;
+use tokio::time::interval;
 use tokio::time::timeout;
 use tracing::Instrument;
+use tracing::debug;
+use tracing::info;
 use tracing::info_span;
 use tracing::warn;
 use uuid::Uuid;
 
 use crate::endpoint::Endpoint;
+use crate::lane::Address;
+use crate::midware::Midware;
+use crate::midware::MidwareChain;
 
 pub enum BusMessage {
     AddEndpoint {
         token: Uuid,
         name: String,
         role: ServiceRole,
-        address: mpsc::Sender<SignedMessage>,
+        address: Address,
         feedback: oneshot::Sender<()>,
     },
     AddProxyEndpoint {
@@ -35,32 +62,100 @@ pub enum BusMessage {
         feedback: oneshot::Sender<()>,
     },
     RegisterStats {
-        sender: mpsc::Sender<(String, String)>,
+        sender: mpsc::Sender<RouteEvent>,
+        feedback: oneshot::Sender<()>,
     },
+    /// 为已注册的服务签发一个 User 身份的 token, 服务自己的 token 不变
     SetUser {
         name: String,
+        feedback: oneshot::Sender<Uuid>,
+    },
+    /// 吊销单个 token, 之后携带它的消息都会被拒绝
+    RevokeToken {
+        token: Uuid,
+        feedback: oneshot::Sender<()>,
+    },
+    /// 移除服务的路由, 并吊销它和它的代理持有的所有 token
+    RemoveEndpoint {
+        name: String,
+        feedback: oneshot::Sender<()>,
+    },
+    AddMidware {
+        midware: Box<dyn Midware>,
+        feedback: oneshot::Sender<()>,
+    },
+    RemoveMidware {
+        name: String,
+        feedback: oneshot::Sender<bool>,
+    },
+    SetPolicy {
+        policy: BusPolicy,
     },
+    /// 服务开始启动或启动失败, 启动中的服务注册 Endpoint 前发给它的消息会暂存
+    SetStarting {
+        name: String,
+        starting: bool,
+    },
+}
+
+/// 死信最多保留的条数
+const DEAD_LETTER_CAPACITY: usize = 100;
+/// 同时等待重投的消息上限
+const PENDING_CAPACITY: usize = 64;
+/// 目标仍在启动时, 消息最多等待多久
+const RETRY_LIMIT: Duration = Duration::from_secs(5);
+/// 目标的一条接收队列已满时, 最多暂存多少条消息
+const OVERFLOW_CAPACITY: usize = 64;
+/// 暂存的消息多久尝试放回接收队列一次
+const OVERFLOW_TICK: Duration = Duration::from_millis(20);
+
+/// 等待目标启动后重投的消息
+struct Pending {
+    msg: SignedMessage,
+    receipt: Option<oneshot::Sender<Receipt>>,
+    deadline: Instant,
+}
+
+/// 目标接收队列已满时暂存的消息
+struct Parked {
+    msg: SignedMessage,
+    receipt: Option<oneshot::Sender<Receipt>>,
 }
 
 pub struct BusHandle {
     endpoint_to_bus: mpsc::Sender<TokenMessage>,
     handle_to_bus: mpsc::Sender<BusMessage>,
     handle: JoinHandle<()>,
+    dead_letters: watch::Receiver<ResourcePayload>,
+    latency: LatencyTable,
 }
 
 pub struct Bus {
     from_endpoints: Option<mpsc::Receiver<TokenMessage>>,
     from_handle: Option<mpsc::Receiver<BusMessage>>,
-    router: HashMap<String, mpsc::Sender<SignedMessage>>,
+    router: HashMap<String, Address>,
     tokens: HashMap<Uuid, (String, ServiceRole)>,
-    stats_tx: Option<mpsc::Sender<(String, String)>>,
+    stats_tx: Option<mpsc::Sender<RouteEvent>>,
+    /// 统计接收方跟不上时连续丢弃的样本数
+    stats_dropped: usize,
+    /// 代理 Endpoint 名到被代理服务名
+    proxies: HashMap<String, String>,
+    midwares: MidwareChain,
+    policy: BusPolicy,
+    /// 正在启动, 还没有注册 Endpoint 的服务
+    starting: HashSet<String>,
+    pending: VecDeque<Pending>,
+    /// (目标, 优先级) 到接收队列已满时暂存的消息, 按发送顺序放回
+    overflow: HashMap<(String, Priority), VecDeque<Parked>>,
+    dead_letters: VecDeque<DeadLetter>,
+    dead_letters_tx: watch::Sender<ResourcePayload>,
 }
 
 impl Bus {
     pub fn new(
         from_endpoints: mpsc::Receiver<TokenMessage>,
         from_handle: mpsc::Receiver<BusMessage>,
-        address_map: HashMap<String, mpsc::Sender<SignedMessage>>,
+        address_map: HashMap<String, Address>,
         tokens: HashMap<Uuid, (String, ServiceRole)>,
     ) -> Bus {
         Self {
@@ -69,9 +164,26 @@ impl Bus {
             router: address_map,
             tokens,
             stats_tx: None,
+            stats_dropped: 0,
+            proxies: HashMap::new(),
+            midwares: MidwareChain::default(),
+            policy: BusPolicy::default(),
+            starting: HashSet::new(),
+            pending: VecDeque::new(),
+            overflow: HashMap::new(),
+            dead_letters: VecDeque::new(),
+            dead_letters_tx: watch::channel(ResourcePayload::DeadLetters {
+                letters: VecDeque::new(),
+            })
+            .0,
         }
     }
 
+    /// 订阅死信列表
+    pub fn subscribe_dead_letters(&self) -> watch::Receiver<ResourcePayload> {
+        self.dead_letters_tx.subscribe()
+    }
+
     pub fn start(mut bus: Bus) -> JoinHandle<()> {
         let span = info_span!("Bus");
         tokio::spawn(
@@ -90,6 +202,10 @@ impl Bus {
                         return;
                     }
                 };
+                let mut retry_interval = interval(Duration::from_secs(1));
+                retry_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                let mut overflow_interval = interval(OVERFLOW_TICK);
+                overflow_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
                 loop {
                     tokio::select! {
                         Some(msg) = from_endpoints.recv() => {
@@ -102,6 +218,12 @@ impl Bus {
                                 warn!("{}",e);
                             };
                         }
+                        _ = retry_interval.tick(), if !bus.pending.is_empty() => {
+                            bus.retry_pending();
+                        }
+                        _ = overflow_interval.tick(), if !bus.overflow.is_empty() => {
+                            bus.drain_all_overflow();
+                        }
                     }
                 }
             }
@@ -118,18 +240,42 @@ impl Bus {
                 address,
                 feedback,
             } => {
+                // 同名服务重启后旧 token 不再有效
+                self.tokens.retain(|_, (exist_name, _)| exist_name != &name);
                 self.tokens.insert(token, (name.clone(), role));
+                // 代理跟随被代理服务的新地址
+                for (proxy_name, proxy) in &self.proxies {
+                    if proxy == &name {
+                        self.router.insert(proxy_name.clone(), address.clone());
+                    }
+                }
+                self.starting.remove(&name);
                 self.router.insert(name, address);
                 let _ = feedback.send(());
+                self.retry_pending();
             }
-            BusMessage::RegisterStats { sender } => self.stats_tx = Some(sender),
-            BusMessage::SetUser { name } => {
-                let user = self
-                    .tokens
-                    .iter_mut()
-                    .find(|(_id, (exist_name, _role))| exist_name == &name)
-                    .context("未找到该用户")?;
-                user.1.1 = ServiceRole::User;
+            BusMessage::RegisterStats { sender, feedback } => {
+                self.stats_tx = Some(sender);
+                self.stats_dropped = 0;
+                let _ = feedback.send(());
+            }
+            BusMessage::SetUser { name, feedback } => {
+                if !self.router.contains_key(&name) {
+                    return Err(anyhow::anyhow!("未找到该用户: {}", name));
+                }
+                let token = Uuid::new_v4();
+                self.tokens.insert(token, (name, ServiceRole::User));
+                let _ = feedback.send(token);
+            }
+            BusMessage::RevokeToken { token, feedback } => {
+                if let Some((name, _)) = self.tokens.remove(&token) {
+                    info!("已吊销 {} 的 token", name);
+                }
+                let _ = feedback.send(());
+            }
+            BusMessage::RemoveEndpoint { name, feedback } => {
+                self.remove_endpoint(&name);
+                let _ = feedback.send(());
             }
             BusMessage::AddProxyEndpoint {
                 token,
@@ -144,15 +290,44 @@ impl Bus {
                     .get(&proxy)
                     .context(format!("找不到代理 {} 的地址", proxy))?
                     .clone();
+                self.proxies.insert(name.clone(), proxy);
                 self.router.insert(name, tx);
                 let _ = feedback.send(());
+                self.retry_pending();
+            }
+            BusMessage::AddMidware { midware, feedback } => {
+                info!("注册拦截器: {}", midware.name());
+                self.midwares.push(midware);
+                let _ = feedback.send(());
+            }
+            BusMessage::RemoveMidware { name, feedback } => {
+                let removed = self.midwares.remove(&name);
+                let _ = feedback.send(removed);
+                if !removed {
+                    return Err(anyhow::anyhow!("未找到拦截器: {}", name));
+                }
+                info!("移除拦截器: {}", name);
+            }
+            BusMessage::SetPolicy { policy } => {
+                info!("更新访问控制策略, 共 {} 条规则", policy.rules.len());
+                self.policy = policy;
             }
+            BusMessage::SetStarting { name, starting } => match starting {
+                true => {
+                    self.starting.insert(name);
+                }
+                false => {
+                    self.starting.remove(&name);
+                    self.retry_pending();
+                }
+            },
         }
         Ok(())
     }
 
-    pub async fn handle_token_message(&mut self, msg: TokenMessage) -> Result<()> {
+    pub async fn handle_token_message(&mut self, mut msg: TokenMessage) -> Result<()> {
         // debug!("未签名: {:?}", msg);
+        let receipt = msg.receipt.take();
         let (name, role) = self
             .tokens
             .get(&msg.token)
@@ -160,28 +335,309 @@ impl Bus {
             .clone();
         let msg = msg.sign(name, role);
         let source = msg.name.clone();
+        // 拦截器丢弃消息时直接丢掉回执, 发送方会收到 Rejected
+        let msg = self
+            .midwares
+            .process(msg)
+            .map_err(|reason| anyhow::anyhow!("{} 的消息{}", source, reason))?;
         // if msg.name==heleny_proto::HUB_SERVICE{
         //     tracing::debug!("已签名: 来源 {} 目标{} 内容{:?}", msg.name, msg.target, msg.payload);
         // }
         let target = msg.target.clone();
-        self.send(msg)
+        self.send_with_receipt(msg, receipt)
             .await
             .context(format!("{} 发送给 {} 失败", source, target))?;
         Ok(())
     }
 
     pub async fn send(&mut self, msg: SignedMessage) -> Result<()> {
+        self.send_with_receipt(msg, None).await
+    }
+
+    /// 投递消息, 如果带有回执则把投递结果告诉发送方
+    pub async fn send_with_receipt(
+        &mut self,
+        msg: SignedMessage,
+        receipt: Option<oneshot::Sender<Receipt>>,
+    ) -> Result<()> {
         let target = msg.target.clone();
-        if let Some(tx) = &self.stats_tx {
-            tx.send((msg.name.clone(), msg.target.clone())).await?;
+        // System 身份的消息不受策略限制, 避免配置错误导致内核失控
+        if msg.role != ServiceRole::System {
+            let (rule, action) =
+                self.policy
+                    .check(&msg.name, msg.role, &target, msg.payload.as_ref());
+            if action == PolicyAction::Deny {
+                let rule = match rule {
+                    Some(index) => format!("第 {} 条规则", index),
+                    None => "默认规则".to_string(),
+                };
+                return Err(anyhow::anyhow!(
+                    "策略拒绝 {}({:?}) 发给 {} 的 {}, 命中{}",
+                    msg.name,
+                    msg.role,
+                    target,
+                    short_type_name(msg.payload.as_ref().type_name()),
+                    rule
+                ));
+            }
         }
-        let tx = self
+        self.record_route(&msg, &target);
+        let Some(tx) = self
             .router
             .get(&target)
-            .context(format!("未找到服务: {}", target))?;
-        tx.send(msg).await?;
+            .map(|address| address.lane(msg.priority).clone())
+        else {
+            let reason = format!("未找到服务: {}", target);
+            return self.retry_or_fail(msg, receipt, Receipt::NotFound, reason);
+        };
+        self.drain_overflow(&target, msg.priority);
+        if let Some((msg, receipt)) = self.deliver(tx, msg, receipt) {
+            let reason = format!("{} 的接收端已关闭", target);
+            return self.retry_or_fail(msg, receipt, Receipt::Closed, reason);
+        }
         Ok(())
     }
+
+    /// 放入目标的接收队列, 不等待队列空出, 避免一个服务积压时卡住整个 Bus
+    ///
+    /// 队列已满或已有暂存的消息时排到暂存队列末尾, 接收端已关闭时交还消息
+    fn deliver(
+        &mut self,
+        tx: mpsc::Sender<SignedMessage>,
+        msg: SignedMessage,
+        receipt: Option<oneshot::Sender<Receipt>>,
+    ) -> Option<(SignedMessage, Option<oneshot::Sender<Receipt>>)> {
+        if self
+            .overflow
+            .contains_key(&(msg.target.clone(), msg.priority))
+        {
+            self.park(msg, receipt);
+            return None;
+        }
+        match tx.try_send(msg) {
+            Ok(()) => reply_receipt(receipt, Receipt::Delivered(tx)),
+            Err(TrySendError::Full(msg)) => self.park(msg, receipt),
+            Err(TrySendError::Closed(msg)) => return Some((msg, receipt)),
+        }
+        None
+    }
+
+    /// 暂存发往已满队列的消息, 超出上限时记为死信
+    fn park(&mut self, msg: SignedMessage, receipt: Option<oneshot::Sender<Receipt>>) {
+        let queue = self
+            .overflow
+            .entry((msg.target.clone(), msg.priority))
+            .or_default();
+        if queue.len() >= OVERFLOW_CAPACITY {
+            let reason = format!("{} 的接收队列已满", msg.target);
+            warn!("{} 发送给 {} 失败: {}", msg.name, msg.target, reason);
+            self.fail(msg, receipt, Receipt::Full, reason);
+            return;
+        }
+        debug!("{} 的接收队列已满, 暂存 {} 的消息", msg.target, msg.name);
+        queue.push_back(Parked { msg, receipt });
+    }
+
+    /// 按顺序把暂存的消息放回目标的接收队列, 直到队列再次占满
+    fn drain_overflow(&mut self, target: &str, priority: Priority) {
+        let key = (target.to_string(), priority);
+        let Some(mut queue) = self.overflow.remove(&key) else {
+            return;
+        };
+        let lane = self
+            .router
+            .get(target)
+            .map(|address| address.lane(priority).clone());
+        while let Some(Parked { msg, receipt }) = queue.pop_front() {
+            let Some(tx) = &lane else {
+                let reason = format!("未找到服务: {}", target);
+                self.fail(msg, receipt, Receipt::NotFound, reason);
+                continue;
+            };
+            match tx.try_send(msg) {
+                Ok(()) => reply_receipt(receipt, Receipt::Delivered(tx.clone())),
+                Err(TrySendError::Full(msg)) => {
+                    queue.push_front(Parked { msg, receipt });
+                    break;
+                }
+                Err(TrySendError::Closed(msg)) => {
+                    let reason = format!("{} 的接收端已关闭", target);
+                    self.fail(msg, receipt, Receipt::Closed, reason);
+                }
+            }
+        }
+        if !queue.is_empty() {
+            self.overflow.insert(key, queue);
+        }
+    }
+
+    fn drain_all_overflow(&mut self) {
+        let keys: Vec<(String, Priority)> = self.overflow.keys().cloned().collect();
+        for (target, priority) in keys {
+            self.drain_overflow(&target, priority);
+        }
+    }
+
+    /// 发送路由统计样本, 统计接收方跟不上时丢弃样本, 不阻塞路由
+    fn record_route(&mut self, msg: &SignedMessage, target: &str) {
+        let Some(tx) = &self.stats_tx else {
+            return;
+        };
+        let queue_depth = self.router.get(target).map_or(0, Address::queued);
+        match tx.try_send(RouteEvent {
+            source: msg.name.clone(),
+            target: msg.target.clone(),
+            queue_depth,
+        }) {
+            Ok(()) => {
+                if self.stats_dropped > 0 {
+                    info!("统计接收方已恢复, 共丢弃 {} 个路由样本", self.stats_dropped);
+                    self.stats_dropped = 0;
+                }
+            }
+            Err(TrySendError::Full(_)) => {
+                // 只在开始丢弃时提醒一次
+                if self.stats_dropped == 0 {
+                    warn!("统计接收方跟不上, 丢弃路由样本");
+                }
+                self.stats_dropped += 1;
+            }
+            Err(TrySendError::Closed(_)) => {
+                warn!("统计接收方已关闭, 停止发送路由样本");
+                self.stats_tx = None;
+            }
+        }
+    }
+
+    /// 移除服务及以它为代理的 Endpoint
+    fn remove_endpoint(&mut self, name: &str) {
+        self.proxies.remove(name);
+        let mut removed = vec![name.to_string()];
+        self.proxies.retain(|proxy_name, proxy| {
+            if proxy == name {
+                removed.push(proxy_name.clone());
+                false
+            } else {
+                true
+            }
+        });
+        for name in &removed {
+            self.router.remove(name);
+        }
+        self.tokens
+            .retain(|_, (exist_name, _)| !removed.contains(exist_name));
+        info!("已移除 Endpoint: {:?}", removed);
+    }
+
+    /// 目标仍在启动时暂存消息等待重投, 否则记为死信
+    fn retry_or_fail(
+        &mut self,
+        msg: SignedMessage,
+        receipt: Option<oneshot::Sender<Receipt>>,
+        failure: Receipt,
+        reason: String,
+    ) -> Result<()> {
+        if self.is_starting(&msg.target) && self.pending.len() < PENDING_CAPACITY {
+            debug!("{} 仍在启动, 稍后重投 {} 的消息", msg.target, msg.name);
+            self.pending.push_back(Pending {
+                msg,
+                receipt,
+                deadline: Instant::now() + RETRY_LIMIT,
+            });
+            return Ok(());
+        }
+        self.fail(msg, receipt, failure, reason.clone());
+        Err(anyhow::anyhow!(reason))
+    }
+
+    /// 重投暂存的消息, 超时或目标不再处于启动状态的记为死信
+    fn retry_pending(&mut self) {
+        let now = Instant::now();
+        for Pending {
+            msg,
+            receipt,
+            deadline,
+        } in std::mem::take(&mut self.pending)
+        {
+            let address = self
+                .router
+                .get(&msg.target)
+                .map(|address| address.lane(msg.priority).clone());
+            let (msg, receipt, failure) = match address {
+                Some(tx) => match self.deliver(tx, msg, receipt) {
+                    None => continue,
+                    Some((msg, receipt)) => (msg, receipt, Receipt::Closed),
+                },
+                None => (msg, receipt, Receipt::NotFound),
+            };
+            if now < deadline && self.is_starting(&msg.target) {
+                self.pending.push_back(Pending {
+                    msg,
+                    receipt,
+                    deadline,
+                });
+                continue;
+            }
+            let reason = format!("等待 {} 启动后重投失败", msg.target);
+            warn!("{} 发送给 {} 失败: {}", msg.name, msg.target, reason);
+            self.fail(msg, receipt, failure, reason);
+        }
+    }
+
+    fn is_starting(&self, name: &str) -> bool {
+        self.starting.contains(name)
+    }
+
+    /// 记录死信, 并把失败告诉发送方
+    fn fail(
+        &mut self,
+        msg: SignedMessage,
+        receipt: Option<oneshot::Sender<Receipt>>,
+        failure: Receipt,
+        reason: String,
+    ) {
+        match receipt {
+            Some(receipt) => {
+                let _ = receipt.send(failure);
+            }
+            // 没有回执的发送方只能通过消息得知失败, 发送方自身不可达时放弃
+            None => {
+                if let Some(address) = self.router.get(&msg.name) {
+                    let notice = DeliveryFailure {
+                        target: msg.target.clone(),
+                        correlation: msg.correlation,
+                        reason: reason.clone(),
+                    };
+                    let _ = address.lane(Priority::Data).try_send(SignedMessage::new(
+                        msg.name.clone(),
+                        BUS_NAME.to_string(),
+                        ServiceRole::System,
+                        Box::new(notice),
+                    ));
+                }
+            }
+        }
+        if self.dead_letters.len() >= DEAD_LETTER_CAPACITY {
+            self.dead_letters.pop_front();
+        }
+        self.dead_letters.push_back(DeadLetter {
+            time: Local::now(),
+            source: msg.name,
+            target: msg.target,
+            message: short_type_name(msg.payload.as_ref().type_name()).to_string(),
+            correlation: msg.correlation,
+            reason,
+        });
+        let _ = self.dead_letters_tx.send(ResourcePayload::DeadLetters {
+            letters: self.dead_letters.clone(),
+        });
+    }
+}
+
+fn reply_receipt(receipt: Option<oneshot::Sender<Receipt>>, result: Receipt) {
+    if let Some(receipt) = receipt {
+        let _ = receipt.send(result);
+    }
 }
 
 impl BusHandle {
@@ -189,15 +645,36 @@ impl BusHandle {
         let (endpoint_to_bus, from_endpoints) = mpsc::channel(buffer);
         let (handle_to_bus, from_handle) = mpsc::channel(buffer);
         let bus = Bus::new(from_endpoints, from_handle, HashMap::new(), HashMap::new());
+        let dead_letters = bus.subscribe_dead_letters();
 
         let handle = Bus::start(bus);
         Self {
             endpoint_to_bus,
             handle_to_bus,
             handle,
+            dead_letters,
+            latency: LatencyTable::default(),
         }
     }
 
+    /// 各服务的处理耗时表
+    pub fn latency(&self) -> LatencyTable {
+        self.latency.clone()
+    }
+
+    /// 死信列表, 用于发布为 Hub 资源
+    pub fn dead_letters(&self) -> watch::Receiver<ResourcePayload> {
+        self.dead_letters.clone()
+    }
+
+    /// 告诉 Bus 服务开始启动或启动失败, 目标仍在启动时暂存消息
+    pub async fn set_starting(&mut self, name: String, starting: bool) -> Result<()> {
+        self.handle_to_bus
+            .send(BusMessage::SetStarting { name, starting })
+            .await
+            .context("发送启动状态失败")
+    }
+
     pub async fn get_endpoint(
         &mut self,
         name: String,
@@ -205,7 +682,7 @@ impl BusHandle {
         role: ServiceRole,
     ) -> Result<Endpoint> {
         let token = Uuid::new_v4();
-        let (mpsc_tx, mpsc_rx) = mpsc::channel(buffer);
+        let (address, receiver) = lane::channel(buffer);
         let (tx, rx) = oneshot::channel();
         let _ = self
             .handle_to_bus
@@ -213,7 +690,7 @@ impl BusHandle {
                 token,
                 name,
                 role,
-                address: mpsc_tx.clone(),
+                address,
                 feedback: tx,
             })
             .await;
@@ -224,8 +701,9 @@ impl BusHandle {
         Ok(Endpoint::new(
             token,
             self.endpoint_to_bus.clone(),
-            mpsc_rx,
+            receiver,
             buffer,
+            self.latency.clone(),
         ))
     }
 
@@ -258,17 +736,100 @@ impl BusHandle {
         self.handle.abort();
     }
 
-    pub async fn register_stats(&mut self, sender: mpsc::Sender<(String, String)>) -> Result<()> {
+    /// 注册统计发送端, 返回后经过 Bus 的消息都会被统计
+    pub async fn register_stats(&mut self, sender: mpsc::Sender<RouteEvent>) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
         self.handle_to_bus
-            .send(BusMessage::RegisterStats { sender })
+            .send(BusMessage::RegisterStats {
+                sender,
+                feedback: tx,
+            })
             .await
-            .context("发送统计发送端失败")
+            .context("发送统计发送端失败")?;
+        timeout(Duration::from_secs(5), rx)
+            .await
+            .context("注册统计发送端超时")?
+            .context("注册统计发送端错误")
     }
 
-    pub async fn set_user(&mut self, name: String) -> Result<()> {
+    /// 返回签发的 User token
+    pub async fn set_user(&mut self, name: String) -> Result<Uuid> {
+        let (tx, rx) = oneshot::channel();
         self.handle_to_bus
-            .send(BusMessage::SetUser { name })
+            .send(BusMessage::SetUser { name, feedback: tx })
+            .await
+            .context("发送 Set User 失败")?;
+        timeout(Duration::from_secs(5), rx)
             .await
-            .context("发送 Set User 失败")
+            .context("签发用户 token 超时")?
+            .context("签发用户 token 错误")
+    }
+
+    pub async fn revoke_token(&mut self, token: Uuid) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.handle_to_bus
+            .send(BusMessage::RevokeToken {
+                token,
+                feedback: tx,
+            })
+            .await
+            .context("发送吊销 token 失败")?;
+        timeout(Duration::from_secs(5), rx)
+            .await
+            .context("吊销 token 超时")?
+            .context("吊销 token 错误")
+    }
+
+    /// 移除服务的路由并吊销它的 token
+    pub async fn remove_endpoint(&mut self, name: String) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.handle_to_bus
+            .send(BusMessage::RemoveEndpoint { name, feedback: tx })
+            .await
+            .context("发送移除 Endpoint 失败")?;
+        timeout(Duration::from_secs(5), rx)
+            .await
+            .context("移除 Endpoint 超时")?
+            .context("移除 Endpoint 错误")
+    }
+
+    /// 替换 Bus 的访问控制策略
+    pub async fn set_policy(&mut self, policy: BusPolicy) -> Result<()> {
+        self.handle_to_bus
+            .send(BusMessage::SetPolicy { policy })
+            .await
+            .context("发送访问控制策略失败")
+    }
+
+    /// 在拦截器链末尾追加一个拦截器
+    pub async fn add_midware(&mut self, midware: Box<dyn Midware>) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.handle_to_bus
+            .send(BusMessage::AddMidware {
+                midware,
+                feedback: tx,
+            })
+            .await
+            .context("发送拦截器失败")?;
+        timeout(Duration::from_secs(5), rx)
+            .await
+            .context("注册拦截器超时")?
+            .context("注册拦截器错误")
+    }
+
+    /// 按名字移除拦截器, 返回是否找到
+    pub async fn remove_midware(&mut self, name: String) -> Result<bool> {
+        let (tx, rx) = oneshot::channel();
+        self.handle_to_bus
+            .send(BusMessage::RemoveMidware { name, feedback: tx })
+            .await
+            .context("发送移除拦截器失败")?;
+        timeout(Duration::from_secs(5), rx)
+            .await
+            .context("移除拦截器超时")?
+            .context("移除拦截器错误")
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -1,20 +1,62 @@
-use heleny_proto::TokenMessage;
-use tokio::sync::mpsc;
+use heleny_proto::SignedMessage;
+use tracing::debug;
 
+/// Bus 拦截器, 在消息签名之后、投递之前看到每一条消息
+pub trait Midware: Send + Sync + 'static {
+    fn name(&self) -> &str;
+    fn handle(&mut self, msg: SignedMessage) -> MidwareAction;
+}
+
+/// 拦截器对一条消息的处理结果
 #[derive(Debug)]
-pub struct Midware {
-    tx: mpsc::Sender<TokenMessage>,
-    rx: mpsc::Receiver<TokenMessage>,
+pub enum MidwareAction {
+    /// 放行, 消息可以已经被修改过
+    Pass(SignedMessage),
+    /// 丢弃, 附带原因
+    Drop(String),
+    /// 改投给另一个服务
+    Divert { target: String, msg: SignedMessage },
+}
+
+/// 按注册顺序排列的拦截器链
+#[derive(Default)]
+pub struct MidwareChain {
+    midwares: Vec<Box<dyn Midware>>,
 }
 
-impl Midware {
-    pub fn new(tx: mpsc::Sender<TokenMessage>, rx: mpsc::Receiver<TokenMessage>) -> Self {
-        Self { tx, rx }
+impl MidwareChain {
+    pub fn push(&mut self, midware: Box<dyn Midware>) {
+        self.midwares.push(midware);
     }
-    pub async fn recv(&mut self) -> Option<TokenMessage> {
-        self.rx.recv().await
+
+    /// 移除指定名字的拦截器, 返回是否找到
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.midwares.len();
+        self.midwares.retain(|midware| midware.name() != name);
+        len != self.midwares.len()
     }
-    pub async fn send(&mut self, msg: TokenMessage) {
-        let _ = self.tx.send(msg).await;
+
+    /// 让消息依次通过所有拦截器, 被丢弃时返回 Err(原因)
+    pub fn process(&mut self, mut msg: SignedMessage) -> Result<SignedMessage, String> {
+        for midware in &mut self.midwares {
+            msg = match midware.handle(msg) {
+                MidwareAction::Pass(msg) => msg,
+                MidwareAction::Drop(reason) => {
+                    return Err(format!("被 {} 丢弃: {}", midware.name(), reason));
+                }
+                MidwareAction::Divert { target, mut msg } => {
+                    debug!(
+                        "{} 把 {} 发给 {} 的消息改投给 {}",
+                        midware.name(),
+                        msg.name,
+                        msg.target,
+                        target
+                    );
+                    msg.target = target;
+                    msg
+                }
+            };
+        }
+        Ok(msg)
     }
 }
@@ -0,0 +1,105 @@
+use anyhow::Context;
+use anyhow::Result;
+use heleny_proto::SignedMessage;
+use heleny_proto::TrafficRecord;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::LineWriter;
+use std::io::Write;
+use std::path::Path;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tracing::warn;
+
+use crate::midware::Midware;
+use crate::midware::MidwareAction;
+
+/// payload 默认最多记录的字符数
+pub const PAYLOAD_LIMIT: usize = 512;
+/// 等待写入的记录上限, 写入跟不上时丢弃新记录
+const RECORD_BUFFER: usize = 1024;
+
+/// 把经过 Bus 的每条消息按行写成 JSON 的拦截器
+///
+/// 写文件在单独的线程里进行, 不占用 Bus 的任务
+pub struct Recorder {
+    records: mpsc::Sender<TrafficRecord>,
+    payload_limit: usize,
+    dropped: usize,
+}
+
+impl Recorder {
+    /// 追加写入到 `path`
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path.as_ref())
+            .context(format!("打开流量记录文件 {:?} 失败", path.as_ref()))?;
+        let (records, rx) = mpsc::channel(RECORD_BUFFER);
+        tokio::task::spawn_blocking(move || write_records(LineWriter::new(file), rx));
+        Ok(Self {
+            records,
+            payload_limit: PAYLOAD_LIMIT,
+            dropped: 0,
+        })
+    }
+
+    /// payload 只记录前 `limit` 个字符, 避免把大段内容或密钥写进文件
+    pub fn payload_limit(mut self, limit: usize) -> Self {
+        self.payload_limit = limit;
+        self
+    }
+}
+
+impl Midware for Recorder {
+    fn name(&self) -> &str {
+        "Recorder"
+    }
+    fn handle(&mut self, msg: SignedMessage) -> MidwareAction {
+        let record = TrafficRecord::truncated(&msg, self.payload_limit);
+        match self.records.try_send(record) {
+            Ok(()) => self.dropped = 0,
+            Err(TrySendError::Full(_)) => {
+                // 只在开始丢弃时提醒一次
+                if self.dropped == 0 {
+                    warn!("流量记录写入跟不上, 丢弃新的记录");
+                }
+                self.dropped += 1;
+            }
+            Err(TrySendError::Closed(_)) => warn!("流量记录写入线程已退出"),
+        }
+        MidwareAction::Pass(msg)
+    }
+}
+
+/// 逐条写入记录, Recorder 被移除后退出
+fn write_records(mut writer: LineWriter<File>, mut rx: mpsc::Receiver<TrafficRecord>) {
+    while let Some(record) = rx.blocking_recv() {
+        if let Err(e) = write(&mut writer, &record) {
+            warn!("记录 Bus 流量失败: {}", e);
+        }
+    }
+}
+
+fn write(writer: &mut LineWriter<File>, record: &TrafficRecord) -> Result<()> {
+    serde_json::to_writer(&mut *writer, record)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// 读取 Recorder 写下的记录
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<TrafficRecord>> {
+    let file =
+        File::open(path.as_ref()).context(format!("打开流量记录文件 {:?} 失败", path.as_ref()))?;
+    BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|(index, line)| {
+            serde_json::from_str(&line?).context(format!("解析第 {} 行记录失败", index + 1))
+        })
+        .collect()
+}
@@ -0,0 +1,743 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use heleny_proto::BUS_NAME;
+use heleny_proto::BusPolicy;
+use heleny_proto::DeliveryFailure;
+use heleny_proto::Priority;
+use heleny_proto::ResourcePayload;
+use heleny_proto::ServiceRole;
+use heleny_proto::SignedMessage;
+use heleny_proto::downcast;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+
+use crate::BusHandle;
+use crate::endpoint::CallErrorKind;
+use crate::midware::Midware;
+use crate::midware::MidwareAction;
+use crate::recorder;
+use crate::recorder::Recorder;
+use crate::trace;
+
+#[derive(Debug)]
+enum Ping {
+    Echo {
+        text: String,
+        feedback: oneshot::Sender<String>,
+    },
+    Ignore {
+        _feedback: oneshot::Sender<String>,
+    },
+    Hang {
+        feedback: oneshot::Sender<String>,
+    },
+}
+
+#[tokio::test]
+async fn test_call() {
+    let mut bus = BusHandle::new(32);
+    let client = bus
+        .get_endpoint("Client".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Client 失败");
+    let mut server = bus
+        .get_endpoint("Server".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Server 失败");
+    let server_handle = tokio::spawn(async move {
+        let mut hanging = Vec::new();
+        while let Ok(msg) = server.recv().await {
+            assert!(msg.correlation.is_some(), "call 应当带有关联 id");
+            match downcast::<Ping>(msg.payload).expect("消息类型错误") {
+                Ping::Echo { text, feedback } => {
+                    let _ = feedback.send(text);
+                }
+                Ping::Ignore { .. } => {}
+                Ping::Hang { feedback } => hanging.push(feedback),
+            }
+        }
+    });
+    let limit = Duration::from_millis(200);
+
+    let reply = client
+        .call(
+            "Server",
+            |feedback| Ping::Echo {
+                text: "hello".into(),
+                feedback,
+            },
+            limit,
+        )
+        .await
+        .expect("调用失败");
+    assert_eq!(reply, "hello");
+
+    let err = client
+        .call("Nobody", |feedback| Ping::Hang { feedback }, limit)
+        .await
+        .expect_err("目标不存在应当失败");
+    assert_eq!(err.kind, CallErrorKind::NotFound);
+
+    let err = client
+        .call("Server", |_feedback| Ping::Ignore { _feedback }, limit)
+        .await
+        .expect_err("丢弃回复应当失败");
+    assert_eq!(err.kind, CallErrorKind::ReplyDropped);
+
+    let err = client
+        .call("Server", |feedback| Ping::Hang { feedback }, limit)
+        .await
+        .expect_err("不回复应当超时");
+    assert_eq!(err.kind, CallErrorKind::Timeout(limit));
+
+    server_handle.abort();
+    let _ = server_handle.await;
+    let err = client
+        .call("Server", |feedback| Ping::Hang { feedback }, limit)
+        .await
+        .expect_err("目标崩溃应当失败");
+    assert_eq!(err.kind, CallErrorKind::Crashed);
+}
+
+/// 统计经过的消息数, 并把 Old 的消息改投给 Server, 丢弃发给 Blackhole 的消息
+struct TestMidware {
+    seen: Arc<AtomicUsize>,
+}
+
+impl Midware for TestMidware {
+    fn name(&self) -> &str {
+        "TestMidware"
+    }
+    fn handle(&mut self, msg: SignedMessage) -> MidwareAction {
+        self.seen.fetch_add(1, Ordering::SeqCst);
+        match msg.target.as_str() {
+            "Blackhole" => MidwareAction::Drop("测试丢弃".into()),
+            "Old" => MidwareAction::Divert {
+                target: "Server".into(),
+                msg,
+            },
+            _ => MidwareAction::Pass(msg),
+        }
+    }
+}
+
+/// 把 Client 的身份改成 User
+struct Promote;
+
+impl Midware for Promote {
+    fn name(&self) -> &str {
+        "Promote"
+    }
+    fn handle(&mut self, mut msg: SignedMessage) -> MidwareAction {
+        if msg.name == "Client" {
+            msg.role = ServiceRole::User;
+        }
+        MidwareAction::Pass(msg)
+    }
+}
+
+#[tokio::test]
+async fn test_midware() {
+    let mut bus = BusHandle::new(32);
+    let client = bus
+        .get_endpoint("Client".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Client 失败");
+    let mut server = bus
+        .get_endpoint("Server".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Server 失败");
+    let _blackhole = bus
+        .get_endpoint("Blackhole".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Blackhole 失败");
+    let seen = Arc::new(AtomicUsize::new(0));
+    bus.add_midware(Box::new(TestMidware { seen: seen.clone() }))
+        .await
+        .expect("注册拦截器失败");
+    bus.add_midware(Box::new(Promote))
+        .await
+        .expect("注册拦截器失败");
+    tokio::spawn(async move {
+        while let Ok(msg) = server.recv().await {
+            assert_eq!(msg.target, "Server");
+            assert_eq!(msg.role, ServiceRole::User);
+            if let Ping::Echo { text, feedback } =
+                downcast::<Ping>(msg.payload).expect("消息类型错误")
+            {
+                let _ = feedback.send(text);
+            }
+        }
+    });
+    let limit = Duration::from_millis(200);
+    let echo = |text: &str| {
+        let text = text.to_string();
+        move |feedback| Ping::Echo { text, feedback }
+    };
+
+    let reply = client
+        .call("Old", echo("diverted"), limit)
+        .await
+        .expect("改投后调用失败");
+    assert_eq!(reply, "diverted");
+
+    let err = client
+        .call("Blackhole", echo("dropped"), limit)
+        .await
+        .expect_err("被丢弃的消息应当失败");
+    assert_eq!(err.kind, CallErrorKind::Rejected);
+    assert_eq!(seen.load(Ordering::SeqCst), 2);
+
+    assert!(
+        bus.remove_midware("TestMidware".into())
+            .await
+            .expect("移除拦截器失败")
+    );
+    assert!(
+        !bus.remove_midware("TestMidware".into())
+            .await
+            .expect("移除拦截器失败")
+    );
+    let err = client
+        .call("Old", echo("not diverted"), limit)
+        .await
+        .expect_err("移除后不应再改投");
+    assert_eq!(err.kind, CallErrorKind::NotFound);
+    assert_eq!(seen.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_policy() {
+    let mut bus = BusHandle::new(32);
+    let client = bus
+        .get_endpoint("Client".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Client 失败");
+    let admin = bus
+        .get_endpoint("Admin".into(), 32, ServiceRole::System)
+        .await
+        .expect("获取 Admin 失败");
+    let mut server = bus
+        .get_endpoint("Server".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Server 失败");
+    let policy: BusPolicy = serde_json::from_value(serde_json::json!({
+        "default": "Deny",
+        "rules": [
+            { "source": "Client", "message": "Ping::Hang", "action": "Deny" },
+            { "target": "Server", "message": "Ping", "action": "Allow" }
+        ]
+    }))
+    .expect("解析策略失败");
+    bus.set_policy(policy).await.expect("设置策略失败");
+    tokio::spawn(async move {
+        while let Ok(msg) = server.recv().await {
+            match downcast::<Ping>(msg.payload).expect("消息类型错误") {
+                Ping::Echo { text, feedback } => {
+                    let _ = feedback.send(text);
+                }
+                Ping::Hang { feedback } => {
+                    let _ = feedback.send("hang".into());
+                }
+                Ping::Ignore { .. } => {}
+            }
+        }
+    });
+    let limit = Duration::from_millis(200);
+
+    let reply = client
+        .call(
+            "Server",
+            |feedback| Ping::Echo {
+                text: "allowed".into(),
+                feedback,
+            },
+            limit,
+        )
+        .await
+        .expect("允许的消息调用失败");
+    assert_eq!(reply, "allowed");
+
+    let err = client
+        .call("Server", |feedback| Ping::Hang { feedback }, limit)
+        .await
+        .expect_err("按变体拒绝的消息应当失败");
+    assert_eq!(err.kind, CallErrorKind::Rejected);
+
+    let err = client
+        .call("Server", |feedback| Some(Ping::Hang { feedback }), limit)
+        .await
+        .expect_err("默认规则应当拒绝");
+    assert_eq!(err.kind, CallErrorKind::Rejected);
+
+    let reply = admin
+        .call("Server", |feedback| Ping::Hang { feedback }, limit)
+        .await
+        .expect("System 身份不受策略限制");
+    assert_eq!(reply, "hang");
+}
+
+#[tokio::test]
+async fn test_dead_letter() {
+    let mut bus = BusHandle::new(32);
+    let mut client = bus
+        .get_endpoint("Client".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Client 失败");
+    let dead_letters = bus.dead_letters();
+    client
+        .send(
+            "Nobody",
+            Ping::Ignore {
+                _feedback: oneshot::channel().0,
+            },
+        )
+        .await
+        .expect("发送失败");
+
+    let notice = client.recv().await.expect("应当收到投递失败通知");
+    assert_eq!(notice.name, BUS_NAME);
+    let failure = downcast::<DeliveryFailure>(notice.payload).expect("通知类型错误");
+    assert_eq!(failure.target, "Nobody");
+    match &*dead_letters.borrow() {
+        ResourcePayload::DeadLetters { letters } => {
+            assert_eq!(letters.len(), 1);
+            assert_eq!(letters[0].source, "Client");
+            assert_eq!(letters[0].target, "Nobody");
+            assert_eq!(letters[0].message, "Ping");
+        }
+        other => panic!("死信资源类型错误: {:?}", other),
+    };
+}
+
+#[tokio::test]
+async fn test_retry_while_starting() {
+    let mut bus = BusHandle::new(32);
+    let client = bus
+        .get_endpoint("Client".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Client 失败");
+    bus.set_starting("Late".into(), true)
+        .await
+        .expect("设置启动状态失败");
+    let limit = Duration::from_secs(2);
+
+    let err = client
+        .call("Stopped", |feedback| Ping::Hang { feedback }, limit)
+        .await
+        .expect_err("未启动的服务应当直接失败");
+    assert_eq!(err.kind, CallErrorKind::NotFound);
+
+    let call = tokio::spawn(async move {
+        client
+            .call(
+                "Late",
+                |feedback| Ping::Echo {
+                    text: "late".into(),
+                    feedback,
+                },
+                limit,
+            )
+            .await
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mut late = bus
+        .get_endpoint("Late".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Late 失败");
+    let msg = late.recv().await.expect("暂存的消息应当被重投");
+    if let Ping::Echo { text, feedback } = downcast::<Ping>(msg.payload).expect("消息类型错误")
+    {
+        let _ = feedback.send(text);
+    }
+    let reply = call.await.expect("调用任务失败").expect("重投后调用失败");
+    assert_eq!(reply, "late");
+    match &*bus.dead_letters().borrow() {
+        ResourcePayload::DeadLetters { letters } => assert_eq!(letters.len(), 1),
+        other => panic!("死信资源类型错误: {:?}", other),
+    };
+}
+
+#[tokio::test]
+async fn test_remove_endpoint() {
+    let mut bus = BusHandle::new(32);
+    let client = bus
+        .get_endpoint("Client".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Client 失败");
+    let old_server = bus
+        .get_endpoint("Server".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Server 失败");
+    let proxy = bus
+        .get_proxy_endpoint("Session".into(), "Server".into(), ServiceRole::Standard)
+        .await
+        .expect("获取 Session 失败");
+    let limit = Duration::from_millis(200);
+
+    // 重启后旧 token 失效
+    let mut server = bus
+        .get_endpoint("Server".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("重新获取 Server 失败");
+    tokio::spawn(async move {
+        while let Ok(msg) = server.recv().await {
+            if let Ping::Echo { text, feedback } =
+                downcast::<Ping>(msg.payload).expect("消息类型错误")
+            {
+                let _ = feedback.send(text);
+            }
+        }
+    });
+    let echo = |text: &str| {
+        let text = text.to_string();
+        move |feedback| Ping::Echo { text, feedback }
+    };
+    let err = old_server
+        .call("Client", echo("stale"), limit)
+        .await
+        .expect_err("旧 token 应当被拒绝");
+    assert_eq!(err.kind, CallErrorKind::Rejected);
+    let reply = client
+        .call("Session", echo("proxy"), limit)
+        .await
+        .expect("代理调用失败");
+    assert_eq!(reply, "proxy");
+
+    bus.remove_endpoint("Server".into())
+        .await
+        .expect("移除 Endpoint 失败");
+    for target in ["Server", "Session"] {
+        let err = client
+            .call(target, echo("removed"), limit)
+            .await
+            .expect_err("已移除的服务应当不可达");
+        assert_eq!(err.kind, CallErrorKind::NotFound);
+    }
+    let err = proxy
+        .call("Client", echo("revoked"), limit)
+        .await
+        .expect_err("代理的 token 应当一并吊销");
+    assert_eq!(err.kind, CallErrorKind::Rejected);
+}
+
+#[tokio::test]
+async fn test_user_token() {
+    let mut bus = BusHandle::new(32);
+    let client = bus
+        .get_endpoint("Client".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Client 失败");
+    let mut server = bus
+        .get_endpoint("Server".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Server 失败");
+    tokio::spawn(async move {
+        while let Ok(msg) = server.recv().await {
+            let role = format!("{}:{:?}", msg.name, msg.role);
+            if let Ping::Echo { feedback, .. } =
+                downcast::<Ping>(msg.payload).expect("消息类型错误")
+            {
+                let _ = feedback.send(role);
+            }
+        }
+    });
+    let echo = |feedback| Ping::Echo {
+        text: "".into(),
+        feedback,
+    };
+    let limit = Duration::from_millis(200);
+
+    assert!(bus.set_user("Nobody".into()).await.is_err());
+    let token = bus.set_user("Client".into()).await.expect("签发用户 token 失败");
+    let user = client.create_token_endpoint(token);
+    let reply = user.call("Server", echo, limit).await.expect("用户调用失败");
+    assert_eq!(reply, "Client:User");
+    let reply = client.call("Server", echo, limit).await.expect("调用失败");
+    assert_eq!(reply, "Client:Standard");
+
+    // 登出后用户 token 失效, 服务自己的 token 不受影响
+    bus.revoke_token(token).await.expect("吊销 token 失败");
+    let err = user
+        .call("Server", echo, limit)
+        .await
+        .expect_err("吊销的 token 应当被拒绝");
+    assert_eq!(err.kind, CallErrorKind::Rejected);
+    let reply = client.call("Server", echo, limit).await.expect("调用失败");
+    assert_eq!(reply, "Client:Standard");
+}
+
+#[tokio::test]
+async fn test_priority_lanes() {
+    let mut bus = BusHandle::new(32);
+    let client = bus
+        .get_endpoint("Client".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Client 失败");
+    let mut server = bus
+        .get_endpoint("Server".into(), 4, ServiceRole::Standard)
+        .await
+        .expect("获取 Server 失败");
+    for _ in 0..4 {
+        client
+            .send(
+                "Server",
+                Ping::Ignore {
+                    _feedback: oneshot::channel().0,
+                },
+            )
+            .await
+            .expect("发送数据消息失败");
+    }
+    let (feedback, _rx) = oneshot::channel();
+    client
+        .send_control("Server", Ping::Hang { feedback })
+        .await
+        .expect("发送控制消息失败");
+    // 数据队列已满时控制消息仍然能送达, 并且先被取出
+    let msg = tokio::time::timeout(Duration::from_millis(200), server.recv())
+        .await
+        .expect("控制消息被数据消息阻塞")
+        .expect("接收失败");
+    assert_eq!(msg.priority, Priority::Control);
+    assert!(matches!(
+        downcast::<Ping>(msg.payload).expect("消息类型错误"),
+        Ping::Hang { .. }
+    ));
+    for _ in 0..4 {
+        let msg = server.recv().await.expect("接收失败");
+        assert_eq!(msg.priority, Priority::Data);
+    }
+}
+
+#[tokio::test]
+async fn test_full_lane_does_not_block_bus() {
+    let mut bus = BusHandle::new(32);
+    let client = bus
+        .get_endpoint("Client".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Client 失败");
+    let mut slow = bus
+        .get_endpoint("Slow".into(), 2, ServiceRole::Standard)
+        .await
+        .expect("获取 Slow 失败");
+    let mut other = bus
+        .get_endpoint("Other".into(), 4, ServiceRole::Standard)
+        .await
+        .expect("获取 Other 失败");
+    let echo = |text: usize| Ping::Echo {
+        text: text.to_string(),
+        feedback: oneshot::channel().0,
+    };
+    // Slow 不取消息, 数据队列很快占满
+    for i in 0..10 {
+        client.send("Slow", echo(i)).await.expect("发送数据消息失败");
+    }
+    let (feedback, _rx) = oneshot::channel();
+    client
+        .send_control("Other", Ping::Hang { feedback })
+        .await
+        .expect("发送控制消息失败");
+    let msg = tokio::time::timeout(Duration::from_millis(200), other.recv())
+        .await
+        .expect("Slow 的队列已满时 Bus 被阻塞")
+        .expect("接收失败");
+    assert_eq!(msg.priority, Priority::Control);
+    // 暂存的消息按发送顺序补投
+    for i in 0..10 {
+        let msg = tokio::time::timeout(Duration::from_secs(1), slow.recv())
+            .await
+            .expect("暂存的消息没有补投")
+            .expect("接收失败");
+        let Ping::Echo { text, .. } = downcast::<Ping>(msg.payload).expect("消息类型错误") else {
+            panic!("应当收到 Echo");
+        };
+        assert_eq!(text, i.to_string());
+    }
+}
+
+#[tokio::test]
+async fn test_trace_propagation() {
+    let mut bus = BusHandle::new(32);
+    let client = bus
+        .get_endpoint("Client".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Client 失败");
+    let mut server = bus
+        .get_endpoint("Server".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Server 失败");
+    let ignore = || Ping::Ignore {
+        _feedback: oneshot::channel().0,
+    };
+
+    client.send("Server", ignore()).await.expect("发送失败");
+    client.send("Server", ignore()).await.expect("发送失败");
+    let first = server
+        .recv()
+        .await
+        .expect("接收失败")
+        .trace
+        .expect("应当带有 trace");
+    let second = server
+        .recv()
+        .await
+        .expect("接收失败")
+        .trace
+        .expect("应当带有 trace");
+    assert_ne!(
+        first.trace_id, second.trace_id,
+        "没有 trace 时应当各自开启新的"
+    );
+
+    // 服务在收到的 trace 中继续发送, trace id 保持不变
+    trace::scope(first, async {
+        assert_eq!(trace::current(), Some(first));
+        client.send("Server", ignore()).await.expect("发送失败");
+    })
+    .await;
+    let next = server
+        .recv()
+        .await
+        .expect("接收失败")
+        .trace
+        .expect("应当带有 trace");
+    assert_eq!(next.trace_id, first.trace_id);
+    assert_eq!(trace::current(), None);
+}
+
+#[tokio::test]
+async fn test_recorder() {
+    let path = std::env::temp_dir().join(format!("heleny_recorder_{}.jsonl", uuid::Uuid::new_v4()));
+    let mut bus = BusHandle::new(32);
+    let client = bus
+        .get_endpoint("Client".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Client 失败");
+    let mut server = bus
+        .get_endpoint("Server".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Server 失败");
+    let recorder = Recorder::create(&path)
+        .expect("创建记录文件失败")
+        .payload_limit(16);
+    bus.add_midware(Box::new(recorder))
+        .await
+        .expect("注册拦截器失败");
+
+    client
+        .send(
+            "Server",
+            Ping::Ignore {
+                _feedback: oneshot::channel().0,
+            },
+        )
+        .await
+        .expect("发送失败");
+    server.recv().await.expect("接收失败");
+
+    // 记录由单独的线程写入
+    let mut records = Vec::new();
+    for _ in 0..50 {
+        records = recorder::load(&path).expect("读取记录失败");
+        if !records.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(records.len(), 1);
+    let record = &records[0];
+    assert_eq!(record.source, "Client");
+    assert_eq!(record.target, "Server");
+    assert_eq!(record.role, ServiceRole::Standard);
+    assert_eq!(record.message, "Ping");
+    assert_eq!(record.variant, "Ignore");
+    assert!(record.payload.starts_with("Ignore {"));
+    // payload 截断到 16 个字符
+    assert_eq!(record.payload.chars().count(), 16 + 3);
+    assert!(record.payload.ends_with("..."));
+    assert!(record.trace_id.is_some());
+}
+
+#[tokio::test]
+async fn test_traffic_stats() {
+    let mut bus = BusHandle::new(32);
+    let client = bus
+        .get_endpoint("Client".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Client 失败");
+    let mut server = bus
+        .get_endpoint("Server".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Server 失败");
+    let (tx, mut rx) = mpsc::channel(8);
+    bus.register_stats(tx).await.expect("注册统计失败");
+    let ignore = || Ping::Ignore {
+        _feedback: oneshot::channel().0,
+    };
+
+    client.send("Server", ignore()).await.expect("发送失败");
+    client.send("Server", ignore()).await.expect("发送失败");
+    let first = rx.recv().await.expect("应当收到路由事件");
+    assert_eq!(
+        (first.source.as_str(), first.target.as_str()),
+        ("Client", "Server")
+    );
+    assert_eq!(first.queue_depth, 0);
+    let second = rx.recv().await.expect("应当收到路由事件");
+    assert_eq!(second.queue_depth, 1, "第一条还在 Server 收件箱里");
+
+    server.recv().await.expect("接收失败");
+    server.record_latency("Server", Duration::from_millis(3));
+    server.record_latency("Server", Duration::from_millis(2000));
+    let latency = bus.latency();
+    let table = latency.lock().expect("获取耗时表失败");
+    let histogram = table.get("Server").expect("应当有 Server 的耗时");
+    assert_eq!(histogram.count, 2);
+    assert_eq!(histogram.buckets[1], 1);
+    assert_eq!(histogram.buckets.last(), Some(&1));
+    assert_eq!(histogram.max_micros, 2_000_000);
+}
+
+#[tokio::test]
+async fn test_full_stats_does_not_block_bus() {
+    let mut bus = BusHandle::new(32);
+    let client = bus
+        .get_endpoint("Client".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Client 失败");
+    let mut server = bus
+        .get_endpoint("Server".into(), 32, ServiceRole::Standard)
+        .await
+        .expect("获取 Server 失败");
+    tokio::spawn(async move {
+        while let Ok(msg) = server.recv().await {
+            if let Ping::Echo { text, feedback } =
+                downcast::<Ping>(msg.payload).expect("消息类型错误")
+            {
+                let _ = feedback.send(text);
+            }
+        }
+    });
+    // 统计接收方从不读取, 样本满了之后应当被丢弃
+    let (tx, _rx) = mpsc::channel(1);
+    bus.register_stats(tx).await.expect("注册统计失败");
+    for i in 0..4 {
+        let reply = client
+            .call(
+                "Server",
+                |feedback| Ping::Echo {
+                    text: i.to_string(),
+                    feedback,
+                },
+                Duration::from_millis(200),
+            )
+            .await
+            .expect("统计积压不应阻塞路由");
+        assert_eq!(reply, i.to_string());
+    }
+}
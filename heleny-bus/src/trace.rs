@@ -0,0 +1,52 @@
+use heleny_proto::TraceContext;
+use std::future::Future;
+use tracing::Instrument;
+use tracing::Span;
+use tracing::info_span;
+use uuid::Uuid;
+
+tokio::task_local! {
+    static CURRENT: TraceContext;
+}
+
+/// 当前任务所在的 trace
+pub fn current() -> Option<TraceContext> {
+    CURRENT.try_with(|trace| *trace).ok()
+}
+
+/// 发送消息时附带的 trace, 沿用当前的 trace, 没有时开启新的
+pub fn outgoing() -> TraceContext {
+    TraceContext {
+        trace_id: current().map_or_else(Uuid::new_v4, |trace| trace.trace_id),
+        parent_span: Span::current().id().map(|id| id.into_u64()),
+    }
+}
+
+/// 在 trace 中运行 future, 期间的日志和发出的消息都带上这个 trace
+pub async fn scope<F: Future>(trace: TraceContext, fut: F) -> F::Output {
+    let span = match trace.parent_span {
+        Some(parent) => info_span!("", Trace = %trace.trace_id, From = parent),
+        None => info_span!("", Trace = %trace.trace_id),
+    };
+    CURRENT.scope(trace, fut.instrument(span)).await
+}
+
+/// 在新任务中沿用当前的 trace 和 span, 不再开启新的 span
+pub fn propagate<F: Future>(fut: F) -> impl Future<Output = F::Output> {
+    let trace = current();
+    let fut = async move {
+        match trace {
+            Some(trace) => CURRENT.scope(trace, fut).await,
+            None => fut.await,
+        }
+    };
+    fut.instrument(Span::current())
+}
+
+/// 有 trace 时在其中运行, 用于延后执行的任务沿用创建时的 trace
+pub async fn within<F: Future>(trace: Option<TraceContext>, fut: F) -> F::Output {
+    match trace {
+        Some(trace) => scope(trace, fut).await,
+        None => fut.await,
+    }
+}
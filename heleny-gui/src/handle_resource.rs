@@ -11,12 +11,13 @@ mod handle_task_abstract;
 mod handle_task_logs;
 mod handle_tool_abstracts;
 mod handle_total_bus_traffic;
+mod handle_traffic_matrix;
 
 impl FrontendHandler {
     pub async fn handle_resource(&self, resource: ResourcePayload) -> Result<()> {
         match resource {
             ResourcePayload::TotalBusTraffic(data) => self.handle_total_bus_traffic(data).await,
-            ResourcePayload::DisplayMessages { new, messages } => {
+            ResourcePayload::DisplayMessages { new, messages, .. } => {
                 debug!("{:?}", messages);
                 self.handle_display_messages(new, messages).await
             }
@@ -38,6 +39,15 @@ impl FrontendHandler {
                 // debug!("ToolAbstracts: {:?}", abstracts);
                 self.handle_tool_abstracts(abstracts).await
             }
+            ResourcePayload::DeadLetters { letters } => {
+                debug!("死信: {:?}", letters);
+                Ok(())
+            }
+            ResourcePayload::TrafficMatrix(matrix) => self.handle_traffic_matrix(matrix).await,
+            ResourcePayload::LlmUsage(usage) => {
+                debug!("LLM 用量: {:?}", usage);
+                Ok(())
+            }
         }
     }
 }
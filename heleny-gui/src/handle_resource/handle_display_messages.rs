@@ -6,6 +6,7 @@ use heleny_proto::ChatRole;
 use heleny_proto::MemoryEntry;
 use heleny_proto::FrontendCommand;
 use heleny_proto::MemoryContent;
+use heleny_proto::STREAMING_MESSAGE_ID;
 use slint::Image;
 use slint::Model;
 use slint::ModelRc;
@@ -74,6 +75,10 @@ impl FrontendHandler {
                 }
 
                 let mut history: Vec<MessageItem> = ui.get_chat_model().iter().collect();
+                // 新消息到达或正在生成的回复更新时, 撤下旧的临时消息
+                if new {
+                    history.retain(|item| item.id != STREAMING_MESSAGE_ID as i32);
+                }
                 let history = if new {
                     history.extend(messages);
                     history
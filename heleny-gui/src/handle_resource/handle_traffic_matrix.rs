@@ -0,0 +1,53 @@
+use crate::FrontendHandler;
+use crate::RouteTrafficItem;
+use crate::ServiceTrafficItem;
+use anyhow::Context;
+use anyhow::Result;
+use heleny_proto::TrafficMatrix;
+use slint::ModelRc;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// 最多展示的路由条数
+const TOP_ROUTES: usize = 10;
+
+impl FrontendHandler {
+    pub async fn handle_traffic_matrix(&self, matrix: TrafficMatrix) -> Result<()> {
+        let mut inbound: HashMap<&str, usize> = HashMap::new();
+        let mut routes: Vec<RouteTrafficItem> = Vec::new();
+        for (source, targets) in &matrix.routes {
+            for (target, count) in targets {
+                *inbound.entry(target).or_default() += count;
+                routes.push(RouteTrafficItem {
+                    source: source.into(),
+                    target: target.into(),
+                    count: *count as i32,
+                });
+            }
+        }
+        routes.sort_by_key(|route| Reverse(route.count));
+        routes.truncate(TOP_ROUTES);
+
+        let mut services: Vec<ServiceTrafficItem> = matrix
+            .services
+            .iter()
+            .map(|(name, traffic)| ServiceTrafficItem {
+                name: name.into(),
+                inbound: inbound.get(name.as_str()).copied().unwrap_or(0) as i32,
+                queue_depth: traffic.queue_depth as i32,
+                handled: traffic.latency.count as i32,
+                mean_ms: format!("{:.2}", traffic.latency.mean_ms()).into(),
+                max_ms: format!("{:.2}", traffic.latency.max_micros as f64 / 1000.).into(),
+            })
+            .collect();
+        services.sort_by(|a, b| a.name.cmp(&b.name));
+
+        self.ui_weak
+            .upgrade_in_event_loop(move |ui| {
+                ui.set_services_traffic(ModelRc::new(slint::VecModel::from(services)));
+                ui.set_routes_traffic(ModelRc::new(slint::VecModel::from(routes)));
+            })
+            .context("更新流量统计失败")?;
+        Ok(())
+    }
+}
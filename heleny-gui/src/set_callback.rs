@@ -97,6 +97,21 @@ pub fn set_callback(ui: &AppWindow, write_tx: &mpsc::Sender<FrontendCommand>) {
         send(&write_tx_clone, FrontendCommand::EnableTool { name:name.to_string(), enable });
     });
 
+    let write_tx_clone = write_tx.clone();
+    ui.on_start_service(move |name| {
+        send(&write_tx_clone, FrontendCommand::StartService { name: name.to_string() });
+    });
+
+    let write_tx_clone = write_tx.clone();
+    ui.on_stop_service(move |name| {
+        send(&write_tx_clone, FrontendCommand::StopService { name: name.to_string() });
+    });
+
+    let write_tx_clone = write_tx.clone();
+    ui.on_restart_service(move |name| {
+        send(&write_tx_clone, FrontendCommand::RestartService { name: name.to_string() });
+    });
+
     let write_tx_clone = write_tx.clone();
     let ui_weak = ui.as_weak();
     ui.on_delete_message(move |id|{
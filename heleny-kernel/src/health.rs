@@ -1,6 +1,7 @@
 use heleny_proto::HealthStatus;
 use heleny_proto::KernelHealth;
 use heleny_service::ServiceFactory;
+use std::collections::HashMap;
 
 pub fn new_kernel_health() -> KernelHealth {
     let services = inventory::iter::<ServiceFactory>
@@ -16,5 +17,6 @@ pub fn new_kernel_health() -> KernelHealth {
     KernelHealth {
         kernel: HealthStatus::Healthy,
         services,
+        reasons: HashMap::new(),
     }
 }
@@ -1,8 +1,12 @@
 use crate::health::new_kernel_health;
+use crate::signal::ShutdownSignal;
 use anyhow::Result;
 use anyhow::anyhow;
 use heleny_bus::BusHandle;
 use heleny_bus::endpoint::Endpoint;
+use heleny_bus::midware::Midware;
+use heleny_bus::recorder::PAYLOAD_LIMIT;
+use heleny_bus::recorder::Recorder;
 use heleny_bus::{self};
 use heleny_proto::AnyMessage;
 use heleny_proto::KERNEL_NAME;
@@ -10,16 +14,19 @@ use heleny_proto::KERNEL_SERVICE;
 use heleny_proto::KernelHealth;
 use heleny_proto::ServiceHandle;
 use heleny_proto::ServiceRole;
+use heleny_proto::ShutdownReport;
 use heleny_proto::SignedMessage;
 use heleny_service::AdminCommand;
 use heleny_service::CommonMessage;
 use heleny_service::KernelMessage;
 use heleny_service::KernelServiceMessage;
+use heleny_service::ServiceFactoryVec;
 use heleny_service::ShutdownStage;
 use heleny_service::get_factory;
 use heleny_service::kernel_downcast;
 use heleny_service::{self};
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Duration;
@@ -35,14 +42,22 @@ pub struct Kernel {
     endpoint: Endpoint,
     services: Arc<Mutex<HashMap<String, ServiceHandle>>>,
     health: Arc<Mutex<KernelHealth>>,
+    selected: Option<HashSet<String>>,
+    shutdown_report: Option<ShutdownReport>,
 
     service_buffer: usize,
     run: bool,
+    shutting_down: bool,
     time_tick: usize,
 }
 
 static ADMIN_SERVICE: [&'static str; 2] = ["KernelService", "UserService"];
 
+/// 设置后把 Bus 流量记录到该路径
+const RECORD_ENV: &str = "HELENIUM_RECORD";
+/// 流量记录里 payload 最多保留的字符数
+const RECORD_PAYLOAD_ENV: &str = "HELENIUM_RECORD_PAYLOAD";
+
 impl Kernel {
     pub async fn new(kernel_buffer: usize, service_buffer: usize) -> Result<Self> {
         let mut bus = BusHandle::new(kernel_buffer);
@@ -54,10 +69,23 @@ impl Kernel {
             endpoint,
             services: Arc::new(Mutex::new(HashMap::new())),
             health: Arc::new(Mutex::new(new_kernel_health())),
+            selected: None,
+            shutdown_report: None,
             service_buffer,
             run: true,
+            shutting_down: false,
             time_tick: 0,
         };
+        if let Ok(path) = std::env::var(RECORD_ENV) {
+            let limit = std::env::var(RECORD_PAYLOAD_ENV)
+                .ok()
+                .and_then(|limit| limit.parse().ok())
+                .unwrap_or(PAYLOAD_LIMIT);
+            kernel
+                .add_midware(Box::new(Recorder::create(&path)?.payload_limit(limit)))
+                .await?;
+            info!("Bus 流量将记录到 {}", path);
+        }
         if let Err(e) = kernel.init_necessary_services().await {
             return Err(anyhow!("创建 Kernel 失败, 因为必要服务启动失败: {}", e));
         }
@@ -74,6 +102,40 @@ impl Kernel {
         self.bus.get_endpoint(name, buffer, role).await
     }
 
+    /// 在 Bus 上注册一个拦截器
+    pub async fn add_midware(&mut self, midware: Box<dyn Midware>) -> Result<()> {
+        self.bus.add_midware(midware).await
+    }
+
+    /// 只运行给定的服务及其依赖, 需在 run 之前调用
+    pub fn select_services<I, T>(&mut self, names: I)
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.selected = Some(names.into_iter().map(Into::into).collect());
+    }
+
+    /// 用给定的工厂替换同名服务或新增服务, 需在 run 之前调用
+    pub async fn replace_service(&mut self, factory: ServiceFactoryVec) -> Result<()> {
+        let name = factory.name;
+        let (feedback, rx) = oneshot::channel();
+        // 与初始化参数走同一通道, 保证 KernelService 先收到初始化参数
+        self.endpoint
+            .send(
+                KERNEL_SERVICE,
+                KernelServiceMessage::ReplaceService { factory, feedback },
+            )
+            .await?;
+        rx.await
+            .map_err(|e| anyhow!("等待替换 {} 的结果失败: {}", name, e))?
+    }
+
+    /// 关机后各服务的退出情况, 内核未走完关机流程时为 None
+    pub fn shutdown_report(&self) -> Option<&ShutdownReport> {
+        self.shutdown_report.as_ref()
+    }
+
     pub async fn wait_for<T: Into<String>>(&mut self, name: T) -> oneshot::Receiver<Result<()>> {
         let (tx, rx) = oneshot::channel();
         let _ = self
@@ -98,8 +160,14 @@ impl Kernel {
             self.endpoint.get_rx().expect("Kernel 应当获取到接收端");
         let mut tick_interval = interval(Duration::from_secs(1));
         tick_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut signal = ShutdownSignal::new();
         while self.run {
             tokio::select! {
+                name = signal.recv() => {
+                    if let Err(e) = self.handle_signal(name).await {
+                        warn!("{}",e)
+                    };
+                }
                 Some(msg) = from_bus.recv() => {
                     if let Err(e) = self.handle_msg(msg).await {
                         warn!("{}",e)
@@ -153,7 +221,13 @@ impl Kernel {
 
     /// 初始化所有服务
     async fn init_all_services(&mut self) -> Result<()> {
-        self.send_kernel_message(KernelServiceMessage::Init).await
+        match self.selected.clone() {
+            Some(names) => {
+                self.send_kernel_message(KernelServiceMessage::InitServices(names))
+                    .await
+            }
+            None => self.send_kernel_message(KernelServiceMessage::Init).await,
+        }
     }
 
     /// 初始化一个服务
@@ -200,12 +274,12 @@ impl Kernel {
 
     /// 发送消息给 KernelService
     async fn send_kernel_message(&self, payload: KernelServiceMessage) -> Result<()> {
-        self.endpoint.send(KERNEL_SERVICE, payload).await
+        self.endpoint.send_control(KERNEL_SERVICE, payload).await
     }
 
     /// 发送 Admin 消息给 Kernel(自己)
     async fn send_admin_command(&self, payload: AdminCommand) -> Result<()> {
-        self.endpoint.send(KERNEL_NAME, payload).await
+        self.endpoint.send_control(KERNEL_NAME, payload).await
     }
 
     // 关机
@@ -213,6 +287,7 @@ impl Kernel {
         match stage {
             ShutdownStage::Start => {
                 info!("开始关机");
+                self.shutting_down = true;
                 self.send_admin_command(AdminCommand::Shutdown(ShutdownStage::StopAllService))
                     .await
             }
@@ -240,7 +315,7 @@ impl Kernel {
                 info!("开始关闭内核");
                 let _ = self
                     .endpoint
-                    .send(KERNEL_SERVICE, CommonMessage::Stop)
+                    .send_control(KERNEL_SERVICE, CommonMessage::Stop)
                     .await;
                 self.bus.abort();
                 self.run = false;
@@ -249,6 +324,18 @@ impl Kernel {
         }
     }
 
+    /// 第一次收到信号时开始关机, 关机过程中再次收到则立即退出
+    async fn handle_signal(&mut self, name: &str) -> Result<()> {
+        if self.shutting_down {
+            warn!("关机过程中再次收到 {}, 强制退出", name);
+            self.bus.abort();
+            self.run = false;
+            return Ok(());
+        }
+        info!("收到 {}, 开始关机", name);
+        self.shutdown(ShutdownStage::Start).await
+    }
+
     /// 处理管理员 Command
     async fn handle_admin(
         &mut self,
@@ -285,6 +372,27 @@ impl Kernel {
                 let _ = feedback.send(endpoint);
                 Ok(())
             }
+            AdminCommand::SetBusPolicy(policy) => self.bus.set_policy(policy).await,
+            AdminCommand::RemoveEndpoint { name } => self.bus.remove_endpoint(name).await,
+            AdminCommand::SetStarting { name, starting } => {
+                self.bus.set_starting(name, starting).await
+            }
+            AdminCommand::StartService { name } => {
+                self.send_kernel_message(KernelServiceMessage::StartService { name })
+                    .await
+            }
+            AdminCommand::StopService { name } => {
+                self.send_kernel_message(KernelServiceMessage::StopService { name })
+                    .await
+            }
+            AdminCommand::RestartService { name } => {
+                self.send_kernel_message(KernelServiceMessage::RestartService { name })
+                    .await
+            }
+            AdminCommand::ShutdownReport(report) => {
+                self.shutdown_report = Some(report);
+                Ok(())
+            }
         }
     }
 
@@ -312,7 +420,7 @@ impl Kernel {
                 )),
             },
             KernelMessage::GetBusStatsRx { sender } => self.bus.register_stats(sender).await,
-            KernelMessage::SetUser { name } => {
+            KernelMessage::SetUser { name, feedback } => {
                 if role != ServiceRole::System {
                     return Err(anyhow::anyhow!(
                         "{} 的身份为 {:?}, 无设置用户权限",
@@ -320,14 +428,77 @@ impl Kernel {
                         role
                     ));
                 }
-                self.bus.set_user(name).await
+                let token = self.bus.set_user(name).await?;
+                let _ = feedback.send(token);
+                Ok(())
+            }
+            KernelMessage::RevokeToken { token } => {
+                if role != ServiceRole::System {
+                    return Err(anyhow::anyhow!(
+                        "{} 的身份为 {:?}, 无吊销 token 权限",
+                        source,
+                        role
+                    ));
+                }
+                self.bus.revoke_token(token).await
+            }
+            KernelMessage::GetDeadLettersRx { sender } => {
+                let _ = sender.send(self.bus.dead_letters());
+                Ok(())
+            }
+            KernelMessage::GetLatencyTable { sender } => {
+                let _ = sender.send(self.bus.latency());
+                Ok(())
+            }
+            KernelMessage::StartService { name } => {
+                self.check_service_control(&source, role)?;
+                self.send_admin_command(AdminCommand::StartService { name })
+                    .await
+            }
+            KernelMessage::StopService { name } => {
+                self.check_service_control(&source, role)?;
+                self.send_admin_command(AdminCommand::StopService { name })
+                    .await
+            }
+            KernelMessage::RestartService { name } => {
+                self.check_service_control(&source, role)?;
+                self.send_admin_command(AdminCommand::RestartService { name })
+                    .await
             }
         }
     }
 
+    /// 只有用户和系统服务可以启停服务
+    fn check_service_control(&self, source: &str, role: ServiceRole) -> Result<()> {
+        match role {
+            ServiceRole::User | ServiceRole::System => Ok(()),
+            _ => Err(anyhow::anyhow!(
+                "{} 的身份为 {:?}, 无启停服务权限",
+                source,
+                role
+            )),
+        }
+    }
+
     /// 处理 Tick
     async fn handle_tick(&mut self) -> Result<()> {
         self.time_tick = self.time_tick + 1;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_second_signal_forces_exit() {
+        let mut kernel = Kernel::new(16, 16).await.unwrap();
+        kernel.handle_signal("SIGINT").await.unwrap();
+        // 第一次信号只开始关机, 等待服务退出
+        assert!(kernel.shutting_down);
+        assert!(kernel.run);
+        kernel.handle_signal("SIGTERM").await.unwrap();
+        assert!(!kernel.run);
+    }
+}
@@ -1,6 +1,7 @@
 pub mod health;
 mod kernel;
 pub mod service;
+mod signal;
 pub use kernel::*;
 
 extern crate service_auth;
@@ -22,5 +23,5 @@ extern crate service_docker;
 extern crate service_mcp;
 extern crate service_process;
 
-extern crate service_tools;
+extern crate service_tools;
 extern crate service_embed;
\ No newline at end of file
@@ -4,6 +4,7 @@ use chrono::Local;
 use heleny_bus::endpoint::Endpoint;
 use heleny_macros::base_service;
 use heleny_proto::AnyMessage;
+use heleny_proto::CONFIG_SERVICE;
 use heleny_proto::HealthStatus;
 use heleny_proto::KERNEL_NAME;
 use heleny_proto::KERNEL_SERVICE;
@@ -19,7 +20,6 @@ use heleny_service::KernelServiceMessage;
 use heleny_service::Service;
 use heleny_service::ServiceFactory;
 use heleny_service::ServiceFactoryVec;
-use heleny_service::ShutdownStage;
 use inventory;
 use std::collections::HashMap;
 use std::collections::HashSet;
@@ -33,7 +33,11 @@ use tracing::info;
 use tracing::warn;
 
 mod cal_deps;
+mod config;
 mod handle_status;
+mod lifecycle;
+mod shutdown;
+mod supervisor;
 
 #[base_service(deps=[])]
 pub struct KernelService {
@@ -44,6 +48,15 @@ pub struct KernelService {
     health: Arc<Mutex<KernelHealth>>,
     is_waiting: HashMap<String, Vec<oneshot::Sender<Result<()>>>>,
     health_tx: Option<watch::Sender<ResourcePayload>>,
+    supervisor: supervisor::Supervisor,
+    shutdown: shutdown::ShutdownTracker,
+    handler_deadline: config::HandlerDeadlineConfig,
+    /// 按 Common.services 启用的服务, 未读取前为 None
+    enabled: Option<HashSet<String>>,
+    /// ConfigService 就绪后再按服务选择启动其余服务
+    waiting_selection: bool,
+    /// 依赖 ConfigService 的 InitServices, 等访问控制策略加载后再启动
+    waiting_init: Option<HashSet<String>>,
 }
 
 #[async_trait]
@@ -61,24 +74,12 @@ impl Service for KernelService {
                 ServiceFactoryVec {
                     name,
                     deps,
-                    launch: launch.clone(),
+                    launch: Arc::new(*launch),
                 }
             })
             .collect();
         // 计算服务依赖
-        let dag_map = service_factories
-            .iter()
-            .map(|f| {
-                (
-                    f.name.to_string(),
-                    f.deps
-                        .iter()
-                        .map(|str| str.to_string())
-                        .collect::<HashSet<String>>(),
-                )
-            })
-            .collect::<HashMap<String, HashSet<String>>>();
-        let deps_relation = cal_deps::DepsRelation::new(dag_map.clone())?;
+        let deps_relation = cal_deps::DepsRelation::new(deps_map(&service_factories))?;
         // 构建健康表
         let (health, services) = match endpoint.recv().await {
             Ok(SignedMessage {
@@ -86,6 +87,7 @@ impl Service for KernelService {
                 name: _,
                 role: _,
                 payload,
+                ..
             }) => match Self::downcast(payload) {
                 Ok(Ok(health)) => match *health {
                     KernelServiceMessage::InitParams(health, services) => (health, services),
@@ -122,6 +124,12 @@ impl Service for KernelService {
             health,
             is_waiting: HashMap::new(),
             health_tx: None,
+            supervisor: supervisor::Supervisor::default(),
+            shutdown: shutdown::ShutdownTracker::default(),
+            handler_deadline: config::HandlerDeadlineConfig::default(),
+            enabled: None,
+            waiting_selection: false,
+            waiting_init: None,
         }))
     }
     async fn handle(
@@ -133,25 +141,78 @@ impl Service for KernelService {
         match (msg, role) {
             (KernelServiceMessage::StopAll(sender), ServiceRole::System) => {
                 let _ = sender.send(());
+                self.supervisor.shutting_down = true;
+                self.shutdown.start();
                 let can_stop = self
                     .deps_relation
                     .prepare_all_services(KernelHealth::get_mut(&self.health).to_owned(), false)?;
                 if can_stop.contains(KernelService::name()) {
-                    self.send_admin_message(AdminCommand::Shutdown(ShutdownStage::StopKernel))
-                        .await;
+                    self.finish_shutdown().await;
                 } else {
                     self.stop_services(can_stop).await;
                 }
             }
             (KernelServiceMessage::Init, ServiceRole::System) => {
-                let can_init = self
-                    .deps_relation
-                    .prepare_all_services(KernelHealth::get_mut(&self.health).to_owned(), true)?;
+                let can_init = if self.service_factories.iter().any(|f| f.name == CONFIG_SERVICE) {
+                    // 先启动 ConfigService, 读取服务选择后再启动其余服务
+                    self.waiting_selection = true;
+                    self.deps_relation.prepare_services(
+                        HashSet::from([CONFIG_SERVICE.to_string()]),
+                        KernelHealth::get_mut(&self.health).to_owned(),
+                        true,
+                    )?
+                } else {
+                    self.deps_relation.prepare_all_services(
+                        KernelHealth::get_mut(&self.health).to_owned(),
+                        true,
+                    )?
+                };
                 self.init_services(can_init).await;
             }
+            (KernelServiceMessage::InitServices(names), ServiceRole::System) => {
+                info!("只初始化 {:?} 及其依赖", names);
+                let health = KernelHealth::get_mut(&self.health).to_owned();
+                let config_ready = health
+                    .services
+                    .get(CONFIG_SERVICE)
+                    .is_some_and(|(status, _)| *status == HealthStatus::Healthy);
+                let names = match !config_ready
+                    && self
+                        .deps_relation
+                        .prepare_cache(names.clone(), true)?
+                        .contains_key(CONFIG_SERVICE)
+                {
+                    true => {
+                        // 先启动 ConfigService, 访问控制策略生效后再启动其余服务
+                        self.waiting_init = Some(names);
+                        HashSet::from([CONFIG_SERVICE.to_string()])
+                    }
+                    false => names,
+                };
+                let can_init = self.deps_relation.prepare_services(names, health, true)?;
+                self.init_services(can_init).await;
+            }
+            (KernelServiceMessage::ReplaceService { factory, feedback }, ServiceRole::System) => {
+                let _ = feedback.send(self.replace_service(factory));
+            }
+            (KernelServiceMessage::StartService { name }, ServiceRole::System) => {
+                self.start_service(name).await?
+            }
+            (KernelServiceMessage::StopService { name }, ServiceRole::System) => {
+                self.stop_service(name).await?
+            }
+            (KernelServiceMessage::RestartService { name }, ServiceRole::System) => {
+                self.restart_service(name).await?
+            }
             (KernelServiceMessage::GetHealth(sender), _) => {
                 let _ = sender.send(KernelHealth::get_mut(&self.health).to_owned());
             }
+            (KernelServiceMessage::GetDependencyGraph(sender), _) => {
+                let graph = self
+                    .deps_relation
+                    .graph(&KernelHealth::get_mut(&self.health));
+                let _ = sender.send(graph);
+            }
             (KernelServiceMessage::UploadStatus(status), _) => {
                 self.handle_status(status, name).await?
             }
@@ -188,6 +249,9 @@ impl Service for KernelService {
         Ok(())
     }
     async fn handle_tick(&mut self, _tick: Instant) -> Result<()> {
+        self.check_stop_timeouts().await;
+        self.supervise().await;
+        self.publish_health();
         Ok(())
     }
     async fn handle_resource(&mut self, _resource: Resource) -> Result<()> {
@@ -238,10 +302,20 @@ impl KernelService {
                 );
                 continue;
             }
+            self.send_admin_message(AdminCommand::SetStarting {
+                name: name.clone(),
+                starting: true,
+            })
+            .await;
             let endpoint = match self.get_endpoint_from_kernel(&name).await {
                 Ok(endpoint) => endpoint,
                 Err(e) => {
                     warn!("无法获取 Endpoint, {} 启动失败: {}", name, e);
+                    self.send_admin_message(AdminCommand::SetStarting {
+                        name: name.clone(),
+                        starting: false,
+                    })
+                    .await;
                     KernelHealth::get_mut(&self.health).services.insert(
                         name.to_string(),
                         (HealthStatus::Stopped, Some(Local::now())),
@@ -273,6 +347,7 @@ impl KernelService {
     async fn stop_services(&mut self, can_stop: HashSet<String>) {
         for name in can_stop {
             info!("开始代理关闭 {}", name);
+            self.supervisor.stopping.insert(name.clone());
             let mut killed = false;
             {
                 let mut health = KernelHealth::get_mut(&self.health);
@@ -300,6 +375,7 @@ impl KernelService {
                                         (HealthStatus::Stopped, Some(Local::now())),
                                     );
                                     info!("强制终止 {} 句柄", name);
+                                    self.shutdown.aborted(&name);
                                 }
                                 None => (),
                             };
@@ -311,7 +387,7 @@ impl KernelService {
             if killed {
                 let _ = self
                     .endpoint
-                    .send(
+                    .send_control(
                         KERNEL_SERVICE,
                         KernelServiceMessage::UploadStatus(
                             heleny_service::ServiceSignal::Terminate(name),
@@ -320,13 +396,14 @@ impl KernelService {
                     .await;
                 continue;
             }
-            let _ = self.endpoint.send(&name, CommonMessage::Stop).await;
+            self.shutdown.stopping(&name);
+            let _ = self.endpoint.send_control(&name, CommonMessage::Stop).await;
         }
     }
 
     /// 向内核发送管理员消息
     async fn send_admin_message(&self, payload: AdminCommand) {
-        let _ = self.endpoint.send(KERNEL_NAME, payload).await;
+        let _ = self.endpoint.send_control(KERNEL_NAME, payload).await;
     }
 
     /// 检测前置服务是否准备好
@@ -348,3 +425,19 @@ impl KernelService {
         }
     }
 }
+
+/// 由工厂列表得到每个服务的直接依赖
+fn deps_map(factories: &[ServiceFactoryVec]) -> HashMap<String, HashSet<String>> {
+    factories
+        .iter()
+        .map(|f| {
+            (
+                f.name.to_string(),
+                f.deps
+                    .iter()
+                    .map(|str| str.to_string())
+                    .collect::<HashSet<String>>(),
+            )
+        })
+        .collect()
+}
@@ -1,5 +1,7 @@
 use anyhow::Context;
 use anyhow::Result;
+use heleny_proto::DependencyGraph;
+use heleny_proto::DependencyNode;
 use heleny_proto::HealthStatus;
 use heleny_proto::KernelHealth;
 use std::collections::HashMap;
@@ -11,16 +13,25 @@ pub struct DepsRelation {
     rev_map: HashMap<String, HashSet<String>>,
     all_deps_map: HashMap<String, HashSet<String>>,
     all_rev_map: HashMap<String, HashSet<String>>,
-    _init_seqs: HashMap<String, Vec<String>>,
-    _stop_seqs: HashMap<String, Vec<String>>,
+    order: Vec<String>,
+    init_seqs: HashMap<String, Vec<String>>,
+    stop_seqs: HashMap<String, Vec<String>>,
     init_cache: Option<HashMap<String, HashSet<String>>>,
     stop_cache: Option<HashMap<String, HashSet<String>>>,
 }
 
 impl DepsRelation {
     pub fn new(deps_map: HashMap<String, HashSet<String>>) -> Result<Self> {
-        if !deps_exist(&deps_map) {
-            return Err(anyhow::anyhow!("服务依赖里含有未知服务名"));
+        let unknown = unknown_deps(&deps_map);
+        if !unknown.is_empty() {
+            let detail: Vec<String> = unknown
+                .iter()
+                .map(|(name, dep)| format!("{} 依赖 {}", name, dep))
+                .collect();
+            return Err(anyhow::anyhow!(
+                "服务依赖里含有未注册的服务: {}",
+                detail.join(", ")
+            ));
         }
         let rev_map = cal_rev_map(&deps_map);
         let mut all_deps_map = HashMap::new();
@@ -54,8 +65,9 @@ impl DepsRelation {
             rev_map,
             all_deps_map,
             all_rev_map,
-            _init_seqs: init_seqs,
-            _stop_seqs: stop_seqs,
+            order,
+            init_seqs,
+            stop_seqs,
             init_cache: None,
             stop_cache: None,
         })
@@ -131,6 +143,112 @@ impl DepsRelation {
         Ok(can_op.into_keys().collect())
     }
 
+    /// 追加若干个想初始化/关闭的服务, 与尚未完成的缓存合并而不是覆盖, 正在启动/关闭的服务不会再次返回
+    pub fn prepare_more_services(
+        &mut self,
+        want_op: HashSet<String>,
+        health: KernelHealth,
+        init: bool,
+    ) -> Result<HashSet<String>> {
+        let in_flight_status = match init {
+            true => HealthStatus::Starting,
+            false => HealthStatus::Stopping,
+        };
+        let pending = match init {
+            true => self.init_cache.take(),
+            false => self.stop_cache.take(),
+        };
+        let in_flight: HashSet<String> = health
+            .services
+            .iter()
+            .filter(|(_, (status, _))| *status == in_flight_status)
+            .map(|(name, _)| name.clone())
+            .collect();
+        let result = self.prepare_services(want_op, health, init);
+        let cache = match init {
+            true => &mut self.init_cache,
+            false => &mut self.stop_cache,
+        };
+        let can_op = match result {
+            Ok(can_op) => &can_op - &in_flight,
+            Err(e) => {
+                *cache = pending;
+                return Err(e);
+            }
+        };
+        let cache = cache.get_or_insert_with(HashMap::new);
+        for (name, pre) in pending.into_iter().flatten() {
+            cache.entry(name).or_default().extend(pre);
+        }
+        cache.retain(|name, _| !can_op.contains(name));
+        Ok(can_op)
+    }
+
+    /// 检查启用的服务是否都认识, 且它们的依赖没有被禁用
+    pub fn check_enabled(&self, enabled: &HashSet<String>) -> Result<()> {
+        let mut unknown: Vec<&String> = enabled
+            .iter()
+            .filter(|name| !self.deps_map.contains_key(*name))
+            .collect();
+        if !unknown.is_empty() {
+            unknown.sort();
+            return Err(anyhow::anyhow!("启用了未知服务: {:?}", unknown));
+        }
+        let mut broken: Vec<String> = enabled
+            .iter()
+            .flat_map(|name| {
+                let mut missing: Vec<&String> = self.deps_map[name]
+                    .iter()
+                    .filter(|dep| !enabled.contains(*dep))
+                    .collect();
+                missing.sort();
+                missing
+                    .into_iter()
+                    .map(move |dep| format!("{} 依赖 {}", name, dep))
+            })
+            .collect();
+        if !broken.is_empty() {
+            broken.sort();
+            return Err(anyhow::anyhow!(
+                "启用的服务依赖了被禁用的服务: {}",
+                broken.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    /// 按初始化顺序导出依赖图, 附带实时健康状态
+    pub fn graph(&self, health: &KernelHealth) -> DependencyGraph {
+        let sorted = |names: &HashSet<String>| {
+            self.order
+                .iter()
+                .filter(|name| names.contains(*name))
+                .cloned()
+                .collect::<Vec<String>>()
+        };
+        let nodes = self
+            .order
+            .iter()
+            .map(|name| DependencyNode {
+                name: name.clone(),
+                status: health
+                    .services
+                    .get(name)
+                    .map(|(status, _)| status.clone())
+                    .unwrap_or(HealthStatus::Stopped),
+                deps: sorted(&self.deps_map[name]),
+                init_seq: self.init_seqs[name].clone(),
+                stop_seq: self.stop_seqs[name].clone(),
+            })
+            .collect();
+        DependencyGraph { nodes }
+    }
+
+    /// 直接或间接依赖 name 的所有服务
+    pub fn dependents(&self, name: &str) -> HashSet<String> {
+        self.all_rev_map.get(name).cloned().unwrap_or_default()
+    }
+
     /// 输入所有想初始化/关闭的服务名字, 返回涉及到的所有服务名字对应的依赖表或被依赖表, init=true代表初始化, 否则代表关闭
     pub fn prepare_cache(
         &self,
@@ -164,12 +282,43 @@ impl DepsRelation {
     }
 }
 
-/// 检查所有依赖是否存在
-fn deps_exist(deps_map: &HashMap<String, HashSet<String>>) -> bool {
-    !deps_map
-        .values()
-        .flatten()
-        .any(|dep| !deps_map.contains_key(dep))
+/// 找出所有不存在的依赖, 返回 (服务名, 依赖名)
+fn unknown_deps(deps_map: &HashMap<String, HashSet<String>>) -> Vec<(String, String)> {
+    let mut unknown: Vec<(String, String)> = deps_map
+        .iter()
+        .flat_map(|(name, deps)| {
+            deps.iter()
+                .filter(|dep| !deps_map.contains_key(*dep))
+                .map(move |dep| (name.clone(), dep.clone()))
+        })
+        .collect();
+    unknown.sort();
+    unknown
+}
+
+/// 在排序剩下的服务中找出一个环, 返回 A -> B -> A 形式的路径
+fn find_cycle(dag_map: &HashMap<String, HashSet<String>>) -> Option<Vec<String>> {
+    let mut names: Vec<&String> = dag_map.keys().collect();
+    names.sort();
+    let start = names.first()?;
+    // 剩下的服务都依赖剩下的服务, 一直沿依赖走必然回到走过的节点
+    let mut path: Vec<String> = vec![start.to_string()];
+    loop {
+        let current = path.last()?;
+        let mut deps: Vec<&String> = dag_map
+            .get(current)?
+            .iter()
+            .filter(|dep| dag_map.contains_key(*dep))
+            .collect();
+        deps.sort();
+        let next = deps.first()?.to_string();
+        if let Some(pos) = path.iter().position(|name| *name == next) {
+            let mut cycle = path.split_off(pos);
+            cycle.push(next);
+            return Some(cycle);
+        }
+        path.push(next);
+    }
 }
 
 /// 计算反向依赖
@@ -231,7 +380,10 @@ fn cal_order(mut dag_map: HashMap<String, HashSet<String>>) -> Result<Vec<String
     if dag_map.len() == 0 {
         Ok(order)
     } else {
-        Err(anyhow::anyhow!("有循环依赖或未知依赖 {:?}", dag_map.keys()))
+        match find_cycle(&dag_map) {
+            Some(cycle) => Err(anyhow::anyhow!("有循环依赖: {}", cycle.join(" -> "))),
+            None => Err(anyhow::anyhow!("有循环依赖或未知依赖 {:?}", dag_map.keys())),
+        }
     }
 }
 
@@ -361,4 +513,112 @@ mod tests {
         let err_msg = result.unwrap_err().to_string();
         assert!(err_msg.contains("S1"), "错误信息应该包含未启动的服务 S1");
     }
+
+    #[test]
+    fn test_prepare_more_services_keeps_pending_init() {
+        // Base <- Mid <- Top, Other 依赖 Base 且仍在等待初始化
+        let mut dag = HashMap::new();
+        dag.insert("Base".to_string(), set(vec![]));
+        dag.insert("Mid".to_string(), set(vec!["Base".to_string()]));
+        dag.insert("Top".to_string(), set(vec!["Mid".to_string()]));
+        dag.insert("Other".to_string(), set(vec!["Base".to_string()]));
+        let mut relation = DepsRelation::new(dag).unwrap();
+        let health = |starting: &[&str]| KernelHealth {
+            kernel: HealthStatus::Healthy,
+            services: ["Base", "Mid", "Top", "Other"]
+                .iter()
+                .map(|name| {
+                    let status = match starting.contains(name) {
+                        true => HealthStatus::Starting,
+                        false => HealthStatus::Stopped,
+                    };
+                    (name.to_string(), (status, None))
+                })
+                .collect(),
+            reasons: HashMap::new(),
+        };
+
+        let can_init = relation
+            .prepare_services(set(vec!["Other".to_string()]), health(&[]), true)
+            .unwrap();
+        assert_eq!(can_init, set(vec!["Base".to_string()]));
+        assert_eq!(relation.dependents("Base").len(), 3);
+
+        // Base 仍在启动时 Mid 失败, 重启 Mid 和 Top
+        let can_init = relation
+            .prepare_more_services(
+                set(vec!["Mid".to_string(), "Top".to_string()]),
+                health(&["Base"]),
+                true,
+            )
+            .unwrap();
+        assert!(can_init.is_empty());
+        let can_init = relation.refresh_cache("Base", true).unwrap();
+        assert_eq!(can_init, set(vec!["Mid".to_string(), "Other".to_string()]));
+        let can_init = relation.refresh_cache("Mid", true).unwrap();
+        assert_eq!(can_init, set(vec!["Top".to_string()]));
+    }
+
+    #[test]
+    fn test_diagnose_cycle_and_unknown() {
+        let mut dag = HashMap::new();
+        dag.insert("A".to_string(), set(vec!["B".to_string()]));
+        dag.insert("B".to_string(), set(vec!["C".to_string()]));
+        dag.insert("C".to_string(), set(vec!["A".to_string()]));
+        dag.insert("D".to_string(), set(vec!["A".to_string()]));
+        let err = DepsRelation::new(dag).err().unwrap();
+        assert_eq!(err.to_string(), "有循环依赖: A -> B -> C -> A");
+
+        let mut dag = HashMap::new();
+        dag.insert("A".to_string(), set(vec!["Ghost".to_string()]));
+        let err = DepsRelation::new(dag).err().unwrap();
+        assert!(err.to_string().contains("A 依赖 Ghost"));
+    }
+
+    #[test]
+    fn test_graph() {
+        let mut dag = HashMap::new();
+        dag.insert("Base".to_string(), set(vec![]));
+        dag.insert("Mid".to_string(), set(vec!["Base".to_string()]));
+        dag.insert("Top".to_string(), set(vec!["Mid".to_string()]));
+        let relation = DepsRelation::new(dag).unwrap();
+        let health = KernelHealth {
+            kernel: HealthStatus::Healthy,
+            services: HashMap::from([("Base".to_string(), (HealthStatus::Healthy, None))]),
+            reasons: HashMap::new(),
+        };
+
+        let graph = relation.graph(&health);
+        let names: Vec<&str> = graph.nodes.iter().map(|node| node.name.as_str()).collect();
+        assert_eq!(names, vec!["Base", "Mid", "Top"]);
+        assert_eq!(graph.nodes[0].status, HealthStatus::Healthy);
+        assert_eq!(graph.nodes[0].stop_seq, vec!["Top", "Mid"]);
+        assert_eq!(graph.nodes[2].init_seq, vec!["Base", "Mid"]);
+        assert!(graph.to_dot().contains("\"Mid\" -> \"Base\";"));
+        assert!(graph.to_json().unwrap().contains("\"init_seq\""));
+    }
+
+    #[test]
+    fn test_check_enabled() {
+        let mut dag = HashMap::new();
+        dag.insert("Base".to_string(), set(vec![]));
+        dag.insert("Mid".to_string(), set(vec!["Base".to_string()]));
+        dag.insert("Top".to_string(), set(vec!["Mid".to_string()]));
+        let relation = DepsRelation::new(dag).unwrap();
+
+        assert!(
+            relation
+                .check_enabled(&set(vec!["Base".to_string(), "Mid".to_string()]))
+                .is_ok()
+        );
+        let err = relation
+            .check_enabled(&set(vec!["Base".to_string(), "Top".to_string()]))
+            .unwrap_err();
+        assert!(err.to_string().contains("Top 依赖 Mid"));
+        assert!(
+            relation
+                .check_enabled(&set(vec!["Base".to_string(), "Ghost".to_string()]))
+                .is_err()
+        );
+    }
 }
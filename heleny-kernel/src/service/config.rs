@@ -0,0 +1,295 @@
+use crate::service::KernelService;
+use crate::service::shutdown::StopTimeoutConfig;
+use crate::service::supervisor::SupervisorConfig;
+use anyhow::Context;
+use anyhow::Result;
+use heleny_proto::BusPolicy;
+use heleny_proto::CONFIG_SERVICE;
+use heleny_proto::CONFIG_SERVICES;
+use heleny_proto::KERNEL_NAME;
+use heleny_proto::KERNEL_SERVICE;
+use heleny_proto::KernelHealth;
+use heleny_service::AdminCommand;
+use heleny_service::CommonMessage;
+use heleny_service::DEFAULT_HANDLER_DEADLINE;
+use heleny_service::ShutdownStage;
+use heleny_service::get_from_config_service;
+use heleny_service::import_from_config_service;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::time::Duration;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+/// 覆盖 Common.services.profile 的环境变量
+const PROFILE_ENV: &str = "HELENIUM_PROFILE";
+
+#[derive(Deserialize, Debug)]
+pub struct KernelServiceConfig {
+    #[serde(default)]
+    pub bus_policy: BusPolicy,
+    #[serde(default)]
+    pub supervisor: SupervisorConfig,
+    #[serde(default)]
+    pub stop_timeout: StopTimeoutConfig,
+    #[serde(default)]
+    pub handler_deadline: HandlerDeadlineConfig,
+}
+
+/// 单条消息的处理时限, 超过后服务被标为不健康
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HandlerDeadlineConfig {
+    pub default_ms: u64,
+    /// 按服务名覆盖, 如需要等待模型回复的服务
+    pub services: HashMap<String, u64>,
+}
+
+impl Default for HandlerDeadlineConfig {
+    fn default() -> Self {
+        Self {
+            default_ms: DEFAULT_HANDLER_DEADLINE.as_millis() as u64,
+            services: HashMap::new(),
+        }
+    }
+}
+
+impl HandlerDeadlineConfig {
+    pub fn deadline(&self, name: &str) -> Duration {
+        Duration::from_millis(*self.services.get(name).unwrap_or(&self.default_ms))
+    }
+}
+
+/// 启用哪些服务, enable 为空时启用全部
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Profile {
+    pub enable: Option<HashSet<String>>,
+    pub disable: HashSet<String>,
+}
+
+impl Profile {
+    fn resolve(&self, all: &HashSet<String>) -> Result<HashSet<String>> {
+        let unknown: Vec<&String> = self
+            .enable
+            .iter()
+            .flatten()
+            .chain(&self.disable)
+            .filter(|name| !all.contains(*name))
+            .collect();
+        if !unknown.is_empty() {
+            return Err(anyhow::anyhow!("没有这些服务: {:?}", unknown));
+        }
+        if self.disable.contains(CONFIG_SERVICE) {
+            return Err(anyhow::anyhow!("不能禁用 {}", CONFIG_SERVICE));
+        }
+        let mut enabled = self.enable.clone().unwrap_or_else(|| all.clone());
+        enabled.retain(|name| !self.disable.contains(name));
+        enabled.insert(KERNEL_SERVICE.to_string());
+        enabled.insert(CONFIG_SERVICE.to_string());
+        Ok(enabled)
+    }
+}
+
+/// Common.services, 未选择 profile 时使用顶层的 enable/disable
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ServiceSelection {
+    pub profile: Option<String>,
+    pub profiles: HashMap<String, Profile>,
+    #[serde(flatten)]
+    pub fallback: Profile,
+}
+
+impl ServiceSelection {
+    /// 返回选中的 profile 名和启用的服务
+    pub fn resolve(&self, all: &HashSet<String>) -> Result<(String, HashSet<String>)> {
+        self.resolve_with(all, std::env::var(PROFILE_ENV).ok())
+    }
+
+    /// env_profile 为环境变量选择的 profile, 优先于配置文件
+    fn resolve_with(
+        &self,
+        all: &HashSet<String>,
+        env_profile: Option<String>,
+    ) -> Result<(String, HashSet<String>)> {
+        let name = env_profile.or(self.profile.clone());
+        let Some(name) = name else {
+            return Ok(("default".to_string(), self.fallback.resolve(all)?));
+        };
+        let profile = self
+            .profiles
+            .get(&name)
+            .with_context(|| format!("没有名为 {} 的 profile", name))?;
+        let enabled = profile
+            .resolve(all)
+            .with_context(|| format!("profile {} 无效", name))?;
+        Ok((name, enabled))
+    }
+}
+
+impl KernelService {
+    /// 从 ConfigService 读取访问控制策略, 重启策略, 关闭时限和处理时限
+    pub async fn load_config(&mut self) {
+        let config: KernelServiceConfig = match get_from_config_service(&self.endpoint).await {
+            Ok(config) => config,
+            Err(e) => {
+                warn!(
+                    "读取 KernelService 配置失败, 放行所有消息并使用默认重启策略: {}",
+                    e
+                );
+                return;
+            }
+        };
+        self.supervisor.config = config.supervisor;
+        self.shutdown.config = config.stop_timeout;
+        self.handler_deadline = config.handler_deadline;
+        self.send_handler_deadline(KERNEL_SERVICE).await;
+        info!(
+            "加载访问控制策略, 共 {} 条规则",
+            config.bus_policy.rules.len()
+        );
+        // 走控制通道, 保证策略先于之后启动的服务的 NewEndpoint 生效
+        if let Err(e) = self
+            .endpoint
+            .send_control(KERNEL_NAME, AdminCommand::SetBusPolicy(config.bus_policy))
+            .await
+        {
+            warn!("发送访问控制策略失败: {}", e);
+        }
+    }
+
+    /// 向服务下发单条消息的处理时限
+    pub async fn send_handler_deadline(&self, name: &str) {
+        let deadline = self.handler_deadline.deadline(name);
+        if let Err(e) = self
+            .endpoint
+            .send_control(name, CommonMessage::HandlerDeadline(deadline))
+            .await
+        {
+            warn!("向 {} 下发处理时限失败: {}", name, e);
+        }
+    }
+
+    /// 读取 Common.services, 校验后启动启用的服务, 无效时拒绝启动并关机
+    pub async fn init_selected(&mut self) -> Result<()> {
+        self.waiting_selection = false;
+        let all: HashSet<String> = self
+            .service_factories
+            .iter()
+            .map(|factory| factory.name.to_string())
+            .collect();
+        let selection: ServiceSelection =
+            match import_from_config_service(&self.endpoint, CONFIG_SERVICES).await {
+                Ok(selection) => selection,
+                Err(e) => {
+                    info!("未读取到服务选择, 启动所有服务: {}", e);
+                    ServiceSelection::default()
+                }
+            };
+        let enabled = selection.resolve(&all).and_then(|(profile, enabled)| {
+            self.deps_relation
+                .check_enabled(&enabled)
+                .with_context(|| format!("profile {} 无效", profile))?;
+            info!("使用 profile {}, 禁用: {:?}", profile, &all - &enabled);
+            Ok(enabled)
+        });
+        let enabled = match enabled {
+            Ok(enabled) => enabled,
+            Err(e) => {
+                error!("服务选择无效, 拒绝启动: {:#}", e);
+                self.send_admin_message(AdminCommand::Shutdown(ShutdownStage::Start))
+                    .await;
+                return Ok(());
+            }
+        };
+        self.enabled = Some(enabled.clone());
+        let health = KernelHealth::get_mut(&self.health).to_owned();
+        let can_init = self
+            .deps_relation
+            .prepare_more_services(enabled, health, true)?;
+        self.init_services(can_init).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn all() -> HashSet<String> {
+        set(&[
+            KERNEL_SERVICE,
+            CONFIG_SERVICE,
+            "FsService",
+            "ChatService",
+            "MemoryService",
+        ])
+    }
+
+    fn selection() -> ServiceSelection {
+        serde_json::from_value(serde_json::json!({
+            "profile": "lite",
+            "disable": ["MemoryService"],
+            "profiles": {
+                "lite": { "enable": ["FsService"] },
+                "full": { "disable": ["ChatService"] },
+                "broken": { "enable": ["NoSuchService"] }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_fallback_without_profile() {
+        let mut selection = selection();
+        selection.profile = None;
+        let (name, enabled) = selection.resolve_with(&all(), None).unwrap();
+        assert_eq!(name, "default");
+        assert_eq!(enabled, &all() - &set(&["MemoryService"]));
+    }
+
+    #[test]
+    fn test_profile_replaces_top_level() {
+        let (name, enabled) = selection().resolve_with(&all(), None).unwrap();
+        assert_eq!(name, "lite");
+        // 内核和 ConfigService 总是启用, 顶层的 disable 不再生效
+        assert_eq!(enabled, set(&[KERNEL_SERVICE, CONFIG_SERVICE, "FsService"]));
+    }
+
+    #[test]
+    fn test_env_overrides_profile() {
+        let (name, enabled) = selection()
+            .resolve_with(&all(), Some("full".to_string()))
+            .unwrap();
+        assert_eq!(name, "full");
+        assert_eq!(enabled, &all() - &set(&["ChatService"]));
+    }
+
+    #[test]
+    fn test_unknown_profile() {
+        let err = selection()
+            .resolve_with(&all(), Some("missing".to_string()))
+            .unwrap_err();
+        assert!(err.to_string().contains("没有名为 missing 的 profile"));
+    }
+
+    #[test]
+    fn test_invalid_profile() {
+        let err = selection()
+            .resolve_with(&all(), Some("broken".to_string()))
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("NoSuchService"));
+        let profile = Profile {
+            enable: None,
+            disable: set(&[CONFIG_SERVICE]),
+        };
+        assert!(profile.resolve(&all()).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use crate::service::KernelService;
-use anyhow::Context;
+use crate::service::supervisor::Failure;
 use anyhow::Result;
+use heleny_proto::CONFIG_SERVICE;
 use heleny_proto::HEALTH;
 use heleny_proto::HUB_SERVICE;
 use heleny_proto::KERNEL_SERVICE;
@@ -8,10 +9,10 @@ use heleny_proto::KernelHealth;
 use heleny_proto::ResourcePayload;
 use heleny_service::AdminCommand;
 use heleny_service::ServiceSignal;
-use heleny_service::ShutdownStage;
 use heleny_service::publish_resource;
 use tokio::sync::watch;
 use tracing::info;
+use tracing::warn;
 
 impl KernelService {
     pub async fn handle_status(&mut self, status: ServiceSignal, name: String) -> Result<()> {
@@ -21,22 +22,11 @@ impl KernelService {
                 KernelHealth::get_mut(&self.health).set_alive(&name);
             }
             ServiceSignal::InitFail => {
-                KernelHealth::get_mut(&self.health).set_dead(&name);
-                let mut services = match self.services.as_ref().lock() {
-                    Ok(service) => service,
-                    Err(e) => {
-                        return Err(anyhow::anyhow!(
-                            "无法获取 {} 的锁, 导致无法 Abort: {}",
-                            name,
-                            e
-                        ));
-                    }
-                };
-                services
-                    .get(&name)
-                    .context(format!("未找到 {} 的句柄, 导致无法 Abort", name))?
-                    .abort();
-                services.remove(&name);
+                self.on_failure(&name, Failure::InitFail).await;
+            }
+            ServiceSignal::Unhealthy(reason) => {
+                warn!("{} 不健康: {}", name, reason);
+                KernelHealth::get_mut(&self.health).set_unhealthy(&name, reason);
             }
             ServiceSignal::Ready => {
                 self.notify(&name);
@@ -47,18 +37,38 @@ impl KernelService {
                     let (tx, rx) = watch::channel(ResourcePayload::Health(health));
                     publish_resource(&self.endpoint, HEALTH, rx).await?;
                     self.health_tx = Some(tx);
+                } else if name == CONFIG_SERVICE {
+                    self.load_config().await;
                 }
+                self.send_handler_deadline(&name).await;
                 info!("{} 成功初始化", name);
                 KernelHealth::get_mut(&self.health).set_alive(&name);
                 let can_init = self.deps_relation.refresh_cache(&name, true)?;
                 if !can_init.is_empty() {
                     self.init_services(can_init).await;
                 }
+                if self.supervisor.ready(&name) {
+                    self.notify_dependents(&name).await;
+                }
+                if name == CONFIG_SERVICE && self.waiting_selection {
+                    self.init_selected().await?;
+                }
+                if name == CONFIG_SERVICE
+                    && let Some(names) = self.waiting_init.take()
+                {
+                    let health = KernelHealth::get_mut(&self.health).to_owned();
+                    let can_init = self
+                        .deps_relation
+                        .prepare_more_services(names, health, true)?;
+                    self.init_services(can_init).await;
+                }
             }
             ServiceSignal::Terminate(service_name) => {
                 let term = if name == KERNEL_SERVICE {
                     service_name
                 } else {
+                    // 服务自己上报的退出, 而不是内核强制终止后代发的
+                    self.shutdown.stopped(&name);
                     name
                 };
                 info!("{} 成功退出", term);
@@ -74,23 +84,35 @@ impl KernelService {
                             ));
                         }
                     };
-                    services
-                        .get(&term)
-                        .context(format!("未找到 {} 的句柄, 导致无法清理", term))?
-                        .abort();
-                    services.remove(&term);
+                    // 被强制终止的服务已经移除了句柄
+                    if let Some(handle) = services.remove(&term) {
+                        handle.abort();
+                    }
                 }
-                let can_stop = self.deps_relation.refresh_cache(&term, false)?;
-                if can_stop.contains(KERNEL_SERVICE) {
-                    self.send_admin_message(AdminCommand::Shutdown(ShutdownStage::StopKernel))
-                        .await;
-                } else if !can_stop.is_empty() {
-                    self.stop_services(can_stop).await;
+                self.send_admin_message(AdminCommand::RemoveEndpoint { name: term.clone() })
+                    .await;
+                if !self.supervisor.stopping.remove(&term) {
+                    // 不是内核要求的退出, 交给重启策略
+                    self.on_failure(&term, Failure::Exited).await;
+                } else {
+                    let can_stop = self.deps_relation.refresh_cache(&term, false)?;
+                    if can_stop.contains(KERNEL_SERVICE) {
+                        self.finish_shutdown().await;
+                    } else if !can_stop.is_empty() {
+                        self.stop_services(can_stop).await;
+                    }
+                    self.resume_restarts().await?;
                 }
             }
         };
+        self.publish_health();
+        Ok(())
+    }
+
+    /// 健康表有变化时发布
+    pub fn publish_health(&self) {
         let Some(tx) = &self.health_tx else {
-            return Ok(());
+            return;
         };
         let new_health = KernelHealth::get_mut(&self.health).to_owned();
         tx.send_if_modified(|health| match health {
@@ -108,6 +130,5 @@ impl KernelService {
             }
         });
         // tx.send(ResourcePayload::Health(new_health)).context("发送 Health 信息失败")?;
-        Ok(())
     }
 }
@@ -0,0 +1,170 @@
+use crate::service::KernelService;
+use crate::service::cal_deps::DepsRelation;
+use crate::service::deps_map;
+use anyhow::Result;
+use heleny_proto::HealthStatus;
+use heleny_proto::KERNEL_SERVICE;
+use heleny_proto::KernelHealth;
+use heleny_service::CommonMessage;
+use heleny_service::ServiceFactoryVec;
+use std::collections::HashSet;
+use tracing::info;
+use tracing::warn;
+
+impl KernelService {
+    /// 启动服务, 缺少的依赖按依赖顺序先启动
+    pub async fn start_service(&mut self, name: String) -> Result<()> {
+        self.check_service_name(&name)?;
+        if let Some(enabled) = &self.enabled
+            && !enabled.contains(&name)
+        {
+            return Err(anyhow::anyhow!("{} 未在 Common.services 中启用", name));
+        }
+        self.supervisor.forget(&name);
+        let health = KernelHealth::get_mut(&self.health).to_owned();
+        let can_init = self.deps_relation.prepare_more_services(
+            HashSet::from([name.clone()]),
+            health,
+            true,
+        )?;
+        info!("启动 {}, 现在可以初始化: {:?}", name, can_init);
+        self.init_services(can_init).await;
+        Ok(())
+    }
+
+    /// 关闭服务, 依赖它的服务先关闭
+    pub async fn stop_service(&mut self, name: String) -> Result<()> {
+        self.check_service_name(&name)?;
+        self.supervisor.cancel(&name);
+        let health = KernelHealth::get_mut(&self.health).to_owned();
+        let can_stop = self.deps_relation.prepare_more_services(
+            HashSet::from([name.clone()]),
+            health,
+            false,
+        )?;
+        info!("关闭 {}, 现在可以关闭: {:?}", name, can_stop);
+        self.stop_services(can_stop).await;
+        Ok(())
+    }
+
+    /// 关闭服务及依赖它的服务, 全部退出后再按依赖顺序启动
+    pub async fn restart_service(&mut self, name: String) -> Result<()> {
+        self.check_service_name(&name)?;
+        let mut services = HashSet::from([name.clone()]);
+        {
+            let health = KernelHealth::get_mut(&self.health);
+            services.extend(
+                self.deps_relation
+                    .dependents(&name)
+                    .into_iter()
+                    .filter(|dependent| {
+                        health
+                            .services
+                            .get(dependent)
+                            .is_some_and(|(status, _)| *status != HealthStatus::Stopped)
+                    }),
+            );
+        }
+        info!("重启 {:?}", services);
+        self.supervisor.restart_after_stop(services);
+        self.stop_service(name).await?;
+        self.resume_restarts().await
+    }
+
+    /// 等待关闭的重启已全部退出时, 开始启动
+    pub async fn resume_restarts(&mut self) -> Result<()> {
+        let stopped = |services: &HashSet<String>| {
+            let health = KernelHealth::get_mut(&self.health);
+            services.iter().all(|name| {
+                health
+                    .services
+                    .get(name)
+                    .is_none_or(|(status, _)| *status == HealthStatus::Stopped)
+            })
+        };
+        let ready = self.supervisor.take_stopped(stopped);
+        for services in ready {
+            let health = KernelHealth::get_mut(&self.health).to_owned();
+            let can_init = self
+                .deps_relation
+                .prepare_more_services(services, health, true)?;
+            self.init_services(can_init).await;
+        }
+        Ok(())
+    }
+
+    /// 替换或新增服务的工厂并重新计算依赖, 只能替换未运行的服务
+    pub fn replace_service(&mut self, mut factory: ServiceFactoryVec) -> Result<()> {
+        let name = factory.name;
+        if name == KERNEL_SERVICE {
+            return Err(anyhow::anyhow!("不能替换 {}", KERNEL_SERVICE));
+        }
+        let running = KernelHealth::get_mut(&self.health)
+            .services
+            .get(name)
+            .is_some_and(|(status, _)| *status != HealthStatus::Stopped);
+        if running {
+            return Err(anyhow::anyhow!("{} 正在运行, 无法替换", name));
+        }
+        if !factory.deps.contains(&KERNEL_SERVICE) {
+            factory.deps.push(KERNEL_SERVICE);
+        }
+        let mut deps = deps_map(&self.service_factories);
+        deps.insert(
+            name.to_string(),
+            factory.deps.iter().map(|dep| dep.to_string()).collect(),
+        );
+        self.deps_relation = DepsRelation::new(deps)?;
+        self.service_factories.retain(|f| f.name != name);
+        self.service_factories.push(factory);
+        KernelHealth::get_mut(&self.health)
+            .services
+            .entry(name.to_string())
+            .or_insert((HealthStatus::Stopped, None));
+        info!("已替换 {} 的工厂", name);
+        Ok(())
+    }
+
+    /// 通知仍在运行的下游服务, name 已重启完成
+    pub async fn notify_dependents(&self, name: &str) {
+        let running: Vec<String> = {
+            let health = KernelHealth::get_mut(&self.health);
+            self.deps_relation
+                .dependents(name)
+                .into_iter()
+                .filter(|dependent| {
+                    matches!(
+                        health.services.get(dependent),
+                        Some((HealthStatus::Healthy | HealthStatus::Unhealthy, _))
+                    )
+                })
+                .collect()
+        };
+        for dependent in running {
+            info!("通知 {}: 依赖的 {} 已重启", dependent, name);
+            if let Err(e) = self
+                .endpoint
+                .send_control(
+                    &dependent,
+                    CommonMessage::DependencyRestarted(name.to_string()),
+                )
+                .await
+            {
+                warn!("通知 {} 依赖重启失败: {}", dependent, e);
+            }
+        }
+    }
+
+    fn check_service_name(&self, name: &str) -> Result<()> {
+        if name == KERNEL_SERVICE {
+            return Err(anyhow::anyhow!("不能单独启停 {}", KERNEL_SERVICE));
+        }
+        if !KernelHealth::get_mut(&self.health)
+            .services
+            .contains_key(name)
+        {
+            return Err(anyhow::anyhow!("没有这个服务: {}", name));
+        }
+        Ok(())
+    }
+}
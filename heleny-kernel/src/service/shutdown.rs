@@ -0,0 +1,184 @@
+use crate::service::KernelService;
+use heleny_proto::KERNEL_SERVICE;
+use heleny_proto::ShutdownReport;
+use heleny_service::AdminCommand;
+use heleny_service::KernelServiceMessage;
+use heleny_service::ServiceSignal;
+use heleny_service::ShutdownStage;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::info;
+use tracing::warn;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct StopTimeoutConfig {
+    /// 收到 Stop 后等待退出的时间
+    pub default_ms: u64,
+    /// 按服务名覆盖, 用于需要持久化大量数据的服务
+    pub services: HashMap<String, u64>,
+}
+
+impl Default for StopTimeoutConfig {
+    fn default() -> Self {
+        Self {
+            default_ms: 5000,
+            services: HashMap::new(),
+        }
+    }
+}
+
+impl StopTimeoutConfig {
+    fn timeout(&self, name: &str) -> Duration {
+        Duration::from_millis(*self.services.get(name).unwrap_or(&self.default_ms))
+    }
+}
+
+/// 记录正在关闭的服务的时限, 关机时汇总成报告
+#[derive(Debug, Default)]
+pub struct ShutdownTracker {
+    pub config: StopTimeoutConfig,
+    deadlines: HashMap<String, Instant>,
+    /// 关机开始的时间, 只有关机时才记录报告
+    started: Option<Instant>,
+    report: ShutdownReport,
+}
+
+impl ShutdownTracker {
+    pub fn start(&mut self) {
+        self.started = Some(Instant::now());
+    }
+
+    /// 已向服务发送 Stop
+    pub fn stopping(&mut self, name: &str) {
+        self.deadlines
+            .insert(name.to_string(), Instant::now() + self.config.timeout(name));
+    }
+
+    /// 服务自己上报退出
+    pub fn stopped(&mut self, name: &str) {
+        self.deadlines.remove(name);
+        if self.started.is_some() {
+            self.report.stopped.push(name.to_string());
+        }
+    }
+
+    /// 服务关闭时已不健康, 被直接终止
+    pub fn aborted(&mut self, name: &str) {
+        self.deadlines.remove(name);
+        if self.started.is_some() {
+            self.report.aborted.push(name.to_string());
+        }
+    }
+
+    /// 取出超过时限的服务
+    fn take_expired(&mut self) -> Vec<String> {
+        let now = Instant::now();
+        let expired: Vec<String> = self
+            .deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in &expired {
+            self.deadlines.remove(name);
+            if self.started.is_some() {
+                self.report.timed_out.push(name.clone());
+            }
+        }
+        expired
+    }
+
+    fn finish(&mut self) -> ShutdownReport {
+        let mut report = std::mem::take(&mut self.report);
+        report.elapsed_ms = self
+            .started
+            .map(|started| started.elapsed().as_millis() as u64)
+            .unwrap_or_default();
+        report
+    }
+}
+
+impl KernelService {
+    /// 强制终止超过关闭时限的服务, 之后按正常退出继续关闭下游
+    pub async fn check_stop_timeouts(&mut self) {
+        for name in self.shutdown.take_expired() {
+            warn!("{} 超过关闭时限仍未退出, 强制终止", name);
+            let _ = self
+                .endpoint
+                .send_control(
+                    KERNEL_SERVICE,
+                    KernelServiceMessage::UploadStatus(ServiceSignal::Terminate(name)),
+                )
+                .await;
+        }
+    }
+
+    /// 所有服务都已退出, 上报关机情况并关闭内核
+    pub async fn finish_shutdown(&mut self) {
+        let report = self.shutdown.finish();
+        match report.is_clean() {
+            true => info!("{}", report),
+            false => warn!("{}", report),
+        }
+        self.send_admin_message(AdminCommand::ShutdownReport(report))
+            .await;
+        self.send_admin_message(AdminCommand::Shutdown(ShutdownStage::StopKernel))
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> ShutdownTracker {
+        ShutdownTracker {
+            config: StopTimeoutConfig {
+                default_ms: 1000,
+                services: HashMap::from([("MemoryService".to_string(), 3000)]),
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_report_lists_timed_out() {
+        let mut tracker = tracker();
+        tracker.start();
+        for name in ["FsService", "ChatService", "MemoryService"] {
+            tracker.stopping(name);
+        }
+        tracker.stopped("FsService");
+        tracker.aborted("TaskService");
+        tokio::time::advance(Duration::from_millis(1000)).await;
+        assert_eq!(tracker.take_expired(), vec!["ChatService".to_string()]);
+        // MemoryService 的时限被单独放宽
+        tokio::time::advance(Duration::from_millis(2000)).await;
+        assert_eq!(tracker.take_expired(), vec!["MemoryService".to_string()]);
+        assert!(tracker.take_expired().is_empty());
+        let report = tracker.finish();
+        assert_eq!(report.stopped, vec!["FsService".to_string()]);
+        assert_eq!(
+            report.timed_out,
+            vec!["ChatService".to_string(), "MemoryService".to_string()]
+        );
+        assert_eq!(report.aborted, vec!["TaskService".to_string()]);
+        assert_eq!(report.elapsed_ms, 3000);
+        assert!(!report.is_clean());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_no_report_outside_shutdown() {
+        // 单独关闭服务时只终止超时的服务, 不记入关机报告
+        let mut tracker = tracker();
+        tracker.stopping("ChatService");
+        tokio::time::advance(Duration::from_millis(1000)).await;
+        assert_eq!(tracker.take_expired(), vec!["ChatService".to_string()]);
+        let report = tracker.finish();
+        assert!(report.timed_out.is_empty());
+        assert!(report.is_clean());
+    }
+}
@@ -0,0 +1,535 @@
+use crate::service::KernelService;
+use crate::service::cal_deps::DepsRelation;
+use chrono::Local;
+use heleny_proto::HealthStatus;
+use heleny_proto::KERNEL_SERVICE;
+use heleny_proto::KernelHealth;
+use heleny_service::AdminCommand;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+/// 服务退出后是否重启
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum RestartPolicy {
+    /// 从不重启
+    Never,
+    /// 初始化失败, 崩溃或失去心跳时重启
+    #[default]
+    OnFailure,
+    /// 除内核要求的关闭外, 任何退出都重启
+    Always,
+}
+
+impl RestartPolicy {
+    pub fn should_restart(self, failure: Failure) -> bool {
+        match (self, failure) {
+            (RestartPolicy::Never, _) => false,
+            (RestartPolicy::OnFailure, Failure::Exited) => false,
+            (RestartPolicy::OnFailure, _) | (RestartPolicy::Always, _) => true,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SupervisorPolicy {
+    pub restart: RestartPolicy,
+    /// 连续重启的上限, 超过后放弃
+    pub max_restarts: u32,
+    /// 首次重启前的等待, 之后每次翻倍
+    pub backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// 健康运行超过这个时间后重新计数
+    pub reset_after_secs: u64,
+    /// 失去心跳超过这个时间才视为失败, 避免误杀正在处理耗时消息的服务
+    pub unhealthy_grace_secs: u64,
+    /// 为 false 时重启不牵连依赖它的服务, 它们会收到 DependencyRestarted
+    pub restart_dependents: bool,
+}
+
+impl Default for SupervisorPolicy {
+    fn default() -> Self {
+        Self {
+            restart: RestartPolicy::OnFailure,
+            max_restarts: 5,
+            backoff_ms: 1000,
+            max_backoff_ms: 60_000,
+            reset_after_secs: 300,
+            unhealthy_grace_secs: 30,
+            restart_dependents: true,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SupervisorConfig {
+    pub default: SupervisorPolicy,
+    /// 按服务名覆盖默认策略
+    pub services: HashMap<String, SupervisorPolicy>,
+}
+
+impl SupervisorConfig {
+    pub fn policy(&self, name: &str) -> &SupervisorPolicy {
+        self.services.get(name).unwrap_or(&self.default)
+    }
+}
+
+/// 服务为何需要重启
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Failure {
+    InitFail,
+    Crashed,
+    Unhealthy,
+    /// 非内核要求的退出
+    Exited,
+}
+
+#[derive(Debug)]
+struct RestartState {
+    count: u32,
+    last: Instant,
+}
+
+/// 等待退避结束的重启, 包含失败的服务及被一起关闭的下游服务
+#[derive(Debug)]
+struct ScheduledRestart {
+    due: Instant,
+    services: HashSet<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Supervisor {
+    pub config: SupervisorConfig,
+    restarts: HashMap<String, RestartState>,
+    scheduled: HashMap<String, ScheduledRestart>,
+    /// 内核主动关闭的服务, 它们退出时不重启
+    pub stopping: HashSet<String>,
+    pub shutting_down: bool,
+    /// 手动重启时, 等待全部退出后再启动的服务
+    restarting: Vec<HashSet<String>>,
+    /// 曾经就绪过的服务, 再次就绪即为重启
+    ready_once: HashSet<String>,
+}
+
+impl Supervisor {
+    /// 计算下一次重启的等待时间, 超过上限时返回 None
+    fn next_backoff(&mut self, name: &str) -> Option<Duration> {
+        let policy = self.config.policy(name).clone();
+        let now = Instant::now();
+        let state = self
+            .restarts
+            .entry(name.to_string())
+            .or_insert(RestartState {
+                count: 0,
+                last: now,
+            });
+        if now.duration_since(state.last) > Duration::from_secs(policy.reset_after_secs) {
+            state.count = 0;
+        }
+        if state.count >= policy.max_restarts {
+            return None;
+        }
+        let backoff = policy
+            .backoff_ms
+            .saturating_mul(1 << state.count.min(16))
+            .min(policy.max_backoff_ms);
+        state.count += 1;
+        state.last = now;
+        Some(Duration::from_millis(backoff))
+    }
+
+    /// 记录服务就绪, 返回它是否是重启后的再次就绪
+    pub fn ready(&mut self, name: &str) -> bool {
+        !self.ready_once.insert(name.to_string())
+    }
+
+    /// 手动启动时清空重启计数
+    pub fn forget(&mut self, name: &str) {
+        self.restarts.remove(name);
+        self.scheduled.remove(name);
+    }
+
+    /// 手动关闭时取消等待中的重启
+    pub fn cancel(&mut self, name: &str) {
+        self.scheduled.remove(name);
+    }
+
+    pub fn restart_after_stop(&mut self, services: HashSet<String>) {
+        self.restarting.push(services);
+    }
+
+    /// 取出已经全部退出的手动重启
+    pub fn take_stopped<F>(&mut self, stopped: F) -> Vec<HashSet<String>>
+    where
+        F: Fn(&HashSet<String>) -> bool,
+    {
+        let (ready, waiting) = self
+            .restarting
+            .drain(..)
+            .partition(|services| stopped(services));
+        self.restarting = waiting;
+        ready
+    }
+
+    /// 取出已到期的重启
+    fn take_due(&mut self) -> Vec<(String, HashSet<String>)> {
+        let now = Instant::now();
+        let due: Vec<String> = self
+            .scheduled
+            .iter()
+            .filter(|(_, restart)| restart.due <= now)
+            .map(|(name, _)| name.clone())
+            .collect();
+        due.into_iter()
+            .filter_map(|name| {
+                self.scheduled
+                    .remove(&name)
+                    .map(|restart| (name, restart.services))
+            })
+            .collect()
+    }
+}
+
+/// 依赖失败服务且仍在运行的下游服务, 它们需要一起关闭并重启
+fn running_dependents(
+    deps_relation: &DepsRelation,
+    health: &KernelHealth,
+    name: &str,
+) -> HashSet<String> {
+    deps_relation
+        .dependents(name)
+        .into_iter()
+        .filter(|dependent| {
+            matches!(
+                health.services.get(dependent),
+                Some((
+                    HealthStatus::Healthy | HealthStatus::Unhealthy | HealthStatus::Starting,
+                    _
+                ))
+            )
+        })
+        .collect()
+}
+
+impl KernelService {
+    /// 服务失败后按策略安排重启
+    pub async fn on_failure(&mut self, name: &str, failure: Failure) {
+        if name == KERNEL_SERVICE
+            || self.supervisor.shutting_down
+            || self.supervisor.scheduled.contains_key(name)
+        {
+            return;
+        }
+        self.supervisor.stopping.remove(name);
+        let policy = self.supervisor.config.policy(name);
+        let (restart, restart_dependents) = (policy.restart, policy.restart_dependents);
+        let should_restart = restart.should_restart(failure);
+        warn!("{} 失败: {:?}, 重启策略: {:?}", name, failure, restart);
+        if let Some(reason) = KernelHealth::get_mut(&self.health).reasons.get(name) {
+            warn!("{} 失败前上报的原因: {}", name, reason);
+        }
+        self.tear_down(name).await;
+        if !should_restart {
+            return;
+        }
+        let Some(backoff) = self.supervisor.next_backoff(name) else {
+            error!("{} 重启次数已达上限, 放弃重启", name);
+            return;
+        };
+        // 下游服务依赖失败的服务, 先正常关闭, 待它恢复后按依赖顺序重启
+        let mut services = HashSet::from([name.to_string()]);
+        let dependents: HashSet<String> = match restart_dependents {
+            true => running_dependents(
+                &self.deps_relation,
+                &KernelHealth::get_mut(&self.health),
+                name,
+            ),
+            false => HashSet::new(),
+        };
+        if !dependents.is_empty() {
+            info!("{:?} 依赖 {}, 关闭后一起重启", dependents, name);
+            let health = KernelHealth::get_mut(&self.health).to_owned();
+            match self
+                .deps_relation
+                .prepare_more_services(dependents.clone(), health, false)
+            {
+                Ok(can_stop) => self.stop_services(can_stop).await,
+                Err(e) => warn!("准备关闭 {} 的下游服务失败: {}", name, e),
+            }
+            services.extend(dependents);
+        }
+        info!("{:?} 后重启 {:?}", backoff, services);
+        self.supervisor.scheduled.insert(
+            name.to_string(),
+            ScheduledRestart {
+                due: Instant::now() + backoff,
+                services,
+            },
+        );
+    }
+
+    /// 检查心跳和任务句柄, 并执行到期的重启
+    pub async fn supervise(&mut self) {
+        if self.supervisor.shutting_down {
+            return;
+        }
+        let failures: Vec<(String, Failure)> = {
+            let mut health = KernelHealth::get_mut(&self.health);
+            health.update();
+            let services = match self.services.as_ref().lock() {
+                Ok(services) => services,
+                Err(e) => {
+                    warn!("获取 services 锁失败, 跳过本次检查: {}", e);
+                    return;
+                }
+            };
+            let now = Local::now();
+            health
+                .services
+                .iter()
+                .filter(|(name, _)| name.as_str() != KERNEL_SERVICE)
+                .filter_map(|(name, (status, last_signal))| match status {
+                    HealthStatus::Unhealthy => {
+                        let grace = self.supervisor.config.policy(name).unhealthy_grace_secs;
+                        last_signal
+                            .filter(|time| (now - *time).num_seconds() as u64 > grace)
+                            .map(|_| (name.clone(), Failure::Unhealthy))
+                    }
+                    HealthStatus::Healthy | HealthStatus::Starting => services
+                        .get(name)
+                        .filter(|handle| handle.is_finished())
+                        .map(|_| (name.clone(), Failure::Crashed)),
+                    _ => None,
+                })
+                .collect()
+        };
+        for (name, failure) in failures {
+            self.on_failure(&name, failure).await;
+        }
+        let due = self.supervisor.take_due();
+        if due.is_empty() {
+            return;
+        }
+        for (name, services) in due {
+            info!("开始重启 {}", name);
+            // 一起关闭的下游服务可能还在退出, 全部退出后再启动
+            self.supervisor.restart_after_stop(services);
+        }
+        if let Err(e) = self.resume_restarts().await {
+            warn!("重启失败: {}", e);
+        }
+    }
+
+    /// 终止失败服务的任务并移除它的 Endpoint
+    async fn tear_down(&mut self, name: &str) {
+        let handle = match self.services.as_ref().lock() {
+            Ok(mut services) => services.remove(name),
+            Err(e) => {
+                warn!("获取 services 锁失败, 无法终止 {}: {}", name, e);
+                None
+            }
+        };
+        if let Some(handle) = handle {
+            handle.abort();
+        }
+        KernelHealth::get_mut(&self.health).set_dead(name);
+        self.send_admin_message(AdminCommand::RemoveEndpoint {
+            name: name.to_string(),
+        })
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn supervisor(policy: SupervisorPolicy) -> Supervisor {
+        Supervisor {
+            config: SupervisorConfig {
+                default: policy,
+                services: HashMap::new(),
+            },
+            ..Default::default()
+        }
+    }
+
+    fn set(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_restart_policy() {
+        let failures = [
+            Failure::InitFail,
+            Failure::Crashed,
+            Failure::Unhealthy,
+            Failure::Exited,
+        ];
+        for failure in failures {
+            assert!(!RestartPolicy::Never.should_restart(failure));
+            assert!(RestartPolicy::Always.should_restart(failure));
+            assert_eq!(
+                RestartPolicy::OnFailure.should_restart(failure),
+                failure != Failure::Exited
+            );
+        }
+    }
+
+    #[test]
+    fn test_policy_override() {
+        let mut config = SupervisorConfig::default();
+        config.services.insert(
+            "ChatService".to_string(),
+            SupervisorPolicy {
+                restart: RestartPolicy::Never,
+                ..Default::default()
+            },
+        );
+        assert_eq!(config.policy("ChatService").restart, RestartPolicy::Never);
+        assert_eq!(config.policy("FsService").restart, RestartPolicy::OnFailure);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_backoff_growth_and_cap() {
+        let mut supervisor = supervisor(SupervisorPolicy {
+            max_restarts: 5,
+            backoff_ms: 1000,
+            max_backoff_ms: 5000,
+            ..Default::default()
+        });
+        let backoffs: Vec<Option<u128>> = (0..6)
+            .map(|_| supervisor.next_backoff("FsService").map(|d| d.as_millis()))
+            .collect();
+        assert_eq!(
+            backoffs,
+            vec![
+                Some(1000),
+                Some(2000),
+                Some(4000),
+                Some(5000),
+                Some(5000),
+                None
+            ]
+        );
+        // 计数按服务区分
+        assert_eq!(
+            supervisor.next_backoff("ChatService"),
+            Some(Duration::from_millis(1000))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_backoff_reset_after() {
+        let mut supervisor = supervisor(SupervisorPolicy {
+            max_restarts: 2,
+            reset_after_secs: 300,
+            ..Default::default()
+        });
+        assert!(supervisor.next_backoff("FsService").is_some());
+        assert!(supervisor.next_backoff("FsService").is_some());
+        assert!(supervisor.next_backoff("FsService").is_none());
+        // 未超过 reset_after 时仍然放弃
+        tokio::time::advance(Duration::from_secs(200)).await;
+        assert!(supervisor.next_backoff("FsService").is_none());
+        tokio::time::advance(Duration::from_secs(301)).await;
+        assert_eq!(
+            supervisor.next_backoff("FsService"),
+            Some(Duration::from_millis(1000))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_forget_resets_count() {
+        let mut supervisor = supervisor(SupervisorPolicy {
+            max_restarts: 1,
+            ..Default::default()
+        });
+        assert!(supervisor.next_backoff("FsService").is_some());
+        assert!(supervisor.next_backoff("FsService").is_none());
+        supervisor.forget("FsService");
+        assert!(supervisor.next_backoff("FsService").is_some());
+    }
+
+    #[test]
+    fn test_running_dependents() {
+        // ChatService -> MemoryService -> FsService, TaskService -> FsService
+        let deps = HashMap::from([
+            ("FsService".to_string(), set(&[])),
+            ("MemoryService".to_string(), set(&["FsService"])),
+            ("ChatService".to_string(), set(&["MemoryService"])),
+            ("TaskService".to_string(), set(&["FsService"])),
+        ]);
+        let deps_relation = DepsRelation::new(deps).unwrap();
+        let health = KernelHealth {
+            kernel: HealthStatus::Healthy,
+            services: HashMap::from([
+                ("FsService".to_string(), (HealthStatus::Healthy, None)),
+                ("MemoryService".to_string(), (HealthStatus::Unhealthy, None)),
+                ("ChatService".to_string(), (HealthStatus::Starting, None)),
+                ("TaskService".to_string(), (HealthStatus::Stopped, None)),
+            ]),
+            reasons: HashMap::new(),
+        };
+        assert_eq!(
+            running_dependents(&deps_relation, &health, "FsService"),
+            set(&["MemoryService", "ChatService"])
+        );
+        assert_eq!(
+            running_dependents(&deps_relation, &health, "ChatService"),
+            HashSet::new()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_take_due() {
+        let mut supervisor = supervisor(SupervisorPolicy::default());
+        let now = Instant::now();
+        supervisor.scheduled.insert(
+            "FsService".to_string(),
+            ScheduledRestart {
+                due: now + Duration::from_secs(1),
+                services: set(&["FsService", "MemoryService"]),
+            },
+        );
+        supervisor.scheduled.insert(
+            "ChatService".to_string(),
+            ScheduledRestart {
+                due: now + Duration::from_secs(10),
+                services: set(&["ChatService"]),
+            },
+        );
+        assert!(supervisor.take_due().is_empty());
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(
+            supervisor.take_due(),
+            vec![(
+                "FsService".to_string(),
+                set(&["FsService", "MemoryService"])
+            )]
+        );
+        // 手动关闭取消等待中的重启
+        supervisor.cancel("ChatService");
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(supervisor.take_due().is_empty());
+    }
+
+    #[test]
+    fn test_restart_after_stop() {
+        let mut supervisor = supervisor(SupervisorPolicy::default());
+        supervisor.restart_after_stop(set(&["FsService", "MemoryService"]));
+        supervisor.restart_after_stop(set(&["ChatService"]));
+        let stopped = set(&["FsService", "MemoryService"]);
+        let ready = supervisor.take_stopped(|services| services.is_subset(&stopped));
+        assert_eq!(ready, vec![set(&["FsService", "MemoryService"])]);
+        assert!(supervisor.take_stopped(|_| false).is_empty());
+        assert_eq!(supervisor.take_stopped(|_| true).len(), 1);
+    }
+}
@@ -0,0 +1,86 @@
+use std::future::pending;
+use tracing::warn;
+
+#[cfg(unix)]
+use tokio::signal::unix::Signal;
+#[cfg(unix)]
+use tokio::signal::unix::SignalKind;
+#[cfg(unix)]
+use tokio::signal::unix::signal;
+#[cfg(windows)]
+use tokio::signal::windows::CtrlC;
+#[cfg(windows)]
+use tokio::signal::windows::CtrlClose;
+#[cfg(windows)]
+use tokio::signal::windows::ctrl_c;
+#[cfg(windows)]
+use tokio::signal::windows::ctrl_close;
+
+/// 监听关机信号, 在 Kernel 运行期间一直持有, 避免处理消息时漏掉信号
+pub struct ShutdownSignal {
+    #[cfg(unix)]
+    interrupt: Option<Signal>,
+    #[cfg(unix)]
+    terminate: Option<Signal>,
+    #[cfg(windows)]
+    ctrl_c: Option<CtrlC>,
+    #[cfg(windows)]
+    ctrl_close: Option<CtrlClose>,
+}
+
+#[cfg(unix)]
+impl ShutdownSignal {
+    pub fn new() -> Self {
+        Self {
+            interrupt: signal(SignalKind::interrupt())
+                .inspect_err(|e| warn!("无法监听 SIGINT: {}", e))
+                .ok(),
+            terminate: signal(SignalKind::terminate())
+                .inspect_err(|e| warn!("无法监听 SIGTERM: {}", e))
+                .ok(),
+        }
+    }
+
+    /// 等待下一个关机信号, 返回信号名
+    pub async fn recv(&mut self) -> &'static str {
+        tokio::select! {
+            _ = recv_or_pending(&mut self.interrupt, Signal::recv) => "SIGINT",
+            _ = recv_or_pending(&mut self.terminate, Signal::recv) => "SIGTERM",
+        }
+    }
+}
+
+#[cfg(windows)]
+impl ShutdownSignal {
+    pub fn new() -> Self {
+        Self {
+            ctrl_c: ctrl_c()
+                .inspect_err(|e| warn!("无法监听 Ctrl-C: {}", e))
+                .ok(),
+            ctrl_close: ctrl_close()
+                .inspect_err(|e| warn!("无法监听 Ctrl-Close: {}", e))
+                .ok(),
+        }
+    }
+
+    /// 等待下一个关机信号, 返回信号名
+    pub async fn recv(&mut self) -> &'static str {
+        tokio::select! {
+            _ = recv_or_pending(&mut self.ctrl_c, CtrlC::recv) => "Ctrl-C",
+            _ = recv_or_pending(&mut self.ctrl_close, CtrlClose::recv) => "Ctrl-Close",
+        }
+    }
+}
+
+/// 监听失败或已关闭的信号永远不会返回
+async fn recv_or_pending<S, F>(listener: &mut Option<S>, recv: F)
+where
+    F: AsyncFnOnce(&mut S) -> Option<()>,
+{
+    if let Some(listener) = listener
+        && recv(listener).await.is_some()
+    {
+        return;
+    }
+    pending::<()>().await
+}
@@ -1,6 +1,7 @@
 use proc_macro::TokenStream;
 use quote::quote;
 use syn::ItemStruct;
+use syn::parse::Parser;
 use syn::parse_macro_input;
 
 #[proc_macro_attribute]
@@ -9,28 +10,19 @@ pub fn base_service(args: TokenStream, input: TokenStream) -> TokenStream {
     let name = &item_struct.ident;
     let name_str = name.to_string();
 
-    let mut deps = Vec::new();
-
-    // 使用更健壮的参数解析方式
-    let args_parser = syn::meta::parser(|meta| {
-        if meta.path.is_ident("deps") {
-            let value = meta.value()?;
-            let array: syn::ExprArray = value.parse()?;
-
-            for expr in array.elems {
-                if let syn::Expr::Lit(syn::ExprLit {
-                    lit: syn::Lit::Str(s),
-                    ..
-                }) = expr
-                {
-                    deps.push(s.value());
-                }
-            }
-        }
-        Ok(())
-    });
+    let ServiceArgs {
+        deps,
+        concurrency,
+        tick_ms,
+    } = match parse_args(args.into()) {
+        Ok(args) => args,
+        Err(e) => return e.to_compile_error().into(),
+    };
 
-    parse_macro_input!(args with args_parser);
+    let tick = match tick_ms {
+        Some(ms) => quote! { Some(std::time::Duration::from_millis(#ms)) },
+        None => quote! { None },
+    };
 
     let expanded = quote! {
         #item_struct
@@ -52,6 +44,18 @@ pub fn base_service(args: TokenStream, input: TokenStream) -> TokenStream {
             }
         }
 
+        impl heleny_service::HasConcurrency for #name {
+            fn concurrency() -> usize {
+                #concurrency
+            }
+        }
+
+        impl heleny_service::HasTick for #name {
+            fn tick_interval() -> Option<std::time::Duration> {
+                #tick
+            }
+        }
+
         inventory::submit! {
             heleny_service::ServiceFactory {
                 name: #name_str,
@@ -65,3 +69,136 @@ pub fn base_service(args: TokenStream, input: TokenStream) -> TokenStream {
 
     TokenStream::from(expanded)
 }
+
+/// base_service 的参数
+#[derive(Debug, PartialEq)]
+struct ServiceArgs {
+    deps: Vec<String>,
+    concurrency: usize,
+    tick_ms: Option<u64>,
+}
+
+impl Default for ServiceArgs {
+    fn default() -> Self {
+        Self {
+            deps: Vec::new(),
+            concurrency: 0,
+            // 默认每秒一次 handle_tick
+            tick_ms: Some(1000),
+        }
+    }
+}
+
+fn parse_args(args: proc_macro2::TokenStream) -> syn::Result<ServiceArgs> {
+    let mut parsed = ServiceArgs::default();
+    // 使用更健壮的参数解析方式
+    let args_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("deps") {
+            let value = meta.value()?;
+            let array: syn::ExprArray = value.parse()?;
+
+            for expr in array.elems {
+                if let syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(s),
+                    ..
+                }) = expr
+                {
+                    parsed.deps.push(s.value());
+                }
+            }
+        } else if meta.path.is_ident("concurrency") {
+            // 同时在独立任务中处理的消息数上限, 配合 Service::dispatch 使用
+            let value: syn::LitInt = meta.value()?.parse()?;
+            parsed.concurrency = value.base10_parse()?;
+        } else if meta.path.is_ident("tick") {
+            // tick = "500ms" / "2s" / "1m", tick = none 表示不调用 handle_tick
+            let value = meta.value()?;
+            if value.peek(syn::Ident) {
+                let ident: syn::Ident = value.parse()?;
+                if ident != "none" {
+                    return Err(syn::Error::new(
+                        ident.span(),
+                        "tick 只能是时长字符串或 none",
+                    ));
+                }
+                parsed.tick_ms = None;
+            } else {
+                let lit: syn::LitStr = value.parse()?;
+                let ms = parse_duration_ms(&lit.value()).ok_or_else(|| {
+                    syn::Error::new(
+                        lit.span(),
+                        "无法解析 tick 时长, 例: \"500ms\", \"2s\", \"1m\"",
+                    )
+                })?;
+                parsed.tick_ms = Some(ms);
+            }
+        }
+        Ok(())
+    });
+    args_parser.parse2(args)?;
+    Ok(parsed)
+}
+
+/// 解析 "500ms" / "2s" / "1m" 形式的时长, 返回毫秒数, 不允许为 0
+fn parse_duration_ms(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (number, unit) = value.split_at(value.find(|c: char| !c.is_ascii_digit())?);
+    let number: u64 = number.parse().ok()?;
+    let ms = match unit.trim() {
+        "ms" => number,
+        "s" => number.checked_mul(1000)?,
+        "m" => number.checked_mul(60_000)?,
+        _ => return None,
+    };
+    (ms > 0).then_some(ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration_ms() {
+        assert_eq!(parse_duration_ms("500ms"), Some(500));
+        assert_eq!(parse_duration_ms("2s"), Some(2000));
+        assert_eq!(parse_duration_ms("1m"), Some(60_000));
+        assert_eq!(parse_duration_ms(" 3 s "), Some(3000));
+        for bad in ["", "500", "ms", "0s", "1h", "-1s", "1.5s", "none"] {
+            assert_eq!(parse_duration_ms(bad), None, "{:?} 不应解析成功", bad);
+        }
+        assert_eq!(parse_duration_ms("99999999999999999999m"), None);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = parse_args(quote! {
+            deps=["ConfigService", "FsService"], concurrency=4, tick="500ms"
+        })
+        .unwrap();
+        assert_eq!(
+            args,
+            ServiceArgs {
+                deps: vec!["ConfigService".to_string(), "FsService".to_string()],
+                concurrency: 4,
+                tick_ms: Some(500),
+            }
+        );
+        assert_eq!(parse_args(quote! { tick="2s" }).unwrap().tick_ms, Some(2000));
+        assert_eq!(parse_args(quote! { tick=none }).unwrap().tick_ms, None);
+        assert_eq!(parse_args(quote! {}).unwrap(), ServiceArgs::default());
+    }
+
+    #[test]
+    fn test_parse_args_bad_input() {
+        let bad = [
+            quote! { tick="soon" },
+            quote! { tick=never },
+            quote! { tick=500 },
+            quote! { concurrency="4" },
+            quote! { concurrency=-1 },
+        ];
+        for args in bad {
+            assert!(parse_args(args.clone()).is_err(), "{} 不应解析成功", args);
+        }
+    }
+}
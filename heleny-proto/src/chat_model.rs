@@ -8,11 +8,19 @@ use async_trait::async_trait;
 use rkyv::Archive;
 use rkyv::Deserialize;
 use rkyv::Serialize;
+use tokio::sync::mpsc;
 use tokio::time::timeout;
+use tracing::info;
+use uuid::Uuid;
 
+use crate::ContextBudget;
 use crate::MemoryEntry;
 use crate::RequiredTools;
+use crate::ToolCall;
+use crate::ToolFunction;
 use crate::ToolIntent;
+use crate::ToolMessage;
+use crate::ToolReply;
 use crate::memory::ChatRole;
 
 #[derive(Debug)]
@@ -61,13 +69,28 @@ impl PlannerModel {
             response
         ))
     }
+
+    /// 最近一次回复所用的 API
+    pub fn provider(&self) -> Option<String> {
+        self.chat_model.provider()
+    }
+
+    pub fn set_task(&self, task: Uuid) {
+        self.chat_model.set_task(task);
+    }
 }
 
 #[derive(Debug)]
 pub struct ExecutorModel {
     memory: Vec<MemoryEntry>,
     timeout: Duration,
-    chat_model: Box<dyn Chat>
+    chat_model: Box<dyn Chat>,
+    /// 为 true 时通过后端的原生工具调用执行, 否则让模型输出 ToolIntent
+    native_tools: bool,
+    tools: Vec<ToolFunction>,
+    /// 原生工具调用模式下, 接在 memory 之后的任务, 调用和结果
+    tool_messages: Vec<ToolMessage>,
+    budget: ContextBudget,
 }
 
 impl ExecutorModel {
@@ -76,9 +99,148 @@ impl ExecutorModel {
             memory: vec![MemoryEntry::temp(ChatRole::System, preset)],
             timeout:Duration::from_secs(timeout),
             chat_model,
+            native_tools: false,
+            tools: Vec::new(),
+            tool_messages: Vec::new(),
+            budget: ContextBudget::default(),
         }
     }
 
+    pub fn with_budget(mut self, budget: ContextBudget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    pub fn with_native_tools(mut self, native_tools: bool) -> Self {
+        self.native_tools = native_tools;
+        self
+    }
+
+    pub fn native_tools(&self) -> bool {
+        self.native_tools
+    }
+
+    /// 最近一次回复所用的 API
+    pub fn provider(&self) -> Option<String> {
+        self.chat_model.provider()
+    }
+
+    pub fn set_task(&self, task: Uuid) {
+        self.chat_model.set_task(task);
+    }
+
+    /// 原生工具调用模式下可供调用的函数
+    pub fn set_tools(&mut self, tools: Vec<ToolFunction>) {
+        self.tools = tools;
+    }
+
+    /// 原生工具调用模式下获取下一步调用, message 为新的任务描述, 只在第一轮提供
+    pub async fn get_tool_calls(&mut self, message: Option<&str>) -> Result<ToolReply> {
+        let checkpoint = self.tool_messages.len();
+        let reply = self._get_tool_calls(message).await;
+        if reply.is_err() {
+            self.tool_messages.truncate(checkpoint);
+        }
+        reply
+    }
+
+    async fn _get_tool_calls(&mut self, message: Option<&str>) -> Result<ToolReply> {
+        if let Some(message) = message {
+            self.tool_messages.push(ToolMessage::Entry(MemoryEntry::temp(ChatRole::User, message)));
+        }
+        let messages: Vec<ToolMessage> = self
+            .memory
+            .iter()
+            .cloned()
+            .map(ToolMessage::Entry)
+            .chain(self.fit_tool_messages().iter().map(|&message| message.clone()))
+            .collect();
+        let reply = timeout(self.timeout, self.chat_model.chat_with_tools(&messages, &self.tools))
+            .await
+            .context("获取工具调用超时")?
+            .context("获取工具调用失败")?;
+        if let ToolReply::Calls { text, calls } = &reply {
+            self.tool_messages.push(ToolMessage::Calls {
+                text: text.clone(),
+                calls: calls.clone(),
+            });
+        }
+        Ok(reply)
+    }
+
+    /// 记录一次原生工具调用的结果, 下一轮会作为工具消息交给模型
+    pub fn push_tool_result(&mut self, call: &ToolCall, content: String) {
+        let content = match self.budget.truncate(&content) {
+            Some(truncated) => {
+                info!("{} 的结果超出单条预算, 已截断", call.name);
+                truncated
+            }
+            None => content,
+        };
+        self.tool_messages.push(ToolMessage::Result {
+            call_id: call.id.clone(),
+            name: call.name.clone(),
+            content,
+        });
+    }
+
+    /// 任务描述总是保留, 超出预算时按轮丢弃最早的调用及其结果
+    fn fit_tool_messages(&self) -> Vec<&ToolMessage> {
+        let start = self
+            .tool_messages
+            .iter()
+            .position(|message| matches!(message, ToolMessage::Calls { .. }))
+            .unwrap_or(self.tool_messages.len());
+        let tokenizer = self.budget.tokenizer;
+        let fixed = self.memory.iter().map(|entry| tokenizer.entry_tokens(entry)).sum::<usize>()
+            + self.tool_messages[..start]
+                .iter()
+                .map(|message| tokenizer.tool_message_tokens(message))
+                .sum::<usize>();
+        let mut rounds: Vec<Vec<&ToolMessage>> = Vec::new();
+        for message in &self.tool_messages[start..] {
+            match (message, rounds.last_mut()) {
+                (ToolMessage::Calls { .. }, _) | (_, None) => rounds.push(vec![message]),
+                (_, Some(round)) => round.push(message),
+            }
+        }
+        let tokens: Vec<usize> = rounds
+            .iter()
+            .map(|round| round.iter().map(|&message| tokenizer.tool_message_tokens(message)).sum())
+            .collect();
+        let skip = self.budget.fit(fixed, &tokens);
+        if skip > 0 {
+            info!("Executor 上下文超出预算, 省略了最早的 {} 轮工具调用", skip);
+        }
+        self.tool_messages[..start]
+            .iter()
+            .chain(rounds.into_iter().skip(skip).flatten())
+            .collect()
+    }
+
+    /// preset 和任务描述总是保留, 超出预算时丢弃最早的中间消息
+    fn fit_memory(&self) -> Vec<&MemoryEntry> {
+        let start = self
+            .memory
+            .iter()
+            .position(|entry| entry.role == ChatRole::User)
+            .map_or(self.memory.len(), |index| index + 1);
+        let tokenizer = self.budget.tokenizer;
+        let fixed = self.memory[..start].iter().map(|entry| tokenizer.entry_tokens(entry)).sum();
+        let tokens: Vec<usize> = self.memory[start..]
+            .iter()
+            .map(|entry| tokenizer.entry_tokens(entry))
+            .collect();
+        let skip = self.budget.fit(fixed, &tokens);
+        if skip > 0 {
+            info!("Executor 上下文超出预算, 省略了最早的 {} 条消息", skip);
+        }
+        self.memory[..start]
+            .iter()
+            .chain(self.memory[start + skip..].iter())
+            .collect()
+    }
+
     pub fn add_preset(&mut self, append: &str) {
         self.memory.push(MemoryEntry::temp(ChatRole::System, append));
     }
@@ -98,9 +260,16 @@ impl ExecutorModel {
         }else {
             ChatRole::System
         };
+        let message = match self.budget.truncate(message) {
+            Some(truncated) => {
+                info!("Executor 收到的消息超出单条预算, 已截断");
+                truncated
+            }
+            None => message.to_string(),
+        };
         let message = MemoryEntry::temp(role,message);
         self.memory.push(message);
-        let messages=self.memory.iter().collect::<Vec<_>>();
+        let messages=self.fit_memory();
         let response = match timeout(self.timeout, self.chat_model.chat(&messages)).await.context("获取 tools_intent 超时")? {
             Ok(resp)=>resp,
             Err(e)=> return Err(anyhow!("获取 tools_intent 失败: {e}"))
@@ -124,6 +293,137 @@ impl ExecutorModel {
 #[async_trait]
 pub trait Chat:Debug+Sync+Send {
     async fn chat(&self,messages: &[&MemoryEntry])->Result<String>;
+
+    /// 流式聊天, 收到的每段文本都发给 delta, 结束后返回完整回复; 默认整段发送一次
+    async fn chat_stream(&self,messages: &[&MemoryEntry],delta: mpsc::UnboundedSender<String>)->Result<String> {
+        let response=self.chat(messages).await?;
+        let _=delta.send(response.clone());
+        Ok(response)
+    }
+
+    /// 原生工具调用, tools 转为后端的函数定义, 不支持的后端返回错误
+    async fn chat_with_tools(&self,_messages: &[ToolMessage],_tools: &[ToolFunction])->Result<ToolReply> {
+        Err(anyhow!("当前后端不支持原生工具调用"))
+    }
+
+    /// 最近一次成功回复所用的 API, 只有组合了多个 API 的后端才知道
+    fn provider(&self)->Option<String> {
+        None
+    }
+
+    /// 之后的调用属于哪个任务, 用于用量统计
+    fn set_task(&self,_task: Uuid) {}
+}
+
+/// 从流式返回的 JSON 中逐步取出顶层 "content" 字段的字符串
+#[derive(Debug, Default)]
+pub struct ContentExtractor {
+    buffer: String,
+    /// content 字符串中下一个未解码字符的位置
+    cursor: Option<usize>,
+    content: String,
+    done: bool,
+}
+
+impl ContentExtractor {
+    /// 追加一段回复, 返回 content 是否有新增
+    pub fn push(&mut self, delta: &str) -> bool {
+        self.buffer.push_str(delta);
+        if self.done {
+            return false;
+        }
+        if self.cursor.is_none() {
+            self.cursor = find_content_start(&self.buffer);
+        }
+        let Some(mut cursor) = self.cursor else {
+            return false;
+        };
+        let before = self.content.len();
+        while let Some(c) = self.buffer[cursor..].chars().next() {
+            match c {
+                '"' => {
+                    self.done = true;
+                    break;
+                }
+                '\\' => match decode_escape(&self.buffer[cursor..]) {
+                    Some((c, len)) => {
+                        self.content.push(c);
+                        cursor += len;
+                    }
+                    // 转义还没收全, 等下一段
+                    None => break,
+                },
+                c => {
+                    self.content.push(c);
+                    cursor += c.len_utf8();
+                }
+            }
+        }
+        self.cursor = Some(cursor);
+        self.content.len() > before
+    }
+
+    /// 目前取出的 content
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// 收到的完整回复
+    pub fn buffer(&self) -> &str {
+        &self.buffer
+    }
+}
+
+/// "content" 键之后字符串值第一个字符的位置
+fn find_content_start(buffer: &str) -> Option<usize> {
+    let mut from = 0;
+    while let Some(index) = buffer[from..].find("\"content\"") {
+        let after = from + index + "\"content\"".len();
+        let rest = buffer[after..].trim_start();
+        if let Some(rest) = rest.strip_prefix(':') {
+            let value = rest.trim_start();
+            if let Some(value) = value.strip_prefix('"') {
+                return Some(buffer.len() - value.len());
+            }
+            if value.is_empty() {
+                return None;
+            }
+        } else if rest.is_empty() {
+            return None;
+        }
+        from = after;
+    }
+    None
+}
+
+/// 解码以 \ 开头的转义, 返回字符和占用的字节数, 未收全时返回 None
+fn decode_escape(escape: &str) -> Option<(char, usize)> {
+    let c = escape[1..].chars().next()?;
+    let decoded = match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        'b' => '\u{8}',
+        'f' => '\u{c}',
+        'u' => {
+            let high = u32::from_str_radix(escape.get(2..6)?, 16).unwrap_or(0xFFFD);
+            if !(0xD800..0xDC00).contains(&high) {
+                return Some((char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER), 6));
+            }
+            // 代理对, 需要再收一个 \uXXXX
+            let low = escape.get(6..12)?;
+            let low = low
+                .strip_prefix("\\u")
+                .and_then(|low| u32::from_str_radix(low, 16).ok())
+                .filter(|low| (0xDC00..0xE000).contains(low));
+            let c = low
+                .and_then(|low| char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)))
+                .unwrap_or(char::REPLACEMENT_CHARACTER);
+            return Some((c, 12));
+        }
+        c => c,
+    };
+    Some((decoded, 1 + c.len_utf8()))
 }
 
 #[async_trait]
@@ -239,3 +539,40 @@ pub static EXECUTOR_SCHEMA: &'static str = r#"{
   "required": ["reason"],
   "additionalProperties": false
 }"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_extractor_handles_split_chunks() {
+        let reply = r#"```json
+{"content": "（点头）好的\n\"你好\" \ud83d\ude00 \u4f60", "need_help": "content"}
+```"#;
+        // 按字符逐个喂入, 转义和代理对都会被切开
+        let mut extractor = ContentExtractor::default();
+        let mut seen = Vec::new();
+        for c in reply.chars() {
+            if extractor.push(&c.to_string()) {
+                seen.push(extractor.content().to_string());
+            }
+        }
+        let expected = "（点头）好的\n\"你好\" 😀 你";
+        assert_eq!(extractor.content(), expected);
+        assert_eq!(extractor.buffer(), reply);
+        assert!(seen.len() > 1);
+        assert!(seen.windows(2).all(|w| w[1].starts_with(w[0].as_str())));
+        let parsed: serde_json::Value = serde_json::from_str(trim_response(&reply.to_string()).unwrap()).unwrap();
+        assert_eq!(parsed["content"], expected);
+    }
+
+    #[test]
+    fn content_extractor_waits_for_value() {
+        let mut extractor = ContentExtractor::default();
+        assert!(!extractor.push(r#"{"need_help": null, "content""#));
+        assert!(!extractor.push(" : "));
+        assert!(extractor.push(r#""嗯"#));
+        assert!(!extractor.push(r#"", "x": "y"}"#));
+        assert_eq!(extractor.content(), "嗯");
+    }
+}
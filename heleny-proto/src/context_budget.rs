@@ -0,0 +1,267 @@
+use crate::MemoryContent;
+use crate::MemoryEntry;
+use crate::ToolMessage;
+use tiktoken_rs::CoreBPE;
+use tiktoken_rs::cl100k_base_singleton;
+use tiktoken_rs::o200k_base_singleton;
+use tiktoken_rs::p50k_base_singleton;
+use tiktoken_rs::p50k_edit_singleton;
+use tiktoken_rs::r50k_base_singleton;
+use tiktoken_rs::tokenizer::Tokenizer as Encoding;
+use tiktoken_rs::tokenizer::get_tokenizer;
+
+/// 每条消息的角色, 时间前缀等额外开销
+const MESSAGE_OVERHEAD: usize = 12;
+
+/// 图片以 256px 的缩略图发送, 按 Gemini 每张图 258 token 估算
+const IMAGE_TOKENS: usize = 258;
+
+/// 模型上下文的 token 预算
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextBudget {
+    /// 整个请求的上限, 超出时丢弃最旧的历史
+    pub max_tokens: usize,
+    /// 单条消息 (如工具结果) 的上限, 超出时截断
+    pub max_message_tokens: usize,
+    pub tokenizer: Tokenizer,
+}
+
+impl Default for ContextBudget {
+    fn default() -> Self {
+        Self {
+            max_tokens: 32000,
+            max_message_tokens: 4000,
+            tokenizer: Tokenizer::default(),
+        }
+    }
+}
+
+impl ContextBudget {
+    pub fn new(max_tokens: usize, max_message_tokens: usize) -> Self {
+        Self {
+            max_tokens,
+            max_message_tokens,
+            tokenizer: Tokenizer::default(),
+        }
+    }
+
+    pub fn with_tokenizer(mut self, tokenizer: Tokenizer) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    /// fixed 为必须保留的 token 数, groups 为按从旧到新排列的各组 token 数,
+    /// 返回需要从最旧一端丢弃的组数, 最新的一组总是保留
+    pub fn fit(&self, fixed: usize, groups: &[usize]) -> usize {
+        let mut total = fixed + groups.iter().sum::<usize>();
+        let mut skip = 0;
+        while total > self.max_tokens && skip + 1 < groups.len() {
+            total -= groups[skip];
+            skip += 1;
+        }
+        skip
+    }
+
+    /// 超出单条上限时截断, 没有超出返回 None
+    pub fn truncate(&self, text: &str) -> Option<String> {
+        self.tokenizer.truncate(text, self.max_message_tokens)
+    }
+}
+
+/// 计算 token 数的分词器
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Tokenizer {
+    /// tiktoken 的 BPE 编码, 与 OpenAI 模型的计数一致
+    Bpe(Encoding),
+    /// 没有已知分词器的模型 (如 Gemini, Grok, 本地模型) 按字符规律估算
+    #[default]
+    Heuristic,
+}
+
+impl Tokenizer {
+    /// name 可以是编码名 (如 "o200k_base") 或模型名 (如 "gpt-4o"), 都不认识时退回估算
+    pub fn from_name(name: &str) -> Self {
+        let encoding = match name {
+            "o200k_base" => Some(Encoding::O200kBase),
+            "cl100k_base" => Some(Encoding::Cl100kBase),
+            "p50k_base" => Some(Encoding::P50kBase),
+            "p50k_edit" => Some(Encoding::P50kEdit),
+            "r50k_base" => Some(Encoding::R50kBase),
+            model => get_tokenizer(model),
+        };
+        encoding.map_or(Self::Heuristic, Self::Bpe)
+    }
+
+    fn bpe(&self) -> Option<&'static CoreBPE> {
+        match self {
+            Self::Bpe(Encoding::O200kBase) => Some(o200k_base_singleton()),
+            Self::Bpe(Encoding::Cl100kBase) => Some(cl100k_base_singleton()),
+            Self::Bpe(Encoding::P50kBase) => Some(p50k_base_singleton()),
+            Self::Bpe(Encoding::P50kEdit) => Some(p50k_edit_singleton()),
+            Self::Bpe(Encoding::R50kBase | Encoding::Gpt2) => Some(r50k_base_singleton()),
+            Self::Heuristic => None,
+        }
+    }
+
+    /// 文本的 token 数
+    pub fn count(&self, text: &str) -> usize {
+        match self.bpe() {
+            Some(bpe) => bpe.encode_ordinary(text).len(),
+            None => {
+                let mut counter = TokenCounter::default();
+                text.chars().for_each(|c| {
+                    counter.push(c);
+                });
+                counter.tokens
+            }
+        }
+    }
+
+    pub fn entry_tokens(&self, entry: &MemoryEntry) -> usize {
+        let content = match &entry.content {
+            MemoryContent::Image(_) => IMAGE_TOKENS,
+            content => self.count(content.to_str()),
+        };
+        content + MESSAGE_OVERHEAD
+    }
+
+    pub fn tool_message_tokens(&self, message: &ToolMessage) -> usize {
+        match message {
+            ToolMessage::Entry(entry) => self.entry_tokens(entry),
+            ToolMessage::Calls { text, calls } => {
+                let calls: usize = calls
+                    .iter()
+                    .map(|call| {
+                        self.count(&call.name)
+                            + self.count(&serde_json::to_string(&call.args).unwrap_or_default())
+                    })
+                    .sum();
+                self.count(text.as_deref().unwrap_or_default()) + calls + MESSAGE_OVERHEAD
+            }
+            ToolMessage::Result { content, .. } => self.count(content) + MESSAGE_OVERHEAD,
+        }
+    }
+
+    /// 保留开头约 2/3 和结尾约 1/3, 中间换成省略标记, 结尾的闭合标签等因此得以保留
+    pub fn truncate(&self, text: &str, max: usize) -> Option<String> {
+        let total = self.count(text);
+        if total <= max {
+            return None;
+        }
+        let (head, tail) = match self.bpe() {
+            Some(bpe) => {
+                let tokens = bpe.encode_ordinary(text);
+                // token 可能只含汉字等字符的一部分字节, 此时无法解码, 向内收缩到完整的字符
+                let decoded_len =
+                    |tokens: &[u32]| bpe.decode(tokens.to_vec()).ok().map(|text| text.len());
+                let head = (0..=max * 2 / 3)
+                    .rev()
+                    .find_map(|end| decoded_len(&tokens[..end]))
+                    .unwrap_or_default();
+                let tail = (tokens.len() - max / 3..=tokens.len())
+                    .find_map(|start| decoded_len(&tokens[start..]))
+                    .unwrap_or_default();
+                (head, (text.len() - tail).max(head))
+            }
+            None => {
+                let head = take_tokens(text.chars(), max * 2 / 3);
+                let tail = take_tokens(text.chars().rev(), max / 3);
+                (head, text.len() - tail.min(text.len() - head))
+            }
+        };
+        let omitted = total.saturating_sub(self.count(&text[..head]) + self.count(&text[tail..]));
+        Some(format!(
+            "{}\n...[内容过长, 省略了约 {} tokens]...\n{}",
+            &text[..head],
+            omitted,
+            &text[tail..]
+        ))
+    }
+}
+
+/// 按 BPE 分词的常见规律计数: 连续的 ASCII 字母数字每 4 个算一个 token,
+/// 空白并入后面的词, 汉字, 标点等其他字符各算一个
+#[derive(Debug, Default)]
+struct TokenCounter {
+    tokens: usize,
+    word: usize,
+}
+
+impl TokenCounter {
+    fn push(&mut self, c: char) -> usize {
+        if c.is_ascii_alphanumeric() {
+            if self.word.is_multiple_of(4) {
+                self.tokens += 1;
+            }
+            self.word += 1;
+        } else {
+            self.word = 0;
+            if !c.is_ascii_whitespace() {
+                self.tokens += 1;
+            }
+        }
+        self.tokens
+    }
+}
+
+/// 估算时从 chars 开头取不超过 max 个 token 的字符, 返回取到的字节数
+fn take_tokens(chars: impl Iterator<Item = char>, max: usize) -> usize {
+    let mut counter = TokenCounter::default();
+    let mut bytes = 0;
+    for c in chars {
+        if counter.push(c) > max {
+            break;
+        }
+        bytes += c.len_utf8();
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChatRole;
+    use std::path::PathBuf;
+
+    #[test]
+    fn budget_fit_and_truncate() {
+        assert_eq!(Tokenizer::Heuristic.count("今天天气 hello world"), 8);
+        let budget = ContextBudget::new(100, 30);
+        assert_eq!(budget.fit(50, &[20, 20, 20]), 1);
+        assert_eq!(budget.fit(50, &[10, 10]), 0);
+        // 最新的一组即使超出也保留
+        assert_eq!(budget.fit(90, &[20, 20, 20]), 2);
+
+        assert_eq!(budget.truncate("短消息"), None);
+        let text = format!("<tool_result>{}</tool_result>", "很长的结果".repeat(20));
+        let truncated = budget.truncate(&text).unwrap();
+        assert!(truncated.starts_with("<tool_result>"));
+        assert!(truncated.ends_with("</tool_result>"));
+        assert!(truncated.contains("省略了约"));
+        assert!(budget.tokenizer.count(&truncated) < budget.tokenizer.count(&text));
+
+        let image = MemoryEntry::temp(ChatRole::User, PathBuf::from("cat.png"));
+        assert_eq!(budget.tokenizer.entry_tokens(&image), IMAGE_TOKENS + MESSAGE_OVERHEAD);
+    }
+
+    #[test]
+    fn bpe_tokenizer() {
+        assert_eq!(Tokenizer::from_name("gpt-4o"), Tokenizer::Bpe(Encoding::O200kBase));
+        assert_eq!(Tokenizer::from_name("cl100k_base"), Tokenizer::Bpe(Encoding::Cl100kBase));
+        assert_eq!(Tokenizer::from_name("gemini-2.5-flash"), Tokenizer::Heuristic);
+
+        let tokenizer = Tokenizer::from_name("cl100k_base");
+        assert_eq!(tokenizer.count("hello world"), 2);
+        let text = format!("<tool_result>{}</tool_result>", "很长的结果".repeat(20));
+        let truncated = tokenizer.truncate(&text, 30).unwrap();
+        assert!(truncated.starts_with("<tool_result>"));
+        assert!(truncated.ends_with("</tool_result>"));
+        assert!(tokenizer.count(&truncated) < tokenizer.count(&text));
+        // 汉字和 emoji 常被拆成多个 token, 任意截断位置都要落在字符边界上
+        let text = "龘😀Ω很长的结果".repeat(10);
+        for max in 3..60 {
+            let truncated = tokenizer.truncate(&text, max).unwrap();
+            assert!(truncated.contains("省略了约"));
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use crate::HealthStatus;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt::Write;
+
+/// 服务依赖图中的一个节点
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DependencyNode {
+    pub name: String,
+    pub status: HealthStatus,
+    /// 直接依赖
+    pub deps: Vec<String>,
+    /// 所有直接或间接依赖, 按初始化顺序
+    pub init_seq: Vec<String>,
+    /// 所有直接或间接依赖它的服务, 按关闭顺序
+    pub stop_seq: Vec<String>,
+}
+
+/// 服务依赖图及实时健康状态
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DependencyGraph {
+    /// 按初始化顺序排列
+    pub nodes: Vec<DependencyNode>,
+}
+
+impl DependencyGraph {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// 导出为 Graphviz DOT, 边从服务指向它的依赖, 颜色表示健康状态
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from(
+            "digraph services {\n    rankdir=BT;\n    node [shape=box, style=filled];\n",
+        );
+        for node in &self.nodes {
+            let color = match node.status {
+                HealthStatus::Healthy => "#28a745",
+                HealthStatus::Unhealthy => "#ffc107",
+                HealthStatus::Stopped => "#dc3545",
+                HealthStatus::Stopping => "#6f42c1",
+                HealthStatus::Starting => "#007bff",
+            };
+            let _ = writeln!(
+                dot,
+                "    \"{}\" [fillcolor=\"{}\", fontcolor=white, tooltip=\"{:?}\"];",
+                node.name, color, node.status
+            );
+        }
+        for node in &self.nodes {
+            for dep in &node.deps {
+                let _ = writeln!(dot, "    \"{}\" -> \"{}\";", node.name, dep);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
@@ -43,6 +43,15 @@ pub enum FrontendCommand {
         file_name: String,
         data_base64: String,
     },
+    StartService {
+        name: String,
+    },
+    StopService {
+        name: String,
+    },
+    RestartService {
+        name: String,
+    },
 }
 
 impl FrontendCommand {
@@ -21,6 +21,9 @@ pub enum HealthStatus {
 pub struct KernelHealth {
     pub kernel: HealthStatus,
     pub services: HashMap<String, (HealthStatus, Option<chrono::prelude::DateTime<Local>>)>,
+    /// 服务自己上报的不健康原因
+    #[serde(default)]
+    pub reasons: HashMap<String, String>,
 }
 
 impl KernelHealth {
@@ -46,7 +49,7 @@ impl KernelHealth {
         if self.kernel != other.kernel {
             return false;
         };
-        if self.services.len() != other.services.len() {
+        if self.services.len() != other.services.len() || self.reasons != other.reasons {
             return false;
         };
         self.services.keys().all(
@@ -67,6 +70,17 @@ impl KernelHealth {
         };
         *status = HealthStatus::Healthy;
         *time = Some(Local::now());
+        self.reasons.remove(name);
+    }
+
+    /// 服务上报不健康, 保留最后一次心跳时间以便计算宽限期
+    pub fn set_unhealthy(&mut self, name: &str, reason: String) {
+        let Some((status, _)) = self.services.get_mut(name) else {
+            warn!("未知服务: {}", name);
+            return;
+        };
+        *status = HealthStatus::Unhealthy;
+        self.reasons.insert(name.to_string(), reason);
     }
 
     pub fn set_dead(&mut self, name: &str) {
@@ -79,5 +93,6 @@ impl KernelHealth {
         };
         *status = HealthStatus::Stopped;
         *time = Some(Local::now());
+        self.reasons.remove(name);
     }
 }
@@ -22,6 +22,10 @@ mod model_response_schema;
 pub use model_response_schema::*;
 mod chat_model;
 pub use chat_model::*;
+mod context_budget;
+pub use context_budget::*;
+mod usage;
+pub use usage::*;
 mod tool;
 pub use tool::*;
 mod user_decision;
@@ -36,3 +40,11 @@ mod process;
 pub use process::*;
 mod mcp;
 pub use mcp::*;
+mod policy;
+pub use policy::*;
+mod traffic;
+pub use traffic::*;
+mod shutdown;
+pub use shutdown::*;
+mod dependency_graph;
+pub use dependency_graph::*;
@@ -2,6 +2,8 @@ use anyhow::Result;
 use std::any::Any;
 use std::any::{self};
 use std::fmt::Debug;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
 use uuid::Uuid;
 
 use crate::role::ServiceRole;
@@ -12,6 +14,12 @@ pub struct TokenMessage {
     pub target: String,
     pub token: Uuid,
     pub payload: Box<dyn AnyMessage>,
+    /// 请求/响应调用的关联 id
+    pub correlation: Option<Uuid>,
+    /// 需要投递回执时由 Bus 回报投递结果
+    pub receipt: Option<oneshot::Sender<Receipt>>,
+    pub priority: Priority,
+    pub trace: Option<TraceContext>,
 }
 
 impl TokenMessage {
@@ -20,14 +28,60 @@ impl TokenMessage {
             target,
             token,
             payload,
+            correlation: None,
+            receipt: None,
+            priority: Priority::Data,
+            trace: None,
         }
     }
 
+    pub fn with_correlation(mut self, correlation: Uuid) -> Self {
+        self.correlation = Some(correlation);
+        self
+    }
+
+    pub fn with_receipt(mut self, receipt: oneshot::Sender<Receipt>) -> Self {
+        self.receipt = Some(receipt);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_trace(mut self, trace: TraceContext) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
     pub fn sign(self, name: String, role: ServiceRole) -> SignedMessage {
-        SignedMessage::new(self.target, name, role, self.payload)
+        let mut msg = SignedMessage::new(self.target, name, role, self.payload);
+        msg.correlation = self.correlation;
+        msg.priority = self.priority;
+        msg.trace = self.trace;
+        msg
     }
 }
 
+/// 跨服务传递的 trace 上下文
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceContext {
+    /// 同一个用户请求经过的所有服务共用一个 trace id
+    pub trace_id: Uuid,
+    /// 发送方当时所在 span 的 id
+    pub parent_span: Option<u64>,
+}
+
+/// 消息优先级, 控制消息总是先于数据消息被处理
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Priority {
+    /// Stop、状态上报等控制面消息
+    Control,
+    #[default]
+    Data,
+}
+
 /// 消息 struct，定义消息的字段
 #[derive(Debug)]
 pub struct SignedMessage {
@@ -35,6 +89,9 @@ pub struct SignedMessage {
     pub name: String,
     pub role: ServiceRole,
     pub payload: Box<dyn AnyMessage>,
+    pub correlation: Option<Uuid>,
+    pub priority: Priority,
+    pub trace: Option<TraceContext>,
 }
 
 impl SignedMessage {
@@ -49,19 +106,48 @@ impl SignedMessage {
             name,
             role,
             payload,
+            correlation: None,
+            priority: Priority::Data,
+            trace: None,
         }
     }
 }
 
+/// Bus 投递消息后的回执
+#[derive(Debug)]
+pub enum Receipt {
+    /// 已放入目标的接收队列, 附带目标地址, 用于之后检查目标是否还活着
+    Delivered(mpsc::Sender<SignedMessage>),
+    /// 没有找到目标服务
+    NotFound,
+    /// 目标服务的接收端已关闭
+    Closed,
+    /// 目标服务的接收队列和暂存队列都已满
+    Full,
+}
+
+/// Bus 无法投递消息时回报给发送方
+#[derive(Debug, Clone)]
+pub struct DeliveryFailure {
+    pub target: String,
+    pub correlation: Option<Uuid>,
+    pub reason: String,
+}
+
 /// 服务消息类型 trait，用于定义服务对应的消息类型
 pub trait AnyMessage: Send + Sync + Any + Debug {
     fn as_any(self: Box<Self>) -> Box<dyn Any>;
+    /// 消息的具体类型名, 对 Box<dyn AnyMessage> 需先解引用再调用
+    fn type_name(&self) -> &'static str;
 }
 
 impl<T: Any + Send + Sync + Debug> AnyMessage for T {
     fn as_any(self: Box<Self>) -> Box<dyn Any> {
         self
     }
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
 }
 
 pub fn downcast<T: Any>(msg: Box<dyn AnyMessage>) -> Result<T> {
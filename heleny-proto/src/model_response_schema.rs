@@ -7,6 +7,8 @@ use serde::Serialize;
 use serde::de::DeserializeOwned;
 use serde_json::Value;
 
+use crate::MemoryEntry;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct HelenyReply {
     pub content: String,
@@ -28,6 +30,47 @@ pub struct ToolIntent {
     pub args: HashMap<String, Value>,
 }
 
+/// 模型发起的一次原生工具调用
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ToolCall {
+    pub id: String,
+    /// 对应 ToolFunction::name
+    pub name: String,
+    #[serde(default)]
+    pub args: HashMap<String, Value>,
+    /// 部分后端要求随调用原样传回的签名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// 原生工具调用模式下模型的回复
+#[derive(Debug, Clone)]
+pub enum ToolReply {
+    /// 不再调用工具, 内容是对任务结果的说明
+    Text(String),
+    /// text 是随调用一起给出的说明, 可能为空
+    Calls {
+        text: Option<String>,
+        calls: Vec<ToolCall>,
+    },
+}
+
+/// 原生工具调用模式下的一条上下文
+#[derive(Debug, Clone)]
+pub enum ToolMessage {
+    Entry(MemoryEntry),
+    Calls {
+        text: Option<String>,
+        calls: Vec<ToolCall>,
+    },
+    /// 工具调用的结果, name 为被调用的函数名
+    Result {
+        call_id: String,
+        name: String,
+        content: String,
+    },
+}
+
 pub fn get_tool_arg<T: DeserializeOwned>(
     args: &mut HashMap<String, Value>,
     name: &str,
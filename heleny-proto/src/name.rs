@@ -1,4 +1,5 @@
 pub static KERNEL_NAME: &'static str = "Kernel";
+pub static BUS_NAME: &'static str = "Bus";
 pub static KERNEL_SERVICE: &'static str = "KernelService";
 pub static CONFIG_SERVICE: &'static str = "ConfigService";
 pub static HUB_SERVICE: &'static str = "HubService";
@@ -12,5 +13,7 @@ pub static WEBUI_SERVICE: &'static str = "WebuiService";
 pub static SCHEDULE_SERVICE: &'static str = "ScheduleService";
 pub static MCP_SERVICE: &'static str = "McpService";
 pub static EMBED_SERVICE: &'static str = "EmbedService";
+pub static STATS_SERVICE: &'static str = "StatsService";
 
-pub static CONFIG_STORAGE_DIR: &'static str = "storage_dir";
\ No newline at end of file
+pub static CONFIG_STORAGE_DIR: &'static str = "storage_dir";
+pub static CONFIG_SERVICES: &'static str = "services";
\ No newline at end of file
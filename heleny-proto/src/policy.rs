@@ -0,0 +1,112 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::AnyMessage;
+use crate::ServiceRole;
+
+/// Bus 的访问控制策略, 按顺序匹配规则, 第一条命中的规则生效
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BusPolicy {
+    /// 没有规则命中时的处理
+    #[serde(default)]
+    pub default: PolicyAction,
+    #[serde(default)]
+    pub rules: Vec<PolicyRule>,
+}
+
+/// 一条策略规则, 未填写的字段匹配任意值
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyRule {
+    /// 来源服务名
+    #[serde(default)]
+    pub source: Option<String>,
+    /// 来源身份
+    #[serde(default)]
+    pub role: Option<ServiceRole>,
+    /// 目标服务名
+    #[serde(default)]
+    pub target: Option<String>,
+    /// 消息类型, 如 MemoryServiceMessage 或 MemoryServiceMessage::Delete
+    #[serde(default)]
+    pub message: Option<String>,
+    pub action: PolicyAction,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum PolicyAction {
+    #[default]
+    Allow,
+    Deny,
+}
+
+impl BusPolicy {
+    /// 返回命中的规则序号和处理方式, 没有命中时返回 None 和默认处理
+    pub fn check(
+        &self,
+        source: &str,
+        role: ServiceRole,
+        target: &str,
+        payload: &dyn AnyMessage,
+    ) -> (Option<usize>, PolicyAction) {
+        let message = short_type_name(payload.type_name());
+        // 变体名需要格式化整条消息, 只在有规则用到时才计算
+        let mut variant = None;
+        for (index, rule) in self.rules.iter().enumerate() {
+            if rule.source.as_ref().is_some_and(|s| s != source)
+                || rule.role.is_some_and(|r| r != role)
+                || rule.target.as_ref().is_some_and(|t| t != target)
+            {
+                continue;
+            }
+            if let Some(pattern) = &rule.message {
+                let matched = match pattern.split_once("::") {
+                    Some((pattern_message, pattern_variant)) => {
+                        pattern_message == message
+                            && variant.get_or_insert_with(|| {
+                                debug_variant_name(&format!("{:?}", payload))
+                            }) == pattern_variant
+                    }
+                    None => pattern == message,
+                };
+                if !matched {
+                    continue;
+                }
+            }
+            return (Some(index), rule.action);
+        }
+        (None, self.default)
+    }
+}
+
+/// 去掉类型名的路径和泛型参数
+pub fn short_type_name(name: &str) -> &str {
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// 从 Debug 输出中取出枚举变体名
+pub fn debug_variant_name(debug: &str) -> String {
+    debug
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_')
+        .collect()
+}
+
+/// 只格式化到变体名结束, 避免为大消息生成完整的 Debug 输出
+pub fn variant_name<T: std::fmt::Debug + ?Sized>(value: &T) -> String {
+    struct VariantWriter(String);
+    impl std::fmt::Write for VariantWriter {
+        fn write_str(&mut self, s: &str) -> std::fmt::Result {
+            for c in s.chars() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    return Err(std::fmt::Error);
+                }
+                self.0.push(c);
+            }
+            Ok(())
+        }
+    }
+    let mut writer = VariantWriter(String::new());
+    let _ = std::fmt::write(&mut writer, format_args!("{:?}", value));
+    writer.0
+}
@@ -8,9 +8,11 @@ use serde::Serialize;
 use uuid::Uuid;
 
 use crate::KernelHealth;
+use crate::LlmUsage;
 use crate::ScheduledTask;
 use crate::TaskAbstract;
 use crate::ToolAbstract;
+use crate::TrafficMatrix;
 use crate::memory::MemoryEntry;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,14 +36,23 @@ pub static HEALTH: &'static str = "Health";
 pub static TASK_ABSTRACT: &'static str = "TaskAbstract";
 pub static SCHEDULE: &'static str = "Schedule";
 pub static TOOL_ABSTRACTS: &'static str = "ToolAbstracts";
+pub static DEAD_LETTERS: &'static str = "DeadLetters";
+pub static TRAFFIC_MATRIX: &'static str = "TrafficMatrix";
+pub static LLM_USAGE: &'static str = "LlmUsage";
+
+/// 正在生成的回复在 DisplayMessages 中使用的临时 id, 不会与数据库 id 冲突
+pub const STREAMING_MESSAGE_ID: i64 = -1;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ResourcePayload {
     Health(KernelHealth),
     TotalBusTraffic(VecDeque<(DateTime<Local>, usize)>),
+    /// streaming 为 true 时 messages 是正在生成的回复, 替换上一条临时消息, 为空表示撤下
     DisplayMessages {
         new: bool,
         messages: Vec<MemoryEntry>,
+        #[serde(default)]
+        streaming: bool,
     },
     Image {
         id: i64,
@@ -60,4 +71,22 @@ pub enum ResourcePayload {
     ToolAbstracts {
         abstracts: Vec<ToolAbstract>,
     },
+    DeadLetters {
+        letters: VecDeque<DeadLetter>,
+    },
+    TrafficMatrix(TrafficMatrix),
+    /// 各模型调用的 token 用量和花费, 按天汇总
+    LlmUsage(LlmUsage),
+}
+
+/// 无法投递的消息, 只保留元数据
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub time: DateTime<Local>,
+    pub source: String,
+    pub target: String,
+    /// 消息类型名
+    pub message: String,
+    pub correlation: Option<Uuid>,
+    pub reason: String,
 }
@@ -1,5 +1,8 @@
+use serde::Deserialize;
+use serde::Serialize;
+
 /// 身份
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum ServiceRole {
     Standard,
     System,
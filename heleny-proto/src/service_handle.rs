@@ -19,6 +19,11 @@ impl ServiceHandle {
         self.thread_handle.abort();
     }
 
+    /// 任务是否已经结束, 包括正常返回和 panic
+    pub fn is_finished(&self) -> bool {
+        self.thread_handle.is_finished()
+    }
+
     pub fn name(&self) -> String {
         self.service_name.clone()
     }
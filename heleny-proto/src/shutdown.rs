@@ -0,0 +1,38 @@
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt::Display;
+
+/// 关机时各服务的退出情况
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShutdownReport {
+    /// 收到 Stop 后正常退出, 执行了 Service::stop
+    pub stopped: Vec<String>,
+    /// 超过关闭时限仍未退出, 被强制终止
+    pub timed_out: Vec<String>,
+    /// 关闭时已不健康, 直接终止
+    pub aborted: Vec<String>,
+    pub elapsed_ms: u64,
+}
+
+impl ShutdownReport {
+    /// 所有服务都正常退出
+    pub fn is_clean(&self) -> bool {
+        self.timed_out.is_empty() && self.aborted.is_empty()
+    }
+}
+
+impl Display for ShutdownReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "关机耗时 {} ms, 正常退出 {} 个: {:?}, 超时 {} 个: {:?}, 强制终止 {} 个: {:?}",
+            self.elapsed_ms,
+            self.stopped.len(),
+            self.stopped,
+            self.timed_out.len(),
+            self.timed_out,
+            self.aborted.len(),
+            self.aborted
+        )
+    }
+}
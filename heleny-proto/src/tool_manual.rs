@@ -1,8 +1,11 @@
 use std::collections::HashMap;
+use std::collections::HashSet;
 
 use serde::Deserialize;
 use serde::Serialize;
+use serde_json::Map;
 use serde_json::Value;
+use serde_json::json;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ToolManual {
@@ -41,6 +44,120 @@ impl ToolManual {
             description: self.description.clone(),
         }
     }
+
+    /// 每个命令转成一个原生工具调用的函数
+    pub fn get_functions(&self) -> Vec<ToolFunction> {
+        self.commands
+            .iter()
+            .map(|command| command.to_function(&self.name))
+            .collect()
+    }
+}
+
+/// 原生工具调用的函数定义, 对应某个工具的一个命令
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ToolFunction {
+    /// 交给模型的函数名, 只含字母, 数字, _ 和 -
+    pub name: String,
+    pub tool: String,
+    pub command: String,
+    pub description: String,
+    /// 参数的 JSON Schema
+    pub parameters: Value,
+}
+
+/// 组成函数名时工具名与命令名之间的分隔
+pub const TOOL_FUNCTION_SEPARATOR: &str = "__";
+
+/// 函数名最长 64 个字符
+const MAX_FUNCTION_NAME: usize = 64;
+
+impl ToolCommand {
+    fn to_function(&self, tool: &str) -> ToolFunction {
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        for arg in &self.args {
+            properties.insert(arg.name.clone(), arg.to_schema());
+            if arg.required {
+                required.push(Value::String(arg.name.clone()));
+            }
+        }
+        let name: String = format!("{}{}{}", tool, TOOL_FUNCTION_SEPARATOR, self.name)
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .take(MAX_FUNCTION_NAME)
+            .collect();
+        ToolFunction {
+            name,
+            tool: tool.to_string(),
+            command: self.name.clone(),
+            description: self.description.clone(),
+            parameters: json!({
+                "type": "object",
+                "properties": properties,
+                "required": required,
+            }),
+        }
+    }
+}
+
+/// 清洗后重名的函数依次加上 _2, _3 等后缀, 返回改名记录
+pub fn dedup_function_names(functions: &mut [ToolFunction]) -> Vec<(String, String)> {
+    let mut used = HashSet::new();
+    let mut renamed = Vec::new();
+    for function in functions.iter_mut() {
+        if used.insert(function.name.clone()) {
+            continue;
+        }
+        let name = (2..)
+            .map(|index| {
+                let suffix = format!("_{}", index);
+                let base: String = function
+                    .name
+                    .chars()
+                    .take(MAX_FUNCTION_NAME - suffix.len())
+                    .collect();
+                base + &suffix
+            })
+            .find(|name| !used.contains(name))
+            .expect("后缀不会用尽");
+        used.insert(name.clone());
+        renamed.push((std::mem::replace(&mut function.name, name.clone()), name));
+    }
+    renamed
+}
+
+impl ToolArgument {
+    /// 未知类型不加限制, 默认值写进描述
+    fn to_schema(&self) -> Value {
+        let mut schema = Map::new();
+        let arg_type = match self.arg_type.as_str() {
+            "bool" | "boolean" => Some("boolean"),
+            "int" | "integer" => Some("integer"),
+            "float" | "number" => Some("number"),
+            "string" => Some("string"),
+            "array" => Some("array"),
+            "object" => Some("object"),
+            _ => None,
+        };
+        if let Some(arg_type) = arg_type {
+            schema.insert("type".into(), arg_type.into());
+        }
+        let description = match &self.default {
+            Some(default) if !default.is_null() => {
+                format!("{} (默认: {})", self.description, default)
+            }
+            _ => self.description.clone(),
+        };
+        schema.insert("description".into(), description.into());
+        Value::Object(schema)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -79,3 +196,60 @@ impl From<ToolManual> for ToolAbstract {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_functions_builds_schema() {
+        let manual: ToolManual = serde_json::from_value(json!({
+            "name": "sandbox",
+            "description": "沙箱",
+            "commands": [{
+                "name": "shell.exec",
+                "description": "执行 shell 命令",
+                "args": [
+                    { "name": "command", "description": "命令", "type": "string", "required": true, "default": null },
+                    { "name": "timeout", "description": "超时", "type": "integer", "required": false, "default": 30 },
+                    { "name": "extra", "description": "其他", "type": "", "required": false, "default": null }
+                ]
+            }]
+        }))
+        .unwrap();
+        let functions = manual.get_functions();
+        assert_eq!(functions.len(), 1);
+        let function = &functions[0];
+        assert_eq!(function.name, "sandbox__shell_exec");
+        assert_eq!(function.tool, "sandbox");
+        assert_eq!(function.command, "shell.exec");
+        assert_eq!(function.parameters["required"], json!(["command"]));
+        let properties = &function.parameters["properties"];
+        assert_eq!(properties["timeout"]["type"], "integer");
+        assert_eq!(properties["timeout"]["description"], "超时 (默认: 30)");
+        assert!(properties["extra"].get("type").is_none());
+    }
+
+    #[test]
+    fn dedup_function_names_adds_suffix() {
+        let manual: ToolManual = serde_json::from_value(json!({
+            "name": "sandbox",
+            "description": "沙箱",
+            "commands": [
+                { "name": "shell.exec", "description": "", "args": [] },
+                { "name": "shell_exec", "description": "", "args": [] },
+                { "name": "shell exec", "description": "", "args": [] }
+            ]
+        }))
+        .unwrap();
+        let mut functions = manual.get_functions();
+        let renamed = dedup_function_names(&mut functions);
+        assert_eq!(renamed.len(), 2);
+        let names: Vec<&str> = functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["sandbox__shell_exec", "sandbox__shell_exec_2", "sandbox__shell_exec_3"]
+        );
+        assert_eq!(functions[2].command, "shell exec");
+    }
+}
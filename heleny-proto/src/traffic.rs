@@ -0,0 +1,154 @@
+use chrono::DateTime;
+use chrono::Local;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::ServiceRole;
+use crate::SignedMessage;
+use crate::debug_variant_name;
+use crate::short_type_name;
+
+/// 一条经过 Bus 的消息记录, 用于排查问题和回放
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrafficRecord {
+    pub time: DateTime<Local>,
+    pub source: String,
+    pub target: String,
+    pub role: ServiceRole,
+    /// 消息类型名
+    pub message: String,
+    /// 枚举变体名
+    pub variant: String,
+    /// 消息的 Debug 输出
+    pub payload: String,
+    pub correlation: Option<Uuid>,
+    pub trace_id: Option<Uuid>,
+}
+
+impl TrafficRecord {
+    pub fn new(msg: &SignedMessage) -> Self {
+        Self::truncated(msg, usize::MAX)
+    }
+
+    /// payload 只保留前 `limit` 个字符, 超出的部分不会被格式化
+    pub fn truncated(msg: &SignedMessage, limit: usize) -> Self {
+        let mut payload = Truncated {
+            text: String::new(),
+            left: limit,
+        };
+        if write!(payload, "{:?}", msg.payload).is_err() {
+            payload.text.push_str("...");
+        }
+        let payload = payload.text;
+        Self {
+            time: Local::now(),
+            source: msg.name.clone(),
+            target: msg.target.clone(),
+            role: msg.role,
+            message: short_type_name(msg.payload.as_ref().type_name()).to_string(),
+            variant: debug_variant_name(&payload),
+            payload,
+            correlation: msg.correlation,
+            trace_id: msg.trace.map(|trace| trace.trace_id),
+        }
+    }
+}
+
+/// 写满后拒绝继续写入, 让 Debug 格式化提前结束
+struct Truncated {
+    text: String,
+    left: usize,
+}
+
+impl fmt::Write for Truncated {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.left == 0 {
+                return Err(fmt::Error);
+            }
+            self.text.push(c);
+            self.left -= 1;
+        }
+        Ok(())
+    }
+}
+
+/// Bus 每投递一条消息发给 StatsService 的事件
+#[derive(Debug, Clone)]
+pub struct RouteEvent {
+    pub source: String,
+    pub target: String,
+    /// 投递前目标收件箱里排队的消息数
+    pub queue_depth: usize,
+}
+
+/// 处理耗时直方图各桶的上界(毫秒), 超过最后一个的计入溢出桶
+pub const LATENCY_BUCKETS_MS: [u64; 7] = [1, 5, 10, 50, 100, 500, 1000];
+
+/// 服务处理消息的耗时直方图
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyHistogram {
+    pub buckets: Vec<usize>,
+    pub count: usize,
+    pub total_micros: u64,
+    pub max_micros: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; LATENCY_BUCKETS_MS.len() + 1],
+            count: 0,
+            total_micros: 0,
+            max_micros: 0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, elapsed: Duration) {
+        let micros = elapsed.as_micros() as u64;
+        let index = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| micros <= bound * 1000)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.buckets[index] += 1;
+        self.count += 1;
+        self.total_micros += micros;
+        self.max_micros = self.max_micros.max(micros);
+    }
+
+    /// 平均耗时(毫秒)
+    pub fn mean_ms(&self) -> f64 {
+        match self.count {
+            0 => 0.,
+            count => self.total_micros as f64 / count as f64 / 1000.,
+        }
+    }
+}
+
+/// 各服务的处理耗时, 由 Endpoint 写入, StatsService 读取
+pub type LatencyTable = Arc<Mutex<HashMap<String, LatencyHistogram>>>;
+
+/// 单个服务的负载
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServiceTraffic {
+    /// 最近一次投递前的收件箱长度
+    pub queue_depth: usize,
+    pub latency: LatencyHistogram,
+}
+
+/// 按路由统计的流量和各服务负载
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrafficMatrix {
+    /// 来源 -> 目标 -> 消息数
+    pub routes: HashMap<String, HashMap<String, usize>>,
+    pub services: HashMap<String, ServiceTraffic>,
+}
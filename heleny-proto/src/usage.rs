@@ -0,0 +1,176 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::future::Future;
+
+use chrono::DateTime;
+use chrono::Local;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// 一次模型调用的 token 用量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+tokio::task_local! {
+    static REPORTED_USAGE: Cell<Option<TokenUsage>>;
+}
+
+/// 后端拿到服务端返回的用量时调用, 同一次调用内多次上报会累加, 不在 with_usage 内时忽略
+pub fn report_usage(usage: TokenUsage) {
+    let _ = REPORTED_USAGE.try_with(|reported| {
+        let mut total = reported.get().unwrap_or_default();
+        total.prompt_tokens += usage.prompt_tokens;
+        total.completion_tokens += usage.completion_tokens;
+        reported.set(Some(total));
+    });
+}
+
+/// 执行 future, 一并返回其中后端上报的用量
+pub async fn with_usage<F: Future>(future: F) -> (F::Output, Option<TokenUsage>) {
+    REPORTED_USAGE
+        .scope(Cell::new(None), async {
+            let output = future.await;
+            (output, REPORTED_USAGE.with(Cell::get))
+        })
+        .await
+}
+
+/// 一次成功的模型调用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub time: DateTime<Local>,
+    /// heleny / planner / executor / embed
+    pub role: String,
+    pub model: String,
+    pub task: Option<Uuid>,
+    pub usage: TokenUsage,
+    /// 后端没有返回用量, 按文本估算
+    pub estimated: bool,
+    pub latency_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageTotals {
+    pub calls: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub latency_ms: u64,
+    /// 按价格表计算, 没有配置价格的模型不计
+    pub cost: f64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, record: &UsageRecord, cost: f64) {
+        self.calls += 1;
+        self.prompt_tokens += record.usage.prompt_tokens;
+        self.completion_tokens += record.usage.completion_tokens;
+        self.latency_ms += record.latency_ms;
+        self.cost += cost;
+    }
+
+    pub fn tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// 一天的用量, 按角色, 模型和任务分别汇总
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DailyUsage {
+    pub total: UsageTotals,
+    pub roles: BTreeMap<String, UsageTotals>,
+    pub models: BTreeMap<String, UsageTotals>,
+    pub tasks: HashMap<Uuid, UsageTotals>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LlmUsage {
+    pub days: BTreeMap<NaiveDate, DailyUsage>,
+}
+
+impl LlmUsage {
+    pub fn record(&mut self, record: &UsageRecord, cost: f64) {
+        let day = self.days.entry(record.time.date_naive()).or_default();
+        day.total.add(record, cost);
+        day.roles
+            .entry(record.role.clone())
+            .or_default()
+            .add(record, cost);
+        day.models
+            .entry(record.model.clone())
+            .or_default()
+            .add(record, cost);
+        if let Some(task) = record.task {
+            day.tasks.entry(task).or_default().add(record, cost);
+        }
+    }
+
+    pub fn today(&self) -> Option<&DailyUsage> {
+        self.days.get(&Local::now().date_naive())
+    }
+
+    /// 只保留最近 keep_days 天
+    pub fn prune(&mut self, keep_days: usize) {
+        while self.days.len() > keep_days {
+            self.days.pop_first();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn usage_is_reported_and_aggregated() {
+        let (output, usage) = with_usage(async {
+            report_usage(TokenUsage {
+                prompt_tokens: 10,
+                completion_tokens: 2,
+            });
+            report_usage(TokenUsage {
+                prompt_tokens: 5,
+                completion_tokens: 1,
+            });
+            "ok"
+        })
+        .await;
+        assert_eq!(output, "ok");
+        assert_eq!(
+            usage,
+            Some(TokenUsage {
+                prompt_tokens: 15,
+                completion_tokens: 3,
+            })
+        );
+        assert_eq!(with_usage(async {}).await.1, None);
+
+        let task = Uuid::new_v4();
+        let mut llm_usage = LlmUsage::default();
+        for role in ["executor", "executor", "heleny"] {
+            llm_usage.record(
+                &UsageRecord {
+                    time: Local::now(),
+                    role: role.into(),
+                    model: "grok".into(),
+                    task: (role == "executor").then_some(task),
+                    usage: usage.unwrap(),
+                    estimated: false,
+                    latency_ms: 100,
+                },
+                0.5,
+            );
+        }
+        let today = llm_usage.today().unwrap();
+        assert_eq!(today.total.calls, 3);
+        assert_eq!(today.total.tokens(), 54);
+        assert_eq!(today.roles["executor"].calls, 2);
+        assert_eq!(today.tasks[&task].prompt_tokens, 30);
+        assert_eq!(today.models["grok"].cost, 1.5);
+    }
+}
@@ -5,6 +5,7 @@ use tokio;
 use tracing::error;
 use tracing::info;
 use tracing::info_span;
+use tracing::warn;
 
 #[tokio::main]
 async fn main() {
@@ -25,4 +26,9 @@ async fn main() {
     };
     info!("Heleny 内核启动成功, 开始运行...");
     kernel.run().await;
+    match kernel.shutdown_report() {
+        Some(report) if report.is_clean() => info!("Heleny 已正常关闭"),
+        Some(report) => warn!("Heleny 关闭时有服务未正常退出: {}", report),
+        None => warn!("Heleny 未走完关机流程"),
+    }
 }
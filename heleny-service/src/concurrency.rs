@@ -0,0 +1,160 @@
+use anyhow::Result;
+use heleny_bus::endpoint::Endpoint;
+use heleny_bus::trace;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+use tokio::time::sleep;
+use tracing::warn;
+
+use crate::Watchdog;
+
+/// 在独立任务中运行的消息处理, 只能持有从服务中克隆出的状态
+pub type ConcurrentHandler = Pin<Box<dyn Future<Output = Result<()>> + Send + 'static>>;
+
+/// 并发模式下一条消息的去向
+pub enum Dispatch<M> {
+    /// 在服务循环内按到达顺序处理
+    Serial(M),
+    /// 在独立任务中处理, 不阻塞后续消息
+    Concurrent(ConcurrentHandler),
+}
+
+/// 由 base_service 的 concurrency 参数生成, 为 0 时不启用并发模式
+pub trait HasConcurrency {
+    fn concurrency() -> usize;
+}
+
+/// 同时处理的并发消息数的上限
+pub struct ConcurrencyLimit {
+    permits: Arc<Semaphore>,
+    endpoint: Endpoint,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(limit: usize, endpoint: &Endpoint) -> Option<Self> {
+        if limit == 0 {
+            return None;
+        }
+        Some(Self {
+            permits: Arc::new(Semaphore::new(limit)),
+            endpoint: endpoint.create_sender_endpoint(),
+        })
+    }
+
+    /// 在独立任务中等到有空余名额后开始处理, 不阻塞服务循环里的串行消息和控制消息
+    pub fn spawn(
+        &self,
+        name: &'static str,
+        label: String,
+        handling: ConcurrentHandler,
+        watchdog: &Watchdog,
+    ) {
+        let permits = self.permits.clone();
+        let endpoint = self.endpoint.create_sender_endpoint();
+        let deadline = watchdog.deadline;
+        tokio::spawn(trace::propagate(async move {
+            let Ok(_permit) = permits.acquire_owned().await else {
+                warn!("并发名额已关闭, 丢弃 {}", label);
+                return;
+            };
+            let start = Instant::now();
+            let result = watch_concurrent(&label, handling, deadline).await;
+            endpoint.record_latency(name, start.elapsed());
+            if let Err(e) = result {
+                warn!("并发处理 {} 时出错: {}", label, e);
+            }
+        }));
+    }
+}
+
+/// 并发任务卡住不影响服务循环, 只记录日志
+async fn watch_concurrent(
+    label: &str,
+    mut handling: ConcurrentHandler,
+    deadline: Duration,
+) -> Result<()> {
+    tokio::select! {
+        result = &mut handling => return result,
+        _ = sleep(deadline) => {}
+    }
+    warn!("并发处理 {} 超过 {:?} 仍未完成", label, deadline);
+    let start = Instant::now();
+    let result = handling.await;
+    warn!("{} 处理完成, 超时后又耗时 {:?}", label, start.elapsed());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heleny_bus::BusHandle;
+    use heleny_proto::ServiceRole;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use tokio::sync::mpsc;
+
+    async fn endpoint(bus: &mut BusHandle) -> Endpoint {
+        bus.get_endpoint("TestService".into(), 32, ServiceRole::Standard)
+            .await
+            .expect("获取 TestService 失败")
+    }
+
+    #[tokio::test]
+    async fn test_zero_disables_concurrency() {
+        let mut bus = BusHandle::new(32);
+        let endpoint = endpoint(&mut bus).await;
+        assert!(ConcurrencyLimit::new(0, &endpoint).is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_permit_limit() {
+        let mut bus = BusHandle::new(32);
+        let endpoint = endpoint(&mut bus).await;
+        let limit = ConcurrencyLimit::new(2, &endpoint).expect("应当启用并发模式");
+        let watchdog = Watchdog::new(&endpoint);
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (done_tx, mut done_rx) = mpsc::channel(8);
+        let start = Instant::now();
+        for i in 0..5 {
+            let running = running.clone();
+            let peak = peak.clone();
+            let done_tx = done_tx.clone();
+            let handling: ConcurrentHandler = Box::pin(async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                sleep(Duration::from_millis(100)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                let _ = done_tx.send(i).await;
+                Ok(())
+            });
+            limit.spawn("TestService", format!("Job{}", i), handling, &watchdog);
+        }
+        drop(done_tx);
+        let mut done = Vec::new();
+        while let Some(i) = done_rx.recv().await {
+            done.push(i);
+        }
+        assert_eq!(done.len(), 5);
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        // 5 条消息每次最多处理 2 条, 需要 3 轮
+        assert_eq!(start.elapsed(), Duration::from_millis(300));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_overdue_handler_still_finishes() {
+        let result = watch_concurrent(
+            "Slow",
+            Box::pin(async {
+                sleep(Duration::from_secs(5)).await;
+                Err(anyhow::anyhow!("完成"))
+            }),
+            Duration::from_secs(1),
+        )
+        .await;
+        assert_eq!(result.unwrap_err().to_string(), "完成");
+    }
+}
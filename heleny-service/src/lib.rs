@@ -1,19 +1,24 @@
+use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Context;
 use anyhow::Result;
 use async_trait::async_trait;
 use heleny_bus::endpoint::Endpoint;
+use heleny_bus::lane::BusReceiver;
+use heleny_bus::trace;
 use heleny_proto::AnyMessage;
+use heleny_proto::DeliveryFailure;
 use heleny_proto::KERNEL_NAME;
 use heleny_proto::KERNEL_SERVICE;
 use heleny_proto::Resource;
 use heleny_proto::ServiceHandle;
 use heleny_proto::ServiceRole;
 use heleny_proto::SignedMessage;
+use heleny_proto::short_type_name;
+use heleny_proto::variant_name;
 use rand::Rng;
 use tokio::sync::mpsc;
-use tokio::sync::oneshot;
 use tokio::time::Instant;
 use tokio::time::Interval;
 use tokio::time::MissedTickBehavior;
@@ -25,12 +30,16 @@ use tracing::warn;
 
 mod utils;
 pub use utils::*;
+mod watchdog;
+pub use watchdog::*;
+mod concurrency;
+pub use concurrency::*;
 mod messages;
 pub use messages::*;
 
 /// 服务 trait，定义了服务的基本行为
 #[async_trait]
-pub trait Service: 'static + HasEndpoint + HasName + Send {
+pub trait Service: 'static + HasEndpoint + HasName + HasConcurrency + HasTick + Send {
     // 需要实现
     type MessageType: AnyMessage + Send + Sync;
     async fn new(endpoint: Endpoint) -> Result<Box<Self>>;
@@ -45,6 +54,23 @@ pub trait Service: 'static + HasEndpoint + HasName + Send {
     async fn handle_tick(&mut self, tick: Instant) -> Result<()>;
     async fn handle_resource(&mut self, resource: Resource) -> Result<()>;
     // 默认实现
+    /// 并发模式下决定消息是否在独立任务中处理, 默认全部按顺序处理
+    fn dispatch(
+        &self,
+        _name: &str,
+        _role: &ServiceRole,
+        msg: Self::MessageType,
+    ) -> Dispatch<Self::MessageType> {
+        Dispatch::Serial(msg)
+    }
+    /// 通知 KernelService 初始化完成后调用
+    async fn on_ready(&mut self) -> Result<()> {
+        Ok(())
+    }
+    /// 依赖的服务重启后调用, 可在此重新注册或订阅
+    async fn on_dependency_restarted(&mut self, _name: &str) -> Result<()> {
+        Ok(())
+    }
     fn start(endpoint: Endpoint) -> Result<ServiceHandle> {
         let span = info_span!("", Name = %Self::name());
         let handle = tokio::spawn(
@@ -61,11 +87,20 @@ pub trait Service: 'static + HasEndpoint + HasName + Send {
                     }
                 };
                 let (from_bus, from_sub_endpoint) = service.endpoint_mut().get_rx()?;
-                let mut tick_interval = interval_at(Instant::now().checked_add(Duration::from_millis(rand::thread_rng().gen_range(100..900))).context("获取间隔失败")?,Duration::from_secs(1));
-                tick_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                // 心跳与 handle_tick 分开, 不需要 tick 的服务也要按时上报
+                let mut heartbeat = interval_at(Instant::now().checked_add(Duration::from_millis(rand::thread_rng().gen_range(100..900))).context("获取间隔失败")?,HEARTBEAT_INTERVAL);
+                heartbeat.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                let tick_interval = Self::tick_interval().map(|period| {
+                    let mut tick_interval = interval_at(Instant::now() + period, period);
+                    tick_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                    tick_interval
+                });
                 service.send_ready().await; // 通知 KernelService 自己初始化完成
+                if let Err(e) = service.on_ready().await {
+                    warn!("执行 on_ready 错误: {}", e)
+                }
                 service
-                    .launch(from_bus, from_sub_endpoint, tick_interval)
+                    .launch(from_bus, from_sub_endpoint, heartbeat, tick_interval)
                     .await; // 启动循环
                 Ok(())
             }
@@ -76,24 +111,29 @@ pub trait Service: 'static + HasEndpoint + HasName + Send {
     /// 控制 tokio::select! 的 loop 循环
     async fn launch(
         &mut self,
-        mut from_bus: mpsc::Receiver<SignedMessage>,
+        mut from_bus: BusReceiver,
         mut from_sub_endpoint: mpsc::Receiver<Box<dyn AnyMessage>>,
-        mut tick_interval: Interval,
+        mut heartbeat: Interval,
+        mut tick_interval: Option<Interval>,
     ) {
         let mut run = true;
+        let mut watchdog = Watchdog::new(self.endpoint());
+        let limit = ConcurrencyLimit::new(Self::concurrency(), self.endpoint());
 
         while run {
             tokio::select! {
                 Some(msg) = from_bus.recv()=>{
-                    self.handle_msg(msg, &mut run).await;
+                    self.handle_msg(msg, &mut run, &mut watchdog, &limit).await;
                 }
                 Some(msg) = from_sub_endpoint.recv()=>{
                     if let Err(e) = self.handle_sub_endpoint(msg).await{
                         warn!("处理 Sub Endpoint 消息错误: {}",e)
                     };
                 }
-                tick = tick_interval.tick()=>{
+                _ = heartbeat.tick()=>{
                     self.send_alive().await;
+                }
+                Some(tick) = next_tick(&mut tick_interval)=>{
                     if let Err(e) = self.handle_tick(tick).await{
                         warn!("处理 Tick 错误: {}",e)
                     };
@@ -101,8 +141,28 @@ pub trait Service: 'static + HasEndpoint + HasName + Send {
             }
         }
     }
-    /// 处理收到的所有信息
-    async fn handle_msg(&mut self, msg: SignedMessage, run: &mut bool) {
+    /// 处理收到的所有信息, 在发送方的 trace 中进行
+    async fn handle_msg(
+        &mut self,
+        msg: SignedMessage,
+        run: &mut bool,
+        watchdog: &mut Watchdog,
+        limit: &Option<ConcurrencyLimit>,
+    ) {
+        match msg.trace {
+            Some(context) => {
+                trace::scope(context, self.handle_traced_msg(msg, run, watchdog, limit)).await
+            }
+            None => self.handle_traced_msg(msg, run, watchdog, limit).await,
+        }
+    }
+    async fn handle_traced_msg(
+        &mut self,
+        msg: SignedMessage,
+        run: &mut bool,
+        watchdog: &mut Watchdog,
+        limit: &Option<ConcurrencyLimit>,
+    ) {
         let payload = match Self::downcast(msg.payload) {
             Ok(payload) => payload,
             Err(e) => {
@@ -112,12 +172,38 @@ pub trait Service: 'static + HasEndpoint + HasName + Send {
         };
         match payload {
             Ok(message) => {
-                if let Err(e) = self.handle(msg.name, msg.role, *message).await {
-                    warn!("处理消息时出错: {}", e);
+                let label = format!(
+                    "{}::{}",
+                    short_type_name(std::any::type_name::<Self::MessageType>()),
+                    variant_name(&message)
+                );
+                let message = match limit {
+                    Some(limit) => match self.dispatch(&msg.name, &msg.role, *message) {
+                        Dispatch::Serial(message) => message,
+                        Dispatch::Concurrent(handling) => {
+                            limit.spawn(Self::name(), label, handling, watchdog);
+                            return;
+                        }
+                    },
+                    None => *message,
+                };
+                let start = Instant::now();
+                let result = watchdog
+                    .watch(&label, self.handle(msg.name, msg.role, message))
+                    .await;
+                self.endpoint()
+                    .record_latency(Self::name(), start.elapsed());
+                if let Err(e) = result {
+                    match msg.correlation {
+                        Some(correlation) => {
+                            warn!("处理消息时出错: {} (关联 id: {})", e, correlation)
+                        }
+                        None => warn!("处理消息时出错: {}", e),
+                    }
                 }
             }
             Err(common_message) => {
-                self.handle_common_message(msg.name, msg.role, common_message, run)
+                self.handle_common_message(msg.name, msg.role, common_message, run, watchdog)
                     .await
             }
         };
@@ -129,6 +215,7 @@ pub trait Service: 'static + HasEndpoint + HasName + Send {
         role: ServiceRole,
         message: Box<CommonMessage>,
         run: &mut bool,
+        watchdog: &mut Watchdog,
     ) {
         match *message {
             CommonMessage::Stop => {
@@ -145,6 +232,25 @@ pub trait Service: 'static + HasEndpoint + HasName + Send {
                     warn!("处理资源失败: {}", e)
                 }
             }
+            CommonMessage::DeliveryFailure(failure) => {
+                warn!("发给 {} 的消息投递失败: {}", failure.target, failure.reason)
+            }
+            CommonMessage::HandlerDeadline(deadline) => {
+                if role != ServiceRole::System {
+                    warn!("非 System 身份不能设置处理时限");
+                    return;
+                }
+                watchdog.deadline = deadline;
+            }
+            CommonMessage::DependencyRestarted(name) => {
+                if role != ServiceRole::System {
+                    warn!("非 System 身份不能发送 DependencyRestarted 消息");
+                    return;
+                }
+                if let Err(e) = self.on_dependency_restarted(&name).await {
+                    warn!("处理依赖 {} 重启错误: {}", name, e)
+                }
+            }
         }
     }
     fn downcast(
@@ -155,8 +261,12 @@ pub trait Service: 'static + HasEndpoint + HasName + Send {
             Ok(msg) => return Ok(Ok(msg)),
             Err(msg) => msg,
         };
-        match msg.downcast::<CommonMessage>() {
-            Ok(msg) => Ok(Err(msg)),
+        let msg = match msg.downcast::<CommonMessage>() {
+            Ok(msg) => return Ok(Err(msg)),
+            Err(msg) => msg,
+        };
+        match msg.downcast::<DeliveryFailure>() {
+            Ok(failure) => Ok(Err(Box::new(CommonMessage::DeliveryFailure(*failure)))),
             Err(_) => Err(anyhow::anyhow!(
                 "消息类型转换失败：期望类型为 {} CommonMessage, 但收到的是其他类型",
                 std::any::type_name::<Self::MessageType>()
@@ -166,7 +276,7 @@ pub trait Service: 'static + HasEndpoint + HasName + Send {
     async fn send_alive(&self) {
         let _ = self
             .endpoint()
-            .send(
+            .send_control(
                 KERNEL_SERVICE,
                 KernelServiceMessage::UploadStatus(ServiceSignal::Alive),
             )
@@ -175,7 +285,7 @@ pub trait Service: 'static + HasEndpoint + HasName + Send {
     async fn send_ready(&self) {
         let _ = self
             .endpoint()
-            .send(
+            .send_control(
                 KERNEL_SERVICE,
                 KernelServiceMessage::UploadStatus(ServiceSignal::Ready),
             )
@@ -185,7 +295,7 @@ pub trait Service: 'static + HasEndpoint + HasName + Send {
     async fn send_terminate(&self) {
         let _ = self
             .endpoint()
-            .send(
+            .send_control(
                 KERNEL_SERVICE,
                 KernelServiceMessage::UploadStatus(ServiceSignal::Terminate("".into())),
             )
@@ -193,18 +303,15 @@ pub trait Service: 'static + HasEndpoint + HasName + Send {
     }
 
     async fn get_endpoint_from_kernel(&self, name: &str) -> Result<Endpoint> {
-        let (tx, rx) = oneshot::channel();
-        let _ = self
-            .endpoint()
-            .send(
+        let name = name.to_string();
+        self.endpoint()
+            .call(
                 KERNEL_NAME,
-                AdminCommand::NewEndpoint {
-                    name: name.to_string(),
-                    feedback: tx,
-                },
+                |feedback| AdminCommand::NewEndpoint { name, feedback },
+                CALL_TIMEOUT,
             )
-            .await;
-        rx.await.context("获取 Endpoint 错误")
+            .await
+            .context("获取 Endpoint 错误")
     }
 }
 
@@ -217,16 +324,44 @@ pub trait HasName {
     fn name() -> &'static str;
 }
 
+/// 由 base_service 的 tick 参数生成, None 表示不调用 handle_tick
+pub trait HasTick {
+    fn tick_interval() -> Option<Duration>;
+}
+
+/// 服务向 KernelService 上报 Alive 的间隔
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 没有配置 tick 时永远等待, 让 select! 跳过这个分支
+async fn next_tick(tick_interval: &mut Option<Interval>) -> Option<Instant> {
+    match tick_interval {
+        Some(tick_interval) => Some(tick_interval.tick().await),
+        None => std::future::pending().await,
+    }
+}
+
 pub struct ServiceFactory {
     pub name: &'static str,
     pub deps: &'static [&'static str],
     pub launch: fn(Endpoint) -> Result<ServiceHandle>,
 }
 
+/// 启动服务的函数, 除注册的工厂外也可以是测试中的替身服务
+pub type ServiceLauncher = Arc<dyn Fn(Endpoint) -> Result<ServiceHandle> + Send + Sync>;
+
 pub struct ServiceFactoryVec {
     pub name: &'static str,
     pub deps: Vec<&'static str>,
-    pub launch: fn(Endpoint) -> Result<ServiceHandle>,
+    pub launch: ServiceLauncher,
+}
+
+impl std::fmt::Debug for ServiceFactoryVec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServiceFactoryVec")
+            .field("name", &self.name)
+            .field("deps", &self.deps)
+            .finish_non_exhaustive()
+    }
 }
 
 inventory::collect!(ServiceFactory);
@@ -1,6 +1,8 @@
 use anyhow::Result;
 use heleny_bus::endpoint::Endpoint;
 use heleny_proto::AnyMessage;
+use heleny_proto::BusPolicy;
+use heleny_proto::ShutdownReport;
 use tokio::sync::oneshot;
 
 use crate::KernelMessage;
@@ -17,6 +19,30 @@ pub enum AdminCommand {
         proxy: String,
         feedback: oneshot::Sender<Endpoint>,
     },
+    SetBusPolicy(BusPolicy),
+    /// 服务退出后移除它的路由和 token
+    RemoveEndpoint {
+        name: String,
+    },
+    /// 启动服务, 缺少的依赖会先启动
+    StartService {
+        name: String,
+    },
+    /// 关闭服务, 依赖它的服务会先关闭
+    StopService {
+        name: String,
+    },
+    /// 关闭后重新启动服务及依赖它的服务
+    RestartService {
+        name: String,
+    },
+    /// 服务开始启动或获取 Endpoint 失败, Bus 据此暂存发给启动中服务的消息
+    SetStarting {
+        name: String,
+        starting: bool,
+    },
+    /// 所有服务退出后, KernelService 上报关机情况
+    ShutdownReport(ShutdownReport),
 }
 
 #[derive(Debug)]
@@ -1,7 +1,15 @@
+use heleny_proto::DeliveryFailure;
 use heleny_proto::Resource;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub enum CommonMessage {
     Stop,
     Resource(Resource),
+    /// Bus 回报的投递失败
+    DeliveryFailure(DeliveryFailure),
+    /// KernelService 下发的单条消息处理时限
+    HandlerDeadline(Duration),
+    /// KernelService 通知依赖的服务已重启完成
+    DependencyRestarted(String),
 }
@@ -1,12 +1,38 @@
+use heleny_proto::LatencyTable;
+use heleny_proto::ResourcePayload;
+use heleny_proto::RouteEvent;
 use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+use tokio::sync::watch;
+use uuid::Uuid;
 
 #[derive(Debug)]
 pub enum KernelMessage {
     Shutdown,
     GetBusStatsRx {
-        sender: mpsc::Sender<(String, String)>,
+        sender: mpsc::Sender<RouteEvent>,
     },
+    /// 返回为该用户签发的 token
     SetUser {
         name: String,
+        feedback: oneshot::Sender<Uuid>,
+    },
+    RevokeToken {
+        token: Uuid,
+    },
+    GetDeadLettersRx {
+        sender: oneshot::Sender<watch::Receiver<ResourcePayload>>,
+    },
+    GetLatencyTable {
+        sender: oneshot::Sender<LatencyTable>,
+    },
+    StartService {
+        name: String,
+    },
+    StopService {
+        name: String,
+    },
+    RestartService {
+        name: String,
     },
 }
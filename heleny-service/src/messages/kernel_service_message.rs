@@ -1,18 +1,24 @@
 use anyhow::Result;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::sync::Mutex;
 
 use tokio::sync::oneshot;
 
+use heleny_proto::DependencyGraph;
 use heleny_proto::KernelHealth;
 use heleny_proto::ServiceHandle;
 
+use crate::ServiceFactoryVec;
+
 #[derive(Debug)]
 pub enum ServiceSignal {
     Ready,
     Alive,
     InitFail,
+    /// 服务仍在运行但无法正常工作, 附带原因
+    Unhealthy(String),
     Terminate(String),
 }
 
@@ -21,12 +27,30 @@ pub enum KernelServiceMessage {
     // System
     StopAll(oneshot::Sender<()>),
     Init,
+    /// 只初始化给定的服务及其依赖
+    InitServices(HashSet<String>),
+    StartService {
+        name: String,
+    },
+    StopService {
+        name: String,
+    },
+    RestartService {
+        name: String,
+    },
+    /// 替换或新增服务的工厂, 需在服务启动前发送
+    ReplaceService {
+        factory: ServiceFactoryVec,
+        feedback: oneshot::Sender<Result<()>>,
+    },
     InitParams(
         Arc<Mutex<KernelHealth>>,
         Arc<Mutex<HashMap<String, ServiceHandle>>>,
     ),
     // Standard
     GetHealth(oneshot::Sender<KernelHealth>),
+    /// 依赖图及实时健康状态, 可导出为 JSON 或 DOT
+    GetDependencyGraph(oneshot::Sender<DependencyGraph>),
     UploadStatus(ServiceSignal),
     WaitFor {
         name: String,
@@ -9,6 +9,10 @@ pub enum MemoryServiceMessage {
         role: ChatRole,
         content: MemoryContent
     },
+    /// 展示正在生成的回复但不保存, None 表示撤下
+    Streaming {
+        content: Option<String>
+    },
     Get {
         id_upper_bound: i64,
         feedback: oneshot::Sender<Vec<MemoryEntry>>,
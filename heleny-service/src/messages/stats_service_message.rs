@@ -2,6 +2,7 @@ use std::collections::VecDeque;
 
 use chrono::DateTime;
 use chrono::Local;
+use heleny_proto::UsageRecord;
 use tokio::sync::oneshot;
 
 #[derive(Debug)]
@@ -9,4 +10,12 @@ pub enum StatsServiceMessage {
     GetBusStats {
         sender: oneshot::Sender<VecDeque<(DateTime<Local>, usize)>>,
     },
+    /// 记录一次模型调用的用量
+    RecordUsage {
+        record: UsageRecord,
+    },
+    /// 今日用量超出预算时返回原因
+    CheckBudget {
+        feedback: oneshot::Sender<Option<String>>,
+    },
 }
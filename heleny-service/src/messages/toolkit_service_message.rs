@@ -8,6 +8,8 @@ use heleny_proto::CanRequestConsent;
 use heleny_proto::ConsentRequestion;
 use heleny_proto::HelenyTool;
 use heleny_proto::HelenyToolFactory;
+use heleny_proto::ToolCall;
+use heleny_proto::ToolFunction;
 use heleny_proto::ToolIntent;
 use heleny_proto::USER_SERVICE;
 use tokio::sync::oneshot;
@@ -40,6 +42,7 @@ pub enum ToolkitServiceMessage {
 pub struct Toolkit {
     endpoint: ToolkitEndpoint,
     tool_manuals: String,
+    functions: Vec<ToolFunction>,
     tools: HashMap<String, Box<dyn HelenyTool>>,
 }
 
@@ -49,12 +52,14 @@ impl Toolkit {
         task_description: String,
         endpoint: Endpoint,
         tool_manuals: String,
+        functions: Vec<ToolFunction>,
         tools: HashMap<String, Box<dyn HelenyTool>>,
     ) -> Self {
         let endpoint = ToolkitEndpoint::new(task_id, task_description, endpoint);
         Toolkit {
             endpoint,
             tool_manuals,
+            functions,
             tools,
         }
     }
@@ -80,9 +85,27 @@ impl Toolkit {
         }
     }
 
+    /// 执行一次原生工具调用, 按函数名找到对应的工具和命令
+    pub async fn call(&mut self, call: ToolCall, reason: String) -> String {
+        let Some(function) = self.functions.iter().find(|function| function.name == call.name) else {
+            return format!("未找到函数: {}", call.name);
+        };
+        let intent = ToolIntent {
+            reason,
+            tool: Some(function.tool.clone()),
+            command: Some(function.command.clone()),
+            args: call.args,
+        };
+        self.invoke(intent).await
+    }
+
     pub fn get_manuals(&self) -> &str {
         &self.tool_manuals
     }
+
+    pub fn get_functions(&self) -> &[ToolFunction] {
+        &self.functions
+    }
 }
 
 #[derive(Debug)]
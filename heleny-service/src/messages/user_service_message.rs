@@ -6,7 +6,12 @@ use uuid::Uuid;
 
 #[derive(Debug)]
 pub enum UserServiceMessage {
-    Login(FrontendType),
+    /// 返回签发给该用户的 token, 需要用户身份的消息用它发送
+    Login {
+        frontend: FrontendType,
+        feedback: oneshot::Sender<Uuid>,
+    },
+    /// 吊销登录时签发的 token
     Logout,
     RequestConsent {
         body: ConsentRequestion,
@@ -12,6 +12,7 @@ use anyhow::Result;
 use heleny_bus::endpoint::Endpoint;
 use heleny_proto::CONFIG_SERVICE;
 use heleny_proto::ChatRole;
+use heleny_proto::DependencyGraph;
 use heleny_proto::FS_SERVICE;
 use heleny_proto::HUB_SERVICE;
 use heleny_proto::HelenyToolFactory;
@@ -20,111 +21,147 @@ use heleny_proto::MEMORY_SERVICE;
 use heleny_proto::ResourcePayload;
 use heleny_proto::TOOLKIT_SERVICE;
 use serde::de::DeserializeOwned;
-use tokio::sync::oneshot;
 use tokio::sync::watch;
-use tokio::time::timeout;
+
+/// 跨服务调用的默认超时时间
+pub const CALL_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub async fn get_from_config_service<T: DeserializeOwned>(endpoint: &Endpoint) -> Result<T> {
-    let (tx, rx) = oneshot::channel();
-    endpoint
-        .send(CONFIG_SERVICE, ConfigServiceMessage::Get { sender: tx })
-        .await
-        .context("获取 ConfigService 的资源发送失败")?;
-    let config = timeout(Duration::from_secs(10), rx)
+    let config = endpoint
+        .call(
+            CONFIG_SERVICE,
+            |sender| ConfigServiceMessage::Get { sender },
+            CALL_TIMEOUT,
+        )
         .await
-        .context("获取 ConfigService 的资源超时")?
         .context("获取 ConfigService 的资源失败")?
         .context("获取 ConfigService 的资源为空")?;
     serde_json::from_value(config).context("获取到 ConfigService 的资源, 但是解析失败")
 }
 
-pub async fn import_from_config_service<T: DeserializeOwned,U: Into<String>>(endpoint: &Endpoint,name: U) -> Result<T> {
-    let (tx, rx) = oneshot::channel();
-    endpoint
-        .send(CONFIG_SERVICE, ConfigServiceMessage::Import { key: name.into(), feedback: tx })
-        .await
-        .context("导入 ConfigService 的变量发送失败")?;
-    let config = timeout(Duration::from_secs(10), rx)
+pub async fn import_from_config_service<T: DeserializeOwned, U: Into<String>>(
+    endpoint: &Endpoint,
+    name: U,
+) -> Result<T> {
+    let key = name.into();
+    let config = endpoint
+        .call(
+            CONFIG_SERVICE,
+            |feedback| ConfigServiceMessage::Import { key, feedback },
+            CALL_TIMEOUT,
+        )
         .await
-        .context("导入 ConfigService 的变量超时")?
         .context("导入 ConfigService 的变量失败")?;
     serde_json::from_value(config).context("导入成功 ConfigService 的变量, 但是解析失败")
 }
 
 pub async fn update_config_service(endpoint: &Endpoint) -> Result<()> {
-    let (tx, rx) = oneshot::channel();
     endpoint
-        .send(CONFIG_SERVICE, ConfigServiceMessage::Update { feedback: tx } )
-        .await
-        .context("发送 Update 失败")?;
-    timeout(Duration::from_secs(10), rx)
+        .call_control(
+            CONFIG_SERVICE,
+            |feedback| ConfigServiceMessage::Update { feedback },
+            CALL_TIMEOUT,
+        )
         .await
-        .context("获取 ConfigService 的资源超时")?
         .context("获取 Update 反馈失败")
 }
 
 pub async fn read_via_fs_service<T: Into<PathBuf>>(endpoint: &Endpoint, path: T) -> Result<String> {
-    let (tx, rx) = oneshot::channel();
+    let path = path.into();
     endpoint
-        .send(
+        .call(
             FS_SERVICE,
-            FsServiceMessage::Read {
-                path: path.into(),
-                feedback: tx,
+            |feedback| FsServiceMessage::Read { path, feedback },
+            CALL_TIMEOUT,
+        )
+        .await
+        .context("获取 FsService 的文件失败")
+}
+
+pub async fn write_via_fs_service<T: Into<PathBuf>>(
+    endpoint: &Endpoint,
+    path: T,
+    content: String,
+) -> Result<()> {
+    let path = path.into();
+    endpoint
+        .call(
+            FS_SERVICE,
+            |feedback| FsServiceMessage::Write {
+                path,
+                content,
+                feedback,
             },
+            CALL_TIMEOUT,
+        )
+        .await
+        .context("通过 FsService 写入文件失败")
+}
+
+/// 读取图片的缩略图 (JPEG)
+pub async fn get_image_via_fs_service<T: Into<PathBuf>>(
+    endpoint: &Endpoint,
+    path: T,
+) -> Result<Vec<u8>> {
+    let path = path.into();
+    endpoint
+        .call(
+            FS_SERVICE,
+            |feedback| FsServiceMessage::GetImage { path, feedback },
+            CALL_TIMEOUT,
         )
         .await
-        .context("获取 FsService 文件的消息发送失败")?;
-    let data = rx.await.context("获取 FsService 的文件失败")?;
-    Ok(data)
+        .context("获取 FsService 的图片失败")
 }
 
 pub async fn list_via_fs_service<T: Into<PathBuf>>(
     endpoint: &Endpoint,
     path: T,
 ) -> Result<Vec<PathBuf>> {
-    let (tx, rx) = oneshot::channel();
+    let dir = path.into();
     endpoint
-        .send(
+        .call(
             FS_SERVICE,
-            FsServiceMessage::List {
-                dir: path.into(),
-                feedback: tx,
-            },
+            |feedback| FsServiceMessage::List { dir, feedback },
+            CALL_TIMEOUT,
         )
         .await
-        .context("获取 FsService 文件的消息发送失败")?;
-    let data = rx.await.context("读取目录失败")?;
-    Ok(data)
+        .context("读取目录失败")
 }
 
 pub async fn get_tool_descriptions(endpoint: &Endpoint) -> Result<String> {
-    let (tx, rx) = oneshot::channel();
     endpoint
-        .send(
+        .call(
             TOOLKIT_SERVICE,
-            ToolkitServiceMessage::GetIntro { feedback: tx },
+            |feedback| ToolkitServiceMessage::GetIntro { feedback },
+            CALL_TIMEOUT,
         )
         .await
-        .context("获取工具简介的消息发送失败")?;
-    let data = rx.await.context("获取工具简介失败")?;
-    Ok(data)
+        .context("获取工具简介失败")
 }
 
 pub async fn wait_for(endpoint: &Endpoint, name: &str) -> Result<()> {
-    let (tx, rx) = oneshot::channel();
+    let name = name.to_string();
+    // 等待服务启动没有时间上限, 只在 KernelService 不可达时失败
     endpoint
-        .send(
+        .call(
             KERNEL_SERVICE,
-            KernelServiceMessage::WaitFor {
-                name: name.to_string(),
-                sender: tx,
-            },
+            |sender| KernelServiceMessage::WaitFor { name, sender },
+            Duration::MAX,
         )
         .await
-        .context("等待服务的消息发送失败")?;
-    let data = rx.await.context("获取工具简介失败")?;
-    data
+        .context("等待服务失败")?
+}
+
+pub async fn get_dependency_graph(endpoint: &Endpoint) -> Result<DependencyGraph> {
+    endpoint
+        .call(
+            KERNEL_SERVICE,
+            KernelServiceMessage::GetDependencyGraph,
+            CALL_TIMEOUT,
+        )
+        .await
+        .context("获取服务依赖图失败")
 }
 
 pub async fn register_tool_factory<T: HelenyToolFactory>(endpoint: &Endpoint, factory: T) {
@@ -164,17 +201,18 @@ pub async fn get_resource<T: Into<String>>(
     endpoint: &Endpoint,
     resource_name: T,
 ) -> Result<ResourcePayload> {
-    let (tx, rx) = oneshot::channel();
+    let resource_name = resource_name.into();
     endpoint
-        .send(
+        .call(
             HUB_SERVICE,
-            HubServiceMessage::Get {
-                resource_name: resource_name.into(),
-                feedback: tx,
+            |feedback| HubServiceMessage::Get {
+                resource_name,
+                feedback,
             },
+            CALL_TIMEOUT,
         )
-        .await?;
-    rx.await.context("获取资源失败")
+        .await
+        .context("获取资源失败")
 }
 
 pub async fn subscribe_resource<T: Into<String>>(
@@ -205,11 +243,27 @@ pub async fn unsubscribe_resource<T: Into<String>>(
         .await
 }
 
-pub async fn send_file<T:Into<String>>(endpoint: &Endpoint, role: ChatRole, dir_name:T, file_name: T, data: Vec<u8>)->Result<()>{
-    let (tx,rx)=oneshot::channel();
-    endpoint.send(FS_SERVICE, FsServiceMessage::TempFile { dir_name:dir_name.into(), file_name:file_name.into(), data, feedback: tx }).await?;
-    let path=rx.await?;
+pub async fn send_file<T: Into<String>>(
+    endpoint: &Endpoint,
+    role: ChatRole,
+    dir_name: T,
+    file_name: T,
+    data: Vec<u8>,
+) -> Result<()> {
+    let (dir_name, file_name) = (dir_name.into(), file_name.into());
+    let path = endpoint
+        .call(
+            FS_SERVICE,
+            |feedback| FsServiceMessage::TempFile {
+                dir_name,
+                file_name,
+                data,
+                feedback,
+            },
+            CALL_TIMEOUT,
+        )
+        .await?;
     endpoint
         .send(MEMORY_SERVICE, MemoryServiceMessage::Post { role, content: path.into() })
         .await
-}
\ No newline at end of file
+}
@@ -0,0 +1,134 @@
+use crate::KernelServiceMessage;
+use crate::ServiceSignal;
+use heleny_bus::endpoint::Endpoint;
+use heleny_proto::KERNEL_SERVICE;
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio::time::MissedTickBehavior;
+use tokio::time::interval;
+use tracing::warn;
+
+/// KernelService 下发时限之前使用的默认值
+pub const DEFAULT_HANDLER_DEADLINE: Duration = Duration::from_secs(60);
+
+/// 看守单条消息的处理, 处理期间代为发送心跳, 超过时限后上报不健康
+pub struct Watchdog {
+    endpoint: Endpoint,
+    pub deadline: Duration,
+}
+
+impl Watchdog {
+    pub fn new(endpoint: &Endpoint) -> Self {
+        Self {
+            endpoint: endpoint.create_sender_endpoint(),
+            deadline: DEFAULT_HANDLER_DEADLINE,
+        }
+    }
+
+    pub async fn watch<F: Future>(&self, label: &str, handling: F) -> F::Output {
+        tokio::pin!(handling);
+        let start = Instant::now();
+        let mut heartbeat = interval(Duration::from_secs(1));
+        heartbeat.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        // 第一次 tick 立即返回, 跳过
+        heartbeat.tick().await;
+        let mut stalled = false;
+        loop {
+            tokio::select! {
+                output = &mut handling => {
+                    if stalled {
+                        warn!("{} 处理完成, 共耗时 {:?}", label, start.elapsed());
+                        self.report(ServiceSignal::Alive).await;
+                    }
+                    return output;
+                }
+                _ = heartbeat.tick(), if !stalled => {
+                    if start.elapsed() < self.deadline {
+                        self.report(ServiceSignal::Alive).await;
+                        continue;
+                    }
+                    stalled = true;
+                    let reason = format!("处理 {} 超过 {:?} 仍未完成", label, self.deadline);
+                    warn!("{}", reason);
+                    self.report(ServiceSignal::Unhealthy(reason)).await;
+                }
+            }
+        }
+    }
+
+    async fn report(&self, signal: ServiceSignal) {
+        let _ = self
+            .endpoint
+            .send_control(KERNEL_SERVICE, KernelServiceMessage::UploadStatus(signal))
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heleny_bus::BusHandle;
+    use heleny_proto::ServiceRole;
+    use heleny_proto::downcast;
+    use tokio::time::sleep;
+    use tokio::time::timeout;
+
+    async fn setup() -> (BusHandle, Watchdog, Endpoint) {
+        let mut bus = BusHandle::new(32);
+        let service = bus
+            .get_endpoint("TestService".into(), 32, ServiceRole::Standard)
+            .await
+            .expect("获取 TestService 失败");
+        let kernel = bus
+            .get_endpoint(KERNEL_SERVICE.into(), 32, ServiceRole::System)
+            .await
+            .expect("获取 KernelService 失败");
+        let mut watchdog = Watchdog::new(&service);
+        watchdog.deadline = Duration::from_secs(3);
+        (bus, watchdog, kernel)
+    }
+
+    /// 取出已上报的状态, 1 秒内没有新消息即结束
+    async fn reports(kernel: &mut Endpoint) -> Vec<String> {
+        let mut reports = Vec::new();
+        while let Ok(msg) = timeout(Duration::from_secs(1), kernel.recv()).await {
+            let msg = msg.expect("接收消息失败");
+            match downcast::<KernelServiceMessage>(msg.payload).expect("消息类型错误") {
+                KernelServiceMessage::UploadStatus(ServiceSignal::Alive) => {
+                    reports.push("Alive".to_string())
+                }
+                KernelServiceMessage::UploadStatus(ServiceSignal::Unhealthy(_)) => {
+                    reports.push("Unhealthy".to_string())
+                }
+                other => panic!("意外的消息: {:?}", other),
+            }
+        }
+        reports
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_alive_then_unhealthy() {
+        let (_bus, watchdog, mut kernel) = setup().await;
+        let output = watchdog
+            .watch("Slow", async {
+                sleep(Duration::from_secs(5)).await;
+                42
+            })
+            .await;
+        assert_eq!(output, 42);
+        // 前两秒代发心跳, 第三秒超过时限, 完成后恢复
+        assert_eq!(
+            reports(&mut kernel).await,
+            vec!["Alive", "Alive", "Unhealthy", "Alive"]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fast_handling_reports_nothing() {
+        let (_bus, watchdog, mut kernel) = setup().await;
+        watchdog
+            .watch("Fast", sleep(Duration::from_millis(500)))
+            .await;
+        assert!(reports(&mut kernel).await.is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+use heleny_bus::midware::Midware;
+use heleny_bus::midware::MidwareAction;
+use heleny_proto::SignedMessage;
+use heleny_proto::TrafficRecord;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// 把经过 Bus 的每条消息记到内存里的拦截器
+pub struct Capture {
+    records: Arc<Mutex<Vec<TrafficRecord>>>,
+}
+
+impl Capture {
+    pub fn new() -> (Self, Arc<Mutex<Vec<TrafficRecord>>>) {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        (
+            Self {
+                records: records.clone(),
+            },
+            records,
+        )
+    }
+}
+
+impl Midware for Capture {
+    fn name(&self) -> &str {
+        "Capture"
+    }
+    fn handle(&mut self, msg: SignedMessage) -> MidwareAction {
+        self.records
+            .lock()
+            .expect("获取流量记录锁失败")
+            .push(TrafficRecord::new(&msg));
+        MidwareAction::Pass(msg)
+    }
+}
+
+/// 某一时刻截获的流量, 用于断言
+#[derive(Debug, Clone, Default)]
+pub struct Traffic {
+    pub records: Vec<TrafficRecord>,
+}
+
+impl Traffic {
+    /// 按来源, 目标和消息筛选, None 表示不限, `message` 为 "Type" 或 "Type::Variant"
+    pub fn filter(
+        &self,
+        source: Option<&str>,
+        target: Option<&str>,
+        message: Option<&str>,
+    ) -> Vec<&TrafficRecord> {
+        self.records
+            .iter()
+            .filter(|record| source.is_none_or(|source| record.source == source))
+            .filter(|record| target.is_none_or(|target| record.target == target))
+            .filter(|record| message.is_none_or(|message| matches_message(record, message)))
+            .collect()
+    }
+
+    /// source 发给 target 的 message 条数
+    pub fn count(&self, source: &str, target: &str, message: &str) -> usize {
+        self.filter(Some(source), Some(target), Some(message)).len()
+    }
+
+    /// 断言 source 至少给 target 发过一条 message
+    pub fn assert_sent(&self, source: &str, target: &str, message: &str) {
+        assert!(
+            self.count(source, target, message) > 0,
+            "{} 没有给 {} 发过 {}, 截获的流量:\n{}",
+            source,
+            target,
+            message,
+            self.summary()
+        );
+    }
+
+    /// 断言 source 从未给 target 发过 message
+    pub fn assert_not_sent(&self, source: &str, target: &str, message: &str) {
+        let sent = self.filter(Some(source), Some(target), Some(message));
+        assert!(
+            sent.is_empty(),
+            "{} 不应给 {} 发 {}, 但发了 {} 条: {:?}",
+            source,
+            target,
+            message,
+            sent.len(),
+            sent
+        );
+    }
+
+    /// 断言这些消息按给定顺序出现过, 中间可以夹杂其他消息
+    pub fn assert_order(&self, expected: &[(&str, &str, &str)]) {
+        let mut records = self.records.iter();
+        for (source, target, message) in expected {
+            let found = records.any(|record| {
+                record.source == *source
+                    && record.target == *target
+                    && matches_message(record, message)
+            });
+            assert!(
+                found,
+                "没有按顺序找到 {} -> {}: {}, 截获的流量:\n{}",
+                source,
+                target,
+                message,
+                self.summary()
+            );
+        }
+    }
+
+    /// 每条一行的流量概要
+    pub fn summary(&self) -> String {
+        self.records
+            .iter()
+            .map(|record| {
+                format!(
+                    "{} -> {}: {}::{}",
+                    record.source, record.target, record.message, record.variant
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// 与 Replayer 的解码器相同, "Type" 匹配类型, "Type::Variant" 还要匹配变体
+fn matches_message(record: &TrafficRecord, message: &str) -> bool {
+    match message.split_once("::") {
+        Some((message, variant)) => record.message == message && record.variant == variant,
+        None => record.message == message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+    use heleny_proto::ServiceRole;
+
+    fn record(source: &str, target: &str, message: &str, variant: &str) -> TrafficRecord {
+        TrafficRecord {
+            time: Local::now(),
+            source: source.into(),
+            target: target.into(),
+            role: ServiceRole::Standard,
+            message: message.into(),
+            variant: variant.into(),
+            payload: variant.into(),
+            correlation: None,
+            trace_id: None,
+        }
+    }
+
+    #[test]
+    fn test_traffic_assertions() {
+        let traffic = Traffic {
+            records: vec![
+                record(
+                    "TaskService",
+                    "ChatService",
+                    "ChatServiceMessage",
+                    "GetPlanner",
+                ),
+                record("TaskService", "HubService", "HubServiceMessage", "Publish"),
+                record(
+                    "TaskService",
+                    "ChatService",
+                    "ChatServiceMessage",
+                    "GetExecutor",
+                ),
+            ],
+        };
+        assert_eq!(
+            traffic.count("TaskService", "ChatService", "ChatServiceMessage"),
+            2
+        );
+        traffic.assert_sent(
+            "TaskService",
+            "ChatService",
+            "ChatServiceMessage::GetPlanner",
+        );
+        traffic.assert_not_sent("TaskService", "FsService", "FsServiceMessage");
+        traffic.assert_order(&[
+            (
+                "TaskService",
+                "ChatService",
+                "ChatServiceMessage::GetPlanner",
+            ),
+            (
+                "TaskService",
+                "ChatService",
+                "ChatServiceMessage::GetExecutor",
+            ),
+        ]);
+        let result = std::panic::catch_unwind(|| {
+            traffic.assert_order(&[
+                (
+                    "TaskService",
+                    "ChatService",
+                    "ChatServiceMessage::GetExecutor",
+                ),
+                ("TaskService", "HubService", "HubServiceMessage::Publish"),
+            ])
+        });
+        assert!(result.is_err());
+    }
+}
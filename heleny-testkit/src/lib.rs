@@ -0,0 +1,333 @@
+use anyhow::Context;
+use anyhow::Result;
+use heleny_bus::endpoint::Endpoint;
+use heleny_kernel::Kernel;
+use heleny_proto::KERNEL_NAME;
+use heleny_proto::KERNEL_SERVICE;
+use heleny_proto::KernelHealth;
+use heleny_proto::ServiceRole;
+use heleny_proto::ShutdownReport;
+use heleny_proto::TrafficRecord;
+use heleny_service::KernelMessage;
+use heleny_service::KernelServiceMessage;
+use heleny_service::ServiceFactoryVec;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio::time::sleep;
+use tokio::time::timeout;
+use tracing::info;
+
+mod capture;
+pub use capture::*;
+mod mock;
+pub use mock::*;
+
+/// TestKit 自己用的 Endpoint, 用于查询健康表和关机
+const CONTROL_NAME: &str = "TestKit";
+
+/// 启动只运行部分服务的内核, 可用替身代替真实服务
+pub struct TestKitBuilder {
+    services: Vec<String>,
+    mocks: Vec<ServiceFactoryVec>,
+    clients: Vec<(String, ServiceRole)>,
+    ready_timeout: Duration,
+    kernel_buffer: usize,
+    service_buffer: usize,
+}
+
+impl Default for TestKitBuilder {
+    fn default() -> Self {
+        Self {
+            services: Vec::new(),
+            mocks: Vec::new(),
+            clients: Vec::new(),
+            ready_timeout: Duration::from_secs(10),
+            kernel_buffer: 64,
+            service_buffer: 32,
+        }
+    }
+}
+
+impl TestKitBuilder {
+    /// 要运行的真实服务, 它们的依赖也会启动
+    pub fn services<I, T>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.services.extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    /// 注册替身, 同名的真实服务不会启动
+    pub fn mock(mut self, mock: MockService) -> Self {
+        self.mocks.push(mock.into_factory());
+        self
+    }
+
+    /// 注册任意工厂, 用于需要完整 Service 实现的替身
+    pub fn factory(mut self, factory: ServiceFactoryVec) -> Self {
+        self.mocks.push(factory);
+        self
+    }
+
+    /// 测试用的 Endpoint, 启动后通过 TestKit::client 取出
+    pub fn client<T: Into<String>>(mut self, name: T, role: ServiceRole) -> Self {
+        self.clients.push((name.into(), role));
+        self
+    }
+
+    pub fn ready_timeout(mut self, ready_timeout: Duration) -> Self {
+        self.ready_timeout = ready_timeout;
+        self
+    }
+
+    pub fn buffer(mut self, kernel_buffer: usize, service_buffer: usize) -> Self {
+        self.kernel_buffer = kernel_buffer;
+        self.service_buffer = service_buffer;
+        self
+    }
+
+    /// 启动内核并等待所有服务和替身就绪
+    pub async fn start(self) -> Result<TestKit> {
+        let mut kernel = Kernel::new(self.kernel_buffer, self.service_buffer).await?;
+        let (capture, records) = Capture::new();
+        kernel.add_midware(Box::new(capture)).await?;
+        let mut waiting = self.services.clone();
+        for factory in self.mocks {
+            let name = factory.name;
+            kernel
+                .replace_service(factory)
+                .await
+                .context(format!("注册替身 {} 失败", name))?;
+            waiting.push(name.to_string());
+        }
+        kernel.select_services(waiting.clone());
+        let control = kernel
+            .get_endpoint(
+                CONTROL_NAME.to_string(),
+                self.service_buffer,
+                ServiceRole::System,
+            )
+            .await?;
+        let mut clients = HashMap::new();
+        for (name, role) in self.clients {
+            let endpoint = kernel
+                .get_endpoint(name.clone(), self.service_buffer, role)
+                .await?;
+            clients.insert(name, endpoint);
+        }
+        let mut ready = Vec::new();
+        for name in waiting {
+            let rx = kernel.wait_for(name.clone()).await;
+            ready.push((name, rx));
+        }
+        let kernel = tokio::spawn(async move {
+            kernel.run().await;
+            kernel
+        });
+        let deadline = Instant::now() + self.ready_timeout;
+        for (name, rx) in ready {
+            let result = match timeout(deadline.saturating_duration_since(Instant::now()), rx).await
+            {
+                Ok(Ok(Ok(()))) => Ok(()),
+                Ok(Ok(Err(e))) => Err(anyhow::anyhow!("等待 {} 失败: {}", name, e)),
+                Ok(Err(e)) => Err(anyhow::anyhow!("等待 {} 时通道关闭: {}", name, e)),
+                Err(_) => Err(anyhow::anyhow!(
+                    "{:?} 内 {} 未就绪",
+                    self.ready_timeout,
+                    name
+                )),
+            };
+            if let Err(e) = result {
+                kernel.abort();
+                return Err(e);
+            }
+            info!("{} 已就绪", name);
+        }
+        Ok(TestKit {
+            kernel,
+            control,
+            clients,
+            records,
+        })
+    }
+}
+
+/// 运行中的测试内核
+pub struct TestKit {
+    kernel: JoinHandle<Kernel>,
+    control: Endpoint,
+    clients: HashMap<String, Endpoint>,
+    records: Arc<Mutex<Vec<TrafficRecord>>>,
+}
+
+impl TestKit {
+    pub fn builder() -> TestKitBuilder {
+        TestKitBuilder::default()
+    }
+
+    /// 取出 builder 里注册的 Endpoint
+    pub fn client(&mut self, name: &str) -> Result<&mut Endpoint> {
+        self.clients
+            .get_mut(name)
+            .context(format!("没有注册 {} 的 Endpoint", name))
+    }
+
+    /// 截至目前经过 Bus 的流量
+    pub fn traffic(&self) -> Traffic {
+        Traffic {
+            records: self.records.lock().expect("获取流量记录锁失败").clone(),
+        }
+    }
+
+    /// 清空已截获的流量
+    pub fn clear_traffic(&self) {
+        self.records.lock().expect("获取流量记录锁失败").clear();
+    }
+
+    /// 等到 source 给 target 发出 message, 超时返回错误
+    pub async fn wait_for_message(
+        &self,
+        source: &str,
+        target: &str,
+        message: &str,
+        wait: Duration,
+    ) -> Result<TrafficRecord> {
+        let deadline = Instant::now() + wait;
+        loop {
+            if let Some(record) = self
+                .traffic()
+                .filter(Some(source), Some(target), Some(message))
+                .first()
+            {
+                return Ok((*record).clone());
+            }
+            if Instant::now() >= deadline {
+                return Err(anyhow::anyhow!(
+                    "{:?} 内 {} 没有给 {} 发 {}",
+                    wait,
+                    source,
+                    target,
+                    message
+                ));
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    pub async fn health(&self) -> Result<KernelHealth> {
+        let (tx, rx) = oneshot::channel();
+        self.control
+            .send(KERNEL_SERVICE, KernelServiceMessage::GetHealth(tx))
+            .await?;
+        Ok(rx.await?)
+    }
+
+    /// 关闭所有服务并返回关机报告
+    pub async fn shutdown(self) -> Result<Option<ShutdownReport>> {
+        self.control
+            .send(KERNEL_NAME, KernelMessage::Shutdown)
+            .await?;
+        let kernel = self.kernel.await.context("等待内核退出失败")?;
+        Ok(kernel.shutdown_report().cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heleny_proto::HealthStatus;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    /// 记录替身被启动的次数
+    fn counted(mock: MockService, launches: Arc<AtomicUsize>) -> ServiceFactoryVec {
+        let mut factory = mock.into_factory();
+        let launch = factory.launch.clone();
+        factory.launch = Arc::new(move |endpoint| {
+            launches.fetch_add(1, Ordering::SeqCst);
+            launch(endpoint)
+        });
+        factory
+    }
+
+    /// 等到 name 第 launches 次启动后进入 status, 超时返回错误
+    async fn wait_for(
+        kit: &TestKit,
+        name: &str,
+        status: HealthStatus,
+        counter: &AtomicUsize,
+        launches: usize,
+    ) -> Result<()> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let health = kit.health().await?;
+            let current = health.services.get(name).map(|(status, _)| status);
+            if current == Some(&status) && counter.load(Ordering::SeqCst) == launches {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(anyhow::anyhow!(
+                    "{} 未在第 {} 次启动后进入 {:?}: {:?}, 已启动 {} 次",
+                    name,
+                    launches,
+                    status,
+                    current,
+                    counter.load(Ordering::SeqCst)
+                ));
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    async fn send(kit: &TestKit, msg: KernelServiceMessage) -> Result<()> {
+        kit.control.send(KERNEL_SERVICE, msg).await
+    }
+
+    #[tokio::test]
+    async fn test_stop_start_restart_service() -> Result<()> {
+        let alpha = Arc::new(AtomicUsize::new(0));
+        let beta = Arc::new(AtomicUsize::new(0));
+        let kit = TestKit::builder()
+            .factory(counted(MockService::new("Alpha"), alpha.clone()))
+            .factory(counted(
+                MockService::new("Beta").deps(&["Alpha"]),
+                beta.clone(),
+            ))
+            .start()
+            .await?;
+
+        // 关闭运行中的服务, 依赖它的服务一起关闭
+        let name = "Alpha".to_string();
+        send(&kit, KernelServiceMessage::StopService { name }).await?;
+        wait_for(&kit, "Beta", HealthStatus::Stopped, &beta, 1).await?;
+        wait_for(&kit, "Alpha", HealthStatus::Stopped, &alpha, 1).await?;
+
+        // 再次启动只启动它自己
+        let name = "Alpha".to_string();
+        send(&kit, KernelServiceMessage::StartService { name }).await?;
+        wait_for(&kit, "Alpha", HealthStatus::Healthy, &alpha, 2).await?;
+        wait_for(&kit, "Beta", HealthStatus::Stopped, &beta, 1).await?;
+
+        // 重启已关闭的服务等同于启动
+        let name = "Beta".to_string();
+        send(&kit, KernelServiceMessage::RestartService { name }).await?;
+        wait_for(&kit, "Beta", HealthStatus::Healthy, &beta, 2).await?;
+
+        // 重启运行中的服务, 下游服务关闭后一起启动
+        let name = "Alpha".to_string();
+        send(&kit, KernelServiceMessage::RestartService { name }).await?;
+        wait_for(&kit, "Alpha", HealthStatus::Healthy, &alpha, 3).await?;
+        wait_for(&kit, "Beta", HealthStatus::Healthy, &beta, 3).await?;
+
+        let report = kit.shutdown().await?.context("未收到关机报告")?;
+        assert!(report.is_clean());
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::Result;
+use heleny_bus::endpoint::Endpoint;
+use heleny_proto::AnyMessage;
+use heleny_proto::KERNEL_SERVICE;
+use heleny_proto::ServiceHandle;
+use heleny_proto::ServiceRole;
+use heleny_proto::short_type_name;
+use heleny_service::CommonMessage;
+use heleny_service::HEARTBEAT_INTERVAL;
+use heleny_service::KernelServiceMessage;
+use heleny_service::ServiceFactoryVec;
+use heleny_service::ServiceSignal;
+use std::any::Any;
+use std::sync::Arc;
+use tokio::time::MissedTickBehavior;
+use tokio::time::interval;
+use tracing::Instrument;
+use tracing::info_span;
+use tracing::warn;
+
+/// 尝试处理一条消息, 类型不符时原样交还
+type Handler = Arc<dyn Fn(Box<dyn Any>) -> Result<Result<()>, Box<dyn Any>> + Send + Sync>;
+
+/// 替身服务, 按消息类型执行预设的处理, 用来代替需要 LLM 或磁盘的真实服务
+pub struct MockService {
+    name: &'static str,
+    deps: Vec<&'static str>,
+    handlers: Vec<Handler>,
+}
+
+impl MockService {
+    /// 同名时替换真实服务, 默认不依赖其他服务
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            deps: Vec::new(),
+            handlers: Vec::new(),
+        }
+    }
+
+    pub fn deps(mut self, deps: &[&'static str]) -> Self {
+        self.deps = deps.to_vec();
+        self
+    }
+
+    /// 收到 M 类型的消息时调用 handler, 一般在这里通过消息里的 feedback 回复
+    pub fn on<M, F>(mut self, handler: F) -> Self
+    where
+        M: AnyMessage,
+        F: Fn(M) -> Result<()> + Send + Sync + 'static,
+    {
+        self.handlers.push(Arc::new(move |msg: Box<dyn Any>| {
+            msg.downcast::<M>().map(|msg| handler(*msg))
+        }));
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// 转为内核可以启动的工厂, 重启时复用同一组处理
+    pub fn into_factory(self) -> ServiceFactoryVec {
+        let Self {
+            name,
+            deps,
+            handlers,
+        } = self;
+        let handlers: Arc<[Handler]> = handlers.into();
+        ServiceFactoryVec {
+            name,
+            deps,
+            launch: Arc::new(move |endpoint| {
+                let span = info_span!("", Name = %name, Mock = true);
+                let handle = tokio::spawn(run(endpoint, handlers.clone()).instrument(span));
+                Ok(ServiceHandle::new(name.to_string(), handle))
+            }),
+        }
+    }
+}
+
+async fn run(mut endpoint: Endpoint, handlers: Arc<[Handler]>) -> Result<()> {
+    let (mut from_bus, _from_sub_endpoint) = endpoint.get_rx()?;
+    upload(&endpoint, ServiceSignal::Ready).await;
+    let mut heartbeat = interval(HEARTBEAT_INTERVAL);
+    heartbeat.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        tokio::select! {
+            Some(msg) = from_bus.recv() => {
+                let type_name = short_type_name(msg.payload.as_ref().type_name());
+                let stop = match msg.payload.as_any().downcast::<CommonMessage>() {
+                    Ok(common) => matches!(*common, CommonMessage::Stop) && msg.role == ServiceRole::System,
+                    Err(payload) => {
+                        handle(&handlers, payload, type_name);
+                        false
+                    }
+                };
+                if stop {
+                    upload(&endpoint, ServiceSignal::Terminate("".into())).await;
+                    return Ok(());
+                }
+            }
+            _ = heartbeat.tick() => {
+                upload(&endpoint, ServiceSignal::Alive).await;
+            }
+        }
+    }
+}
+
+/// 交给第一个类型相符的处理
+fn handle(handlers: &[Handler], mut payload: Box<dyn Any>, type_name: &str) {
+    for handler in handlers {
+        payload = match handler(payload) {
+            Ok(Ok(())) => return,
+            Ok(Err(e)) => {
+                warn!("替身处理 {} 出错: {}", type_name, e);
+                return;
+            }
+            Err(payload) => payload,
+        };
+    }
+    warn!("替身没有 {} 的处理, 忽略", type_name);
+}
+
+async fn upload(endpoint: &Endpoint, signal: ServiceSignal) {
+    let _ = endpoint
+        .send_control(KERNEL_SERVICE, KernelServiceMessage::UploadStatus(signal))
+        .await;
+}
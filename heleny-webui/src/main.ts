@@ -15,6 +15,9 @@ const wsHost = import.meta.env.DEV
 const wsProtocol = window.location.protocol === 'https:' ? 'wss' : 'ws';
 export const socket: WebSocket = new WebSocket(`${wsProtocol}://${wsHost}/ws`);
 
+// 与后端 STREAMING_MESSAGE_ID 一致, 正在生成的回复使用的临时 id
+const STREAMING_MESSAGE_ID = -1;
+
 type FrontendCommand =
   | { UserInput: string }
   | { GetHistory: number }
@@ -155,9 +158,10 @@ socket.onmessage = (event: MessageEvent) => {
       if (data.UpdateResource.payload?.Health) {
         const health = data.UpdateResource.payload.Health;
         const services = health?.services ?? {};
+        const reasons: Record<string, string> = health?.reasons ?? {};
         const entries = Object.entries(services).map(([name, value]) => {
           const status = Array.isArray(value) ? value[0] : value;
-          return { name, status };
+          return { name, status, reason: reasons[name] };
         });
         entries.sort((a, b) => a.name.localeCompare(b.name));
         store.servicesHealth = entries;
@@ -174,14 +178,34 @@ socket.onmessage = (event: MessageEvent) => {
         case 'TotalBusTraffic':
           store.totalBusTraffic = data.UpdateResource.payload.TotalBusTraffic;
           break;
+        case 'TrafficMatrix':
+          store.trafficMatrix = data.UpdateResource.payload.TrafficMatrix;
+          break;
+        case 'LlmUsage':
+          store.llmUsage = data.UpdateResource.payload.LlmUsage;
+          break;
         case 'DisplayMessages': {
           const payload = data.UpdateResource.payload.DisplayMessages;
           const newMessages = payload?.messages;
           if (Array.isArray(newMessages)) {
+            // 新消息到达或正在生成的回复更新时, 撤下旧的临时消息
+            if (payload.new) {
+              const index = store.messages.findIndex(m => m.id === STREAMING_MESSAGE_ID);
+              if (index !== -1) {
+                store.messages.splice(index, 1);
+              }
+            }
+            if (payload.streaming) {
+              store.messages.push(...newMessages);
+              break;
+            }
             const existingIds = new Set(store.messages.map(m => m.id));
             const uniqueNewMessages = newMessages.filter(m => !existingIds.has(m.id));
             store.messages.push(...uniqueNewMessages);
-            store.messages.sort((a, b) => a.id - b.id);
+            // 临时消息始终排在最后
+            store.messages.sort((a, b) =>
+              Number(a.id === STREAMING_MESSAGE_ID) - Number(b.id === STREAMING_MESSAGE_ID) || a.id - b.id
+            );
 
             for (const msg of uniqueNewMessages) {
               const imagePath = msg.content?.Image;
@@ -14,6 +14,43 @@ export interface ChatMessage {
 export interface ServiceHealthItem {
   name: string;
   status: string;
+  reason?: string;
+}
+
+export interface LatencyHistogram {
+  buckets: number[];
+  count: number;
+  total_micros: number;
+  max_micros: number;
+}
+
+export interface ServiceTraffic {
+  queue_depth: number;
+  latency: LatencyHistogram;
+}
+
+export interface TrafficMatrix {
+  routes: Record<string, Record<string, number>>;
+  services: Record<string, ServiceTraffic>;
+}
+
+export interface UsageTotals {
+  calls: number;
+  prompt_tokens: number;
+  completion_tokens: number;
+  latency_ms: number;
+  cost: number;
+}
+
+export interface DailyUsage {
+  total: UsageTotals;
+  roles: Record<string, UsageTotals>;
+  models: Record<string, UsageTotals>;
+  tasks: Record<string, UsageTotals>;
+}
+
+export interface LlmUsage {
+  days: Record<string, DailyUsage>;
 }
 
 export interface TaskItem {
@@ -56,6 +93,8 @@ export interface ToolAbstractItem {
 
 export const store = reactive({
   totalBusTraffic: [] as [string, number][],
+  trafficMatrix: { routes: {}, services: {} } as TrafficMatrix,
+  llmUsage: { days: {} } as LlmUsage,
   messages: [] as ChatMessage[],
   images: {} as Record<number, string>,
   servicesHealth: [] as ServiceHealthItem[],
@@ -60,7 +60,7 @@ use anyhow::Result;
 use heleny_proto::Resource;
 
 
-#[base_service(deps=[])]
+#[base_service(deps=[], tick=none)]
 pub struct {PATTERN}Service{
     endpoint:Endpoint,
 }
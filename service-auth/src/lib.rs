@@ -29,7 +29,7 @@ use tracing::warn;
 
 use crate::config::AuthConfig;
 
-#[base_service(deps=["ConfigService"])]
+#[base_service(deps=["ConfigService"], tick=none)]
 pub struct AuthService {
     endpoint: Endpoint,
     pub_keys: Vec<VerifyingKey>,
@@ -1,27 +1,76 @@
 mod async_openai_backend;
+pub(crate) mod fallback_backend;
 mod gemini_rust_backend;
+mod image_loader;
+mod metered_backend;
+mod mock_backend;
 
+use anyhow::Context;
 use anyhow::Result;
 use async_openai_backend::AsyncOpenaiChat;
 use genai::{Client, adapter::AdapterKind};
+use heleny_bus::endpoint::Endpoint;
 use heleny_proto::{Chat, Embed};
+use std::time::Duration;
+use tracing::warn;
 
-use crate::{backend::{async_openai_backend::AsyncOpenaiEmbed, gemini_rust_backend::GeminiChat}, config::ApiConfig};
+use crate::{backend::{async_openai_backend::AsyncOpenaiEmbed, fallback_backend::FallbackChat, gemini_rust_backend::GeminiChat, image_loader::ImageLoader, metered_backend::{MeteredChat, MeteredEmbed, UsageMeter}, mock_backend::{MOCK_SCHEME, MockChat, MockEmbed}}, config::{ApiConfig, ChatConfig, RoleConfig}};
 
-pub async fn get_chat_model(api_config:ApiConfig,schema:&'static str)->Result<Box<dyn Chat>> {
+/// 按 role.api 的顺序组合各 API, 单次请求超时为 role.timeout_secs, 整次调用不超过 role.total_timeout_secs(), 每次调用的用量记在 name 名下
+pub async fn get_role_chat_model(config:&ChatConfig,role:&RoleConfig,name:&str,schema:&'static str,endpoint:&Endpoint)->Result<Box<dyn Chat>> {
+    if role.api.is_empty() {
+        return Err(anyhow::anyhow!("{} 没有配置 API",name));
+    }
+    let meter=UsageMeter::new(&name.to_lowercase(), endpoint.create_sender_endpoint());
+    let loader=ImageLoader::new(endpoint.create_sender_endpoint());
+    let mut chat=FallbackChat::new(config.retry.clone(), Duration::from_secs(role.timeout_secs), Duration::from_secs(role.total_timeout_secs()));
+    for &index in &role.api {
+        let api=config
+            .api
+            .get(index)
+            .context(format!("没有第 {} 个 API 配置",index))?
+            .to_owned();
+        if api.api_key.is_empty() {
+            warn!("注意, {} 使用的 API {} 没有 API_KEY",name,index);
+        }
+        let label=format!("{} (api {})",api.model,index);
+        let model=api.model.clone();
+        let tokenizer=api.tokenizer();
+        let images=api.vision.then(|| loader.clone());
+        let metered=MeteredChat::new(get_chat_model(api, schema, images).await?, model, tokenizer, meter.clone());
+        chat=chat.with_provider(label, Box::new(metered));
+    }
+    Ok(Box::new(chat))
+}
+
+/// images 为 None 时图片只以文字描述发送
+pub async fn get_chat_model(api_config:ApiConfig,schema:&'static str,images:Option<ImageLoader>)->Result<Box<dyn Chat>> {
+    // 离线脚本, 不需要网络
+    if let Some(script) = api_config.base_url.strip_prefix(MOCK_SCHEME) {
+        return Ok(Box::new(MockChat::load(script, schema).await?) as Box<dyn Chat>);
+    }
     let client = Client::default();
     let adapter_kind = client.resolve_service_target(&api_config.model).await?.model.adapter_kind;
     match adapter_kind {
         AdapterKind::Gemini=>{
-            Ok(Box::new(GeminiChat::new(api_config)) as Box<dyn Chat>)
+            Ok(Box::new(GeminiChat::new(api_config, images)) as Box<dyn Chat>)
         }
         _=>{
-            Ok(Box::new(AsyncOpenaiChat::new(api_config, schema)) as Box<dyn Chat>)
+            Ok(Box::new(AsyncOpenaiChat::new(api_config, schema, images)) as Box<dyn Chat>)
         }
     }
 }
 
+/// 带用量统计的嵌入模型
+pub fn get_metered_embed_model(base_url:String,model:String,api_key:String,endpoint:&Endpoint)->Result<Box<dyn Embed>> {
+    let embed=get_embed_model(base_url, model.clone(), api_key)?;
+    Ok(Box::new(MeteredEmbed::new(embed, model, UsageMeter::new("embed", endpoint.create_sender_endpoint()))))
+}
+
 pub fn get_embed_model(base_url:String,model:String,api_key:String)->Result<Box<dyn Embed>> {
+    if base_url.starts_with(MOCK_SCHEME) {
+        return Ok(Box::new(MockEmbed));
+    }
     let client=AsyncOpenaiEmbed::new(base_url, model, api_key);
     Ok(Box::new(client))
 }
\ No newline at end of file
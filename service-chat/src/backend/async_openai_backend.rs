@@ -2,56 +2,110 @@ use std::fmt::Debug;
 use anyhow::Context;
 use anyhow::Result;
 use async_openai::Client;
+use async_openai::config::Config;
 use async_openai::config::OpenAIConfig;
 use async_openai::types::chat::ChatCompletionRequestAssistantMessageArgs;
+use async_openai::types::chat::ChatCompletionMessageToolCall;
+use async_openai::types::chat::ChatCompletionMessageToolCalls;
 use async_openai::types::chat::ChatCompletionRequestMessage;
+use async_openai::types::chat::ChatCompletionRequestMessageContentPartImage;
+use async_openai::types::chat::ChatCompletionRequestMessageContentPartText;
 use async_openai::types::chat::ChatCompletionRequestSystemMessageArgs;
+use async_openai::types::chat::ChatCompletionRequestToolMessageArgs;
 use async_openai::types::chat::ChatCompletionRequestUserMessageArgs;
+use async_openai::types::chat::ChatCompletionStreamOptions;
+use async_openai::types::chat::ChatCompletionTool;
+use async_openai::types::chat::ChatCompletionTools;
+use async_openai::types::chat::CreateChatCompletionRequest;
 use async_openai::types::chat::CreateChatCompletionRequestArgs;
+use async_openai::types::chat::CompletionUsage;
+use async_openai::types::chat::CreateChatCompletionResponse;
+use async_openai::types::chat::FunctionCall;
+use async_openai::types::chat::FunctionObject;
+use async_openai::types::chat::ImageUrl;
 use async_openai::types::chat::ResponseFormat;
 use async_openai::types::chat::ResponseFormatJsonSchema;
 use async_openai::types::embeddings::CreateEmbeddingRequest;
 use async_openai::types::embeddings::EmbeddingInput;
 use async_trait::async_trait;
 use heleny_proto::ChatRole;
+use heleny_proto::TokenUsage;
+use heleny_proto::report_usage;
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
 
 use async_openai::Embeddings;
 use heleny_proto::Embed;
 use heleny_proto::Embedding;
 use tracing::info;
 use crate::ApiConfig;
+use crate::backend::fallback_backend::ApiFailure;
+use crate::backend::fallback_backend::retry_after;
+use crate::backend::image_loader::ImageLoader;
+use crate::backend::image_loader::Images;
+use crate::backend::image_loader::image_data;
+use crate::backend::image_loader::load_images;
 use heleny_proto::Chat;
 use heleny_proto::MemoryEntry;
+use heleny_proto::ToolCall;
+use heleny_proto::ToolFunction;
+use heleny_proto::ToolMessage;
+use heleny_proto::ToolReply;
 
 #[derive(Debug)]
 pub struct AsyncOpenaiChat {
     client: Client<OpenAIConfig>,
+    /// 非流式请求直接发送, 不用 async_openai 内置的重试
+    http: reqwest::Client,
     model: String,
-    schema: &'static str
+    schema: &'static str,
+    /// 只有支持图片输入的 API 才有
+    images: Option<ImageLoader>,
 }
 
 impl AsyncOpenaiChat {
-    pub fn new(api_config: ApiConfig,schema:&'static str) -> Self {
+    pub fn new(api_config: ApiConfig,schema:&'static str,images: Option<ImageLoader>) -> Self {
         let config = OpenAIConfig::new()
             .with_api_base(api_config.base_url)
             .with_api_key(api_config.api_key);
         Self {
             client: Client::with_config(config),
+            http: reqwest::Client::new(),
             model: api_config.model,
             schema,
+            images,
         }
     }
-}
 
-#[async_trait]
-impl Chat for AsyncOpenaiChat {
-    async fn chat(&self,messages: &[&MemoryEntry])->Result<String> {
-        info!("当前聊天模型 {}",self.model);
-        let messages:Vec<_>=messages.iter().filter_map(|&msg| entry_to_async_openai(msg).ok()).collect();
-        let request = CreateChatCompletionRequestArgs::default()
-            .model(&self.model)
+    /// 保留状态码和 Retry-After, 交给 FallbackChat 决定重试还是切换 API
+    async fn create(&self,request: CreateChatCompletionRequest)->Result<CreateChatCompletionResponse> {
+        let config = self.client.config();
+        let response = self
+            .http
+            .post(config.url("/chat/completions"))
+            .headers(config.headers())
+            .json(&request)
+            .send()
+            .await
+            .context("发送请求失败")?;
+        let status = response.status();
+        if !status.is_success() {
+            let retry_after = retry_after(response.headers());
+            let body = response.text().await.unwrap_or_default();
+            return Err(ApiFailure::from_status(status.as_u16(), retry_after, body).into());
+        }
+        let body = response.text().await.context("读取回复失败")?;
+        serde_json::from_str(&body).context(format!("解析回复失败: {}", body))
+    }
+
+    async fn request(&self,messages: &[&MemoryEntry],stream: bool)->Result<CreateChatCompletionRequest> {
+        let images = load_images(self.images.as_ref(), messages).await;
+        let messages:Vec<_>=messages.iter().filter_map(|&msg| entry_to_async_openai(msg, &images).ok()).collect();
+        let mut args = CreateChatCompletionRequestArgs::default();
+        args.model(&self.model)
             .messages(messages)
             .n(1)
+            .stream(stream)
             .response_format(ResponseFormat::JsonSchema {
                 json_schema: ResponseFormatJsonSchema {
                     schema: Some(serde_json::from_str(self.schema).context("解析成 Value 失败")?),
@@ -59,16 +113,25 @@ impl Chat for AsyncOpenaiChat {
                     name: "math_reasoning".into(),
                     strict: Some(true),
                 },
-            })
-            .build()
-            .context("构造请求失败")?;
-        
+            });
+        if stream {
+            // 最后一段附带整次请求的用量
+            args.stream_options(ChatCompletionStreamOptions { include_usage: Some(true), include_obfuscation: None });
+        }
+        args.build().context("构造请求失败")
+    }
+}
+
+#[async_trait]
+impl Chat for AsyncOpenaiChat {
+    async fn chat(&self,messages: &[&MemoryEntry])->Result<String> {
+        info!("当前聊天模型 {}",self.model);
+        let request = self.request(messages, false).await?;
         let response = self
-            .client
-            .chat()
             .create(request)
             .await
             .context("获取回复失败")?;
+        report_openai_usage(response.usage.as_ref());
         let content = response
             .choices
             .first()
@@ -79,19 +142,155 @@ impl Chat for AsyncOpenaiChat {
             .context("回复内容为空")?;
         Ok(content)
     }
+
+    async fn chat_stream(&self,messages: &[&MemoryEntry],delta: mpsc::UnboundedSender<String>)->Result<String> {
+        info!("当前聊天模型 {} (流式)",self.model);
+        let request = self.request(messages, true).await?;
+        let mut stream = self
+            .client
+            .chat()
+            .create_stream(request)
+            .await
+            .context("获取回复失败")?;
+        let mut content = String::new();
+        while let Some(response) = stream.next().await {
+            let response = response.context("接收回复失败")?;
+            report_openai_usage(response.usage.as_ref());
+            let Some(chunk) = response.choices.into_iter().next().and_then(|choice| choice.delta.content) else {
+                continue;
+            };
+            content.push_str(&chunk);
+            let _ = delta.send(chunk);
+        }
+        if content.is_empty() {
+            return Err(anyhow::anyhow!("回复内容为空"));
+        }
+        Ok(content)
+    }
+
+    async fn chat_with_tools(&self,messages: &[ToolMessage],tools: &[ToolFunction])->Result<ToolReply> {
+        info!("当前聊天模型 {} (工具调用)",self.model);
+        let entries: Vec<&MemoryEntry> = messages.iter().filter_map(|msg| match msg {
+            ToolMessage::Entry(entry) => Some(entry),
+            _ => None,
+        }).collect();
+        let images = load_images(self.images.as_ref(), &entries).await;
+        let messages=messages.iter().map(|msg| tool_message_to_async_openai(msg, &images)).collect::<Result<Vec<_>>>()?;
+        let tools:Vec<_>=tools.iter().map(|tool| ChatCompletionTools::Function(ChatCompletionTool {
+            function: FunctionObject {
+                name: tool.name.clone(),
+                description: Some(tool.description.clone()),
+                parameters: Some(tool.parameters.clone()),
+                strict: None,
+            },
+        })).collect();
+        let request = CreateChatCompletionRequestArgs::default()
+            .model(&self.model)
+            .messages(messages)
+            .n(1)
+            .tools(tools)
+            .build()
+            .context("构造请求失败")?;
+        let response = self
+            .create(request)
+            .await
+            .context("获取回复失败")?;
+        report_openai_usage(response.usage.as_ref());
+        let message = response
+            .choices
+            .into_iter()
+            .next()
+            .context("回复数量为空")?
+            .message;
+        let calls:Vec<ToolCall> = message
+            .tool_calls
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|call| match call {
+                ChatCompletionMessageToolCalls::Function(call) => Some(call),
+                ChatCompletionMessageToolCalls::Custom(_) => None,
+            })
+            .map(|call| {
+                let args=if call.function.arguments.trim().is_empty() {
+                    Default::default()
+                } else {
+                    serde_json::from_str(&call.function.arguments)
+                        .context(format!("解析工具调用参数失败: {}",call.function.arguments))?
+                };
+                Ok(ToolCall { id: call.id, name: call.function.name, args, signature: None })
+            })
+            .collect::<Result<_>>()?;
+        let text = message.content.filter(|content| !content.trim().is_empty());
+        if calls.is_empty() {
+            return Ok(ToolReply::Text(text.context("回复内容为空")?));
+        }
+        Ok(ToolReply::Calls { text, calls })
+    }
+}
+
+fn report_openai_usage(usage: Option<&CompletionUsage>) {
+    if let Some(usage) = usage {
+        report_usage(TokenUsage {
+            prompt_tokens: usage.prompt_tokens as u64,
+            completion_tokens: usage.completion_tokens as u64,
+        });
+    }
 }
 
-fn entry_to_async_openai(value: &MemoryEntry) -> Result<ChatCompletionRequestMessage> {
+fn tool_message_to_async_openai(value: &ToolMessage, images: &Images) -> Result<ChatCompletionRequestMessage> {
+    let msg = match value {
+        ToolMessage::Entry(entry) => entry_to_async_openai(entry, images)?,
+        ToolMessage::Calls { text, calls } => {
+            let calls = calls
+                .iter()
+                .map(|call| {
+                    Ok(ChatCompletionMessageToolCalls::Function(ChatCompletionMessageToolCall {
+                        id: call.id.clone(),
+                        function: FunctionCall {
+                            name: call.name.clone(),
+                            arguments: serde_json::to_string(&call.args)?,
+                        },
+                    }))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let mut msg = ChatCompletionRequestAssistantMessageArgs::default();
+            if let Some(text) = text {
+                msg.content(text.as_str());
+            }
+            msg.tool_calls(calls).build()?.into()
+        }
+        ToolMessage::Result { call_id, name: _, content } => ChatCompletionRequestToolMessageArgs::default()
+            .tool_call_id(call_id)
+            .content(content.as_str())
+            .build()?
+            .into(),
+    };
+    Ok(msg)
+}
+
+/// 用户发送的图片作为图片内容发送, 其余 (包括没有读取的图片) 转成文字
+fn entry_to_async_openai(value: &MemoryEntry, images: &Images) -> Result<ChatCompletionRequestMessage> {
     let content = value.time.to_string() + ":" + value.content.to_str();
     let msg = match value.role {
         ChatRole::System => ChatCompletionRequestSystemMessageArgs::default()
             .content(content)
             .build()?
             .into(),
-        ChatRole::User => ChatCompletionRequestUserMessageArgs::default()
-            .content(content)
-            .build()?
-            .into(),
+        ChatRole::User => {
+            let mut msg = ChatCompletionRequestUserMessageArgs::default();
+            match image_data(value, images) {
+                Some(image) => msg.content(vec![
+                    ChatCompletionRequestMessageContentPartText::from(value.time.to_string() + ":").into(),
+                    ChatCompletionRequestMessageContentPartImage::from(ImageUrl::from(format!(
+                        "data:image/jpeg;base64,{}",
+                        image
+                    )))
+                    .into(),
+                ]),
+                None => msg.content(content),
+            };
+            msg.build()?.into()
+        }
         ChatRole::Assistant => ChatCompletionRequestAssistantMessageArgs::default()
             .content(content)
             .build()?
@@ -118,12 +317,36 @@ impl AsyncOpenaiEmbed {
 #[async_trait]
 impl Embed for AsyncOpenaiEmbed {
     async fn embed(&self,dimensions: u32, messages: Vec<String>)->Result<Vec<Embedding>> {
-        let mut request=CreateEmbeddingRequest::default();
-        request.model=self.model.clone();
-        request.dimensions=Some(dimensions);
-        request.input=EmbeddingInput::StringArray(messages);
+        let request = CreateEmbeddingRequest {
+            model: self.model.clone(),
+            dimensions: Some(dimensions),
+            input: EmbeddingInput::StringArray(messages),
+            ..Default::default()
+        };
         let embedding=Embeddings::new(&self.client).create(request).await?;
+        report_usage(TokenUsage { prompt_tokens: embedding.usage.prompt_tokens as u64, completion_tokens: 0 });
         let embeddings=embedding.data.into_iter().map(|vec| Embedding::new(vec.embedding)).collect();
         Ok(embeddings)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn image_entry_to_async_openai() -> Result<()> {
+        let entry = MemoryEntry::temp(ChatRole::User, PathBuf::from("cat.png"));
+        let mut images = Images::new();
+        let text = serde_json::to_string(&entry_to_async_openai(&entry, &images)?)?;
+        assert!(!text.contains("image_url"));
+        assert!(text.contains("一张图片."));
+
+        images.insert(PathBuf::from("cat.png"), "aW1hZ2U=".into());
+        let image = serde_json::to_string(&entry_to_async_openai(&entry, &images)?)?;
+        assert!(image.contains("image_url"));
+        assert!(image.contains("data:image/jpeg;base64,aW1hZ2U="));
+        Ok(())
+    }
+}
@@ -0,0 +1,339 @@
+use anyhow::Result;
+use anyhow::anyhow;
+use async_openai::error::OpenAIError;
+use async_openai::error::StreamError;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use heleny_proto::Chat;
+use heleny_proto::MemoryEntry;
+use heleny_proto::ToolFunction;
+use heleny_proto::ToolMessage;
+use heleny_proto::ToolReply;
+use regex::Regex;
+use reqwest::header::HeaderMap;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio::time::error::Elapsed;
+use tokio::time::sleep;
+use tokio::time::timeout;
+use tracing::info;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::config::RetryConfig;
+
+/// 决定是重试, 切换 API 还是直接失败
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    /// 429
+    RateLimit,
+    Timeout,
+    /// 5xx
+    Server,
+    /// 连接失败等, 请求可能没有到达服务器
+    Network,
+    /// 其他错误, 重试也没用, 直接切换 API
+    Fatal,
+}
+
+/// 带分类的请求失败, 后端拿得到状态码时返回它
+#[derive(Debug)]
+pub struct ApiFailure {
+    pub class: ErrorClass,
+    pub retry_after: Option<Duration>,
+    pub message: String,
+}
+
+impl ApiFailure {
+    pub fn from_status(code: u16, retry_after: Option<Duration>, message: String) -> Self {
+        Self {
+            class: status_class(code),
+            retry_after,
+            message: format!("{}: {}", code, message),
+        }
+    }
+}
+
+impl Display for ApiFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ApiFailure {}
+
+fn status_class(code: u16) -> ErrorClass {
+    match code {
+        429 => ErrorClass::RateLimit,
+        408 => ErrorClass::Timeout,
+        500..=599 => ErrorClass::Server,
+        _ => ErrorClass::Fatal,
+    }
+}
+
+/// 沿错误链找出能识别的错误并分类
+pub fn classify(e: &anyhow::Error) -> (ErrorClass, Option<Duration>) {
+    for cause in e.chain() {
+        if let Some(failure) = cause.downcast_ref::<ApiFailure>() {
+            return (failure.class, failure.retry_after);
+        }
+        if cause.downcast_ref::<Elapsed>().is_some() {
+            return (ErrorClass::Timeout, None);
+        }
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return (transport_class(e.is_timeout()), None);
+        }
+        if let Some(e) = cause.downcast_ref::<OpenAIError>() {
+            match e {
+                OpenAIError::Reqwest(e) => return (transport_class(e.is_timeout()), None),
+                OpenAIError::StreamError(e) => match e.as_ref() {
+                    StreamError::ReqwestEventSource(
+                        reqwest_eventsource::Error::InvalidStatusCode(status, response),
+                    ) => {
+                        return (
+                            status_class(status.as_u16()),
+                            retry_after(response.headers()),
+                        );
+                    }
+                    StreamError::ReqwestEventSource(reqwest_eventsource::Error::Transport(
+                        e,
+                    )) => return (transport_class(e.is_timeout()), None),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        if let Some(e) = cause.downcast_ref::<gemini_rust::ClientError>() {
+            match e {
+                gemini_rust::ClientError::BadResponse { code, description } => {
+                    return (
+                        status_class(*code),
+                        description.as_deref().and_then(retry_delay),
+                    );
+                }
+                gemini_rust::ClientError::PerformRequest { source, .. }
+                | gemini_rust::ClientError::PerformRequestNew { source } => {
+                    return (transport_class(source.is_timeout()), None);
+                }
+                gemini_rust::ClientError::BadPart { .. } => return (ErrorClass::Network, None),
+                _ => {}
+            }
+        }
+    }
+    (ErrorClass::Fatal, None)
+}
+
+fn transport_class(is_timeout: bool) -> ErrorClass {
+    if is_timeout {
+        ErrorClass::Timeout
+    } else {
+        ErrorClass::Network
+    }
+}
+
+/// 解析 Retry-After (秒数或 HTTP 日期), OpenAI 兼容服务还可能给出 retry-after-ms
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(ms) = headers
+        .get("retry-after-ms")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<f64>().ok())
+    {
+        return Some(Duration::from_secs_f64(ms.max(0.0) / 1000.0));
+    }
+    let value = headers.get("retry-after")?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<f64>() {
+        return Some(Duration::from_secs_f64(secs.max(0.0)));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+static RETRY_DELAY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#""retryDelay"\s*:\s*"(\d+(?:\.\d+)?)s""#).expect("retryDelay 正则无效")
+});
+
+/// Gemini 把重试时间放在错误详情里, 例如 "retryDelay": "30s"
+fn retry_delay(description: &str) -> Option<Duration> {
+    let secs = RETRY_DELAY.captures(description)?.get(1)?.as_str().parse::<f64>().ok()?;
+    Some(Duration::from_secs_f64(secs))
+}
+
+#[derive(Debug)]
+struct Provider {
+    /// 用于日志, 例如 "grok-4 (api 0)"
+    label: String,
+    chat: Box<dyn Chat>,
+}
+
+/// 按顺序组合多个 API, 按错误类别重试, 重试用完后切换到下一个
+#[derive(Debug)]
+pub struct FallbackChat {
+    providers: Vec<Provider>,
+    retry: RetryConfig,
+    /// 单次请求的超时
+    attempt_timeout: Duration,
+    /// 整次调用的超时, 到时不再重试或切换 API
+    total_timeout: Duration,
+    last_provider: Mutex<Option<String>>,
+}
+
+impl FallbackChat {
+    pub fn new(retry: RetryConfig, attempt_timeout: Duration, total_timeout: Duration) -> Self {
+        Self {
+            providers: Vec::new(),
+            retry,
+            attempt_timeout,
+            total_timeout,
+            last_provider: Mutex::new(None),
+        }
+    }
+
+    pub fn with_provider(mut self, label: String, chat: Box<dyn Chat>) -> Self {
+        self.providers.push(Provider { label, chat });
+        self
+    }
+
+    fn retries(&self, class: ErrorClass) -> u32 {
+        match class {
+            ErrorClass::RateLimit => self.retry.rate_limit,
+            ErrorClass::Timeout => self.retry.timeout,
+            ErrorClass::Server => self.retry.server,
+            ErrorClass::Network => self.retry.network,
+            ErrorClass::Fatal => 0,
+        }
+    }
+
+    /// 依次在各 API 上执行 call; output 为 true 表示已有内容发给前端, 此时不再重试
+    async fn run<'a, T, F, Fut>(&'a self, action: &str, output: &AtomicBool, mut call: F) -> Result<T>
+    where
+        F: FnMut(&'a dyn Chat) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let deadline = Instant::now() + self.total_timeout;
+        let mut errors = Vec::new();
+        for (index, provider) in self.providers.iter().enumerate() {
+            let mut used: HashMap<ErrorClass, u32> = HashMap::new();
+            let mut count = 0;
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    warn!("{} 超过总超时 {:?}, 不再重试", action, self.total_timeout);
+                    return Err(anyhow!(
+                        "{} 超过总超时 {:?}: {}",
+                        action,
+                        self.total_timeout,
+                        errors.join("; ")
+                    ));
+                }
+                let attempt = self.attempt_timeout.min(remaining);
+                let result = match timeout(attempt, call(provider.chat.as_ref())).await {
+                    Ok(result) => result,
+                    Err(e) => Err(e.into()),
+                };
+                let e = match result {
+                    Ok(value) => {
+                        if index > 0 || count > 0 {
+                            info!("{} 由 {} 完成", action, provider.label);
+                        }
+                        if let Ok(mut last_provider) = self.last_provider.lock() {
+                            *last_provider = Some(provider.label.clone());
+                        }
+                        return Ok(value);
+                    }
+                    Err(e) => e,
+                };
+                if output.load(Ordering::SeqCst) {
+                    warn!("{} 在 {} 输出中途失败, 已输出的内容无法撤回: {:#}", action, provider.label, e);
+                    return Err(e);
+                }
+                let (class, retry_after) = classify(&e);
+                let times = used.entry(class).or_default();
+                let wait = retry_after.unwrap_or(self.retry.backoff(count));
+                if *times >= self.retries(class) || wait > Duration::from_millis(self.retry.max_backoff_ms) {
+                    warn!("{} 在 {} 失败 ({:?}), 切换到下一个 API: {:#}", action, provider.label, class, e);
+                    errors.push(format!("{}: {:#}", provider.label, e));
+                    break;
+                }
+                if Instant::now() + wait >= deadline {
+                    warn!("{} 在 {} 失败 ({:?}), 等待 {:?} 会超过总超时, 不再重试: {:#}", action, provider.label, class, wait, e);
+                    errors.push(format!("{}: {:#}", provider.label, e));
+                    return Err(anyhow!("{} 超过总超时 {:?}: {}", action, self.total_timeout, errors.join("; ")));
+                }
+                *times += 1;
+                count += 1;
+                warn!(
+                    "{} 在 {} 失败 ({:?}), {:?} 后第 {} 次重试: {:#}",
+                    action, provider.label, class, wait, times, e
+                );
+                sleep(wait).await;
+            }
+        }
+        Err(anyhow!("{} 在所有 API 上都失败了: {}", action, errors.join("; ")))
+    }
+}
+
+#[async_trait]
+impl Chat for FallbackChat {
+    async fn chat(&self, messages: &[&MemoryEntry]) -> Result<String> {
+        self.run("聊天", &AtomicBool::new(false), |chat| chat.chat(messages))
+            .await
+    }
+
+    /// 每次尝试经过中间通道转发, 已有内容发出后失败的不再重试
+    async fn chat_stream(
+        &self,
+        messages: &[&MemoryEntry],
+        delta: mpsc::UnboundedSender<String>,
+    ) -> Result<String> {
+        let output = AtomicBool::new(false);
+        self.run("流式聊天", &output, |chat| {
+            let delta = delta.clone();
+            let output = &output;
+            async move {
+                let (tx, mut rx) = mpsc::unbounded_channel();
+                let forward = async {
+                    while let Some(chunk) = rx.recv().await {
+                        output.store(true, Ordering::SeqCst);
+                        let _ = delta.send(chunk);
+                    }
+                };
+                let (result, _) = tokio::join!(chat.chat_stream(messages, tx), forward);
+                result
+            }
+        })
+        .await
+    }
+
+    async fn chat_with_tools(
+        &self,
+        messages: &[ToolMessage],
+        tools: &[ToolFunction],
+    ) -> Result<ToolReply> {
+        self.run("工具调用", &AtomicBool::new(false), |chat| {
+            chat.chat_with_tools(messages, tools)
+        })
+        .await
+    }
+
+    fn provider(&self) -> Option<String> {
+        self.last_provider
+            .lock()
+            .ok()
+            .and_then(|last_provider| last_provider.clone())
+    }
+
+    fn set_task(&self, task: Uuid) {
+        for provider in &self.providers {
+            provider.chat.set_task(task);
+        }
+    }
+}
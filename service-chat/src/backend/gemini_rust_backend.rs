@@ -1,54 +1,80 @@
 use std::fmt::Debug;
+use anyhow::Context;
 use anyhow::Result;
 use anyhow::anyhow;
 use async_trait::async_trait;
 use heleny_proto::ChatRole;
+use gemini_rust::Content;
+use gemini_rust::ContentBuilder;
+use gemini_rust::FunctionCall;
+use gemini_rust::FunctionDeclaration;
 use gemini_rust::Gemini;
+use gemini_rust::Message;
+use gemini_rust::Part;
+use gemini_rust::Role;
+use gemini_rust::Tool;
+use gemini_rust::UsageMetadata;
+use serde_json::Value;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
 use tracing::info;
 use crate::ApiConfig;
+use crate::backend::image_loader::ImageLoader;
+use crate::backend::image_loader::Images;
+use crate::backend::image_loader::image_data;
+use crate::backend::image_loader::load_images;
 use heleny_proto::Chat;
 use heleny_proto::MemoryEntry;
+use heleny_proto::ToolCall;
+use heleny_proto::ToolFunction;
+use heleny_proto::ToolMessage;
+use heleny_proto::ToolReply;
+use heleny_proto::TokenUsage;
+use heleny_proto::report_usage;
 
 #[derive(Debug)]
 pub struct GeminiChat {
     api_config: ApiConfig,
+    /// 只有支持图片输入的 API 才有
+    images: Option<ImageLoader>,
 }
 
 impl GeminiChat {
-    pub fn new(api_config: ApiConfig) -> Self {
+    pub fn new(api_config: ApiConfig, images: Option<ImageLoader>) -> Self {
         Self {
             api_config,
+            images,
         }
     }
-}
 
-#[async_trait]
-impl Chat for GeminiChat {
-    async fn chat(&self,messages: &[&MemoryEntry])->Result<String> {
-        let ApiConfig { base_url:_, model, api_key_env_var:_, api_key }= self.api_config.clone();
+    fn client(&self)->Result<Gemini> {
+        let ApiConfig { model, api_key, .. }= self.api_config.clone();
         info!("当前聊天模型 {}",model);
         let model = if model.starts_with("models/") {
             model
         } else {
             format!("models/{}", model)
         };
-        let client = Gemini::with_model(api_key, model)?;
-        let mut conversation_builder = client
+        Ok(Gemini::with_model(api_key, model)?)
+    }
+
+    async fn builder(&self,messages: &[&MemoryEntry])->Result<ContentBuilder> {
+        let images = load_images(self.images.as_ref(), messages).await;
+        let mut conversation_builder = self.client()?
         .generate_content();
         for msg in messages {
-            match msg.role {
-                ChatRole::System=>{
-                    conversation_builder=conversation_builder.with_user_message(entry_to_string(*msg))
-                }
-                ChatRole::Assistant=>{
-                    conversation_builder=conversation_builder.with_model_message(entry_to_string(*msg))
-                }
-                ChatRole::User=>{
-                    conversation_builder=conversation_builder.with_user_message(entry_to_string(*msg))
-                }
-            }
+            conversation_builder=with_entry(conversation_builder, msg, &images);
         }
-        let resp=conversation_builder.execute().await?;
+        Ok(conversation_builder)
+    }
+}
+
+#[async_trait]
+impl Chat for GeminiChat {
+    async fn chat(&self,messages: &[&MemoryEntry])->Result<String> {
+        let resp=self.builder(messages).await?.execute().await?;
+        report_gemini_usage(resp.usage_metadata.as_ref());
         let text = resp.text();
         if text.trim().is_empty() {
             return Err(anyhow!(
@@ -58,10 +84,154 @@ impl Chat for GeminiChat {
         }
         Ok(text)
     }
+
+    async fn chat_stream(&self,messages: &[&MemoryEntry],delta: mpsc::UnboundedSender<String>)->Result<String> {
+        let mut stream=self.builder(messages).await?.execute_stream().await?;
+        let mut text = String::new();
+        let mut prompt_feedback = None;
+        // 每段的用量是累计值, 结束后只上报最后一次
+        let mut usage_metadata = None;
+        while let Some(resp) = stream.next().await {
+            let resp = resp?;
+            let chunk = resp.text();
+            if resp.prompt_feedback.is_some() {
+                prompt_feedback = resp.prompt_feedback;
+            }
+            if resp.usage_metadata.is_some() {
+                usage_metadata = resp.usage_metadata;
+            }
+            if chunk.is_empty() {
+                continue;
+            }
+            text.push_str(&chunk);
+            let _ = delta.send(chunk);
+        }
+        report_gemini_usage(usage_metadata.as_ref());
+        if text.trim().is_empty() {
+            return Err(anyhow!(
+                "Gemini 返回空响应: prompt_feedback={:?}",
+                prompt_feedback
+            ));
+        }
+        Ok(text)
+    }
+
+    async fn chat_with_tools(&self,messages: &[ToolMessage],tools: &[ToolFunction])->Result<ToolReply> {
+        let declarations=tools.iter().map(|tool| serde_json::from_value(json!({
+            "name": tool.name,
+            "description": tool.description,
+            "parameters": tool.parameters,
+        }))).collect::<Result<Vec<FunctionDeclaration>,_>>().context("构造函数声明失败")?;
+        let entries: Vec<&MemoryEntry> = messages.iter().filter_map(|msg| match msg {
+            ToolMessage::Entry(entry) => Some(entry),
+            _ => None,
+        }).collect();
+        let images = load_images(self.images.as_ref(), &entries).await;
+        let mut conversation_builder = self.client()?
+        .generate_content()
+        .with_tool(Tool::with_functions(declarations));
+        for msg in messages {
+            conversation_builder=match msg {
+                ToolMessage::Entry(entry)=>with_entry(conversation_builder, entry, &images),
+                ToolMessage::Calls { text, calls }=>{
+                    let mut parts:Vec<Part>=text.iter().map(|text| Part::Text { text: text.clone(), thought: None, thought_signature: None }).collect();
+                    parts.extend(calls.iter().map(|call| Part::FunctionCall {
+                        function_call: FunctionCall::new(call.name.clone(), json!(call.args)),
+                        thought_signature: call.signature.clone(),
+                    }));
+                    conversation_builder.with_message(Message {
+                        content: Content { parts: Some(parts), role: Some(Role::Model) },
+                        role: Role::Model,
+                    })
+                }
+                ToolMessage::Result { call_id:_, name, content }=>{
+                    conversation_builder.with_function_response(name, json!({ "result": content }))?
+                }
+            };
+        }
+        let resp=conversation_builder.execute().await?;
+        report_gemini_usage(resp.usage_metadata.as_ref());
+        let parts=resp.candidates.into_iter().next().and_then(|candidate| candidate.content.parts).unwrap_or_default();
+        let mut text=String::new();
+        let mut calls=Vec::new();
+        for part in parts {
+            match part {
+                Part::Text { text: chunk, thought, .. } if thought != Some(true) => text.push_str(&chunk),
+                Part::FunctionCall { function_call, thought_signature }=>{
+                    let args=match function_call.args {
+                        Value::Null=>Default::default(),
+                        args=>serde_json::from_value(args).context("解析工具调用参数失败")?,
+                    };
+                    calls.push(ToolCall {
+                        // Gemini 按函数名对应结果, 这里只需要在本轮内唯一
+                        id: format!("{}-{}", function_call.name, calls.len()),
+                        name: function_call.name,
+                        args,
+                        signature: thought_signature.or(function_call.thought_signature),
+                    });
+                }
+                _=>{}
+            }
+        }
+        let text=Some(text).filter(|text| !text.trim().is_empty());
+        if calls.is_empty() {
+            return Ok(ToolReply::Text(text.ok_or_else(|| anyhow!("Gemini 返回空响应: prompt_feedback={:?}", resp.prompt_feedback))?));
+        }
+        Ok(ToolReply::Calls { text, calls })
+    }
+}
+
+fn report_gemini_usage(usage: Option<&UsageMetadata>) {
+    if let Some(usage) = usage {
+        report_usage(TokenUsage {
+            prompt_tokens: usage.prompt_token_count.unwrap_or_default().max(0) as u64,
+            completion_tokens: (usage.candidates_token_count.unwrap_or_default() + usage.thoughts_token_count.unwrap_or_default()).max(0) as u64,
+        });
+    }
+}
+
+/// 用户发送的图片作为 inline data 发送, 其余 (包括没有读取的图片) 转成文字
+fn with_entry(conversation_builder: ContentBuilder, msg: &MemoryEntry, images: &Images) -> ContentBuilder {
+    match msg.role {
+        ChatRole::System=>{
+            conversation_builder.with_user_message(entry_to_string(msg))
+        }
+        ChatRole::Assistant=>{
+            conversation_builder.with_model_message(entry_to_string(msg))
+        }
+        ChatRole::User=>match image_data(msg, images) {
+            Some(image)=>conversation_builder
+                .with_user_message(msg.time.to_string() + ":")
+                .with_inline_data(image, "image/jpeg"),
+            None=>conversation_builder.with_user_message(entry_to_string(msg)),
+        }
+    }
 }
 
 fn entry_to_string(value: &MemoryEntry) -> String {
-    let content = value.time.to_string() + ":" + value.content.to_str();
-    content
+    value.time.to_string() + ":" + value.content.to_str()
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn image_entry_to_gemini() -> Result<()> {
+        let entry = MemoryEntry::temp(ChatRole::User, PathBuf::from("cat.png"));
+        let mut images = Images::new();
+        let builder = with_entry(Gemini::new("test")?.generate_content(), &entry, &images);
+        let text = serde_json::to_string(&builder.contents)?;
+        assert!(!text.contains("inlineData"));
+        assert!(text.contains("一张图片."));
+
+        images.insert(PathBuf::from("cat.png"), "aW1hZ2U=".into());
+        let builder = with_entry(Gemini::new("test")?.generate_content(), &entry, &images);
+        let image = serde_json::to_string(&builder.contents)?;
+        assert!(image.contains("inlineData"));
+        assert!(image.contains("aW1hZ2U="));
+        Ok(())
+    }
+}
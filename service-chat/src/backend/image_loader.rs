@@ -0,0 +1,80 @@
+use base64::prelude::*;
+use heleny_bus::endpoint::Endpoint;
+use heleny_proto::MemoryContent;
+use heleny_proto::MemoryEntry;
+use heleny_service::get_image_via_fs_service;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tracing::warn;
+
+/// 图片路径 -> base64 编码的 JPEG 缩略图
+pub type Images = HashMap<PathBuf, String>;
+
+/// 通过 FsService 读取消息里的图片, 只给支持图片输入的 API 使用
+///
+/// 同一角色的各个 API 共享一个 loader, 重试和切换 API 时复用上一次读到的图片
+#[derive(Debug, Clone)]
+pub struct ImageLoader {
+    endpoint: Arc<Endpoint>,
+    last: Arc<Mutex<Images>>,
+}
+
+impl ImageLoader {
+    pub fn new(endpoint: Endpoint) -> Self {
+        Self {
+            endpoint: Arc::new(endpoint),
+            last: Arc::new(Mutex::new(Images::new())),
+        }
+    }
+
+    fn cached(&self, path: &Path) -> Option<String> {
+        self.last.lock().ok()?.get(path).cloned()
+    }
+
+    /// 读取失败的图片不在结果里, 由后端退回文字描述
+    pub async fn load(&self, entries: &[&MemoryEntry]) -> Images {
+        let mut images = Images::new();
+        for &entry in entries {
+            let MemoryContent::Image(path) = &entry.content else {
+                continue;
+            };
+            if images.contains_key(path) {
+                continue;
+            }
+            if let Some(image) = self.cached(path) {
+                images.insert(path.clone(), image);
+                continue;
+            }
+            match get_image_via_fs_service(&self.endpoint, path).await {
+                Ok(image) => {
+                    images.insert(path.clone(), BASE64_STANDARD.encode(image));
+                }
+                Err(e) => warn!("读取图片 {} 失败, 改用文字描述: {:#}", path.display(), e),
+            }
+        }
+        // 只保留本次请求的图片
+        if let Ok(mut last) = self.last.lock() {
+            *last = images.clone();
+        }
+        images
+    }
+}
+
+/// 没有 loader 时不读取图片
+pub async fn load_images(loader: Option<&ImageLoader>, entries: &[&MemoryEntry]) -> Images {
+    match loader {
+        Some(loader) => loader.load(entries).await,
+        None => Images::new(),
+    }
+}
+
+/// entry 是已读取的图片时返回其 base64 数据
+pub fn image_data<'a>(entry: &MemoryEntry, images: &'a Images) -> Option<&'a str> {
+    match &entry.content {
+        MemoryContent::Image(path) => images.get(path).map(String::as_str),
+        _ => None,
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Local;
+use heleny_bus::endpoint::Endpoint;
+use heleny_proto::Chat;
+use heleny_proto::Embed;
+use heleny_proto::Embedding;
+use heleny_proto::MemoryEntry;
+use heleny_proto::STATS_SERVICE;
+use heleny_proto::TokenUsage;
+use heleny_proto::ToolFunction;
+use heleny_proto::ToolMessage;
+use heleny_proto::ToolReply;
+use heleny_proto::Tokenizer;
+use heleny_proto::UsageRecord;
+use heleny_proto::with_usage;
+use heleny_service::StatsServiceMessage;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tracing::debug;
+use uuid::Uuid;
+
+/// 把调用的用量发给 StatsService, 同一角色的各个 API 共享
+#[derive(Debug, Clone)]
+pub struct UsageMeter {
+    role: String,
+    task: Arc<Mutex<Option<Uuid>>>,
+    endpoint: Arc<Endpoint>,
+}
+
+impl UsageMeter {
+    pub fn new(role: &str, endpoint: Endpoint) -> Self {
+        Self {
+            role: role.to_string(),
+            task: Arc::new(Mutex::new(None)),
+            endpoint: Arc::new(endpoint),
+        }
+    }
+
+    fn set_task(&self, task: Uuid) {
+        if let Ok(mut current) = self.task.lock() {
+            *current = Some(task);
+        }
+    }
+
+    /// 后端没有上报用量时使用 estimate 估算
+    async fn record(
+        &self,
+        model: &str,
+        start: Instant,
+        usage: Option<TokenUsage>,
+        estimate: impl FnOnce() -> TokenUsage,
+    ) {
+        let record = UsageRecord {
+            time: Local::now(),
+            role: self.role.clone(),
+            model: model.to_string(),
+            task: self.task.lock().ok().and_then(|task| *task),
+            estimated: usage.is_none(),
+            usage: usage.unwrap_or_else(estimate),
+            latency_ms: start.elapsed().as_millis() as u64,
+        };
+        if let Err(e) = self
+            .endpoint
+            .send(STATS_SERVICE, StatsServiceMessage::RecordUsage { record })
+            .await
+        {
+            debug!("发送用量记录失败: {}", e);
+        }
+    }
+}
+
+/// 记录每次成功调用的用量和耗时
+#[derive(Debug)]
+pub struct MeteredChat {
+    inner: Box<dyn Chat>,
+    model: String,
+    /// 后端没有上报用量时用于估算
+    tokenizer: Tokenizer,
+    meter: UsageMeter,
+}
+
+impl MeteredChat {
+    pub fn new(inner: Box<dyn Chat>, model: String, tokenizer: Tokenizer, meter: UsageMeter) -> Self {
+        Self {
+            inner,
+            model,
+            tokenizer,
+            meter,
+        }
+    }
+}
+
+#[async_trait]
+impl Chat for MeteredChat {
+    async fn chat(&self, messages: &[&MemoryEntry]) -> Result<String> {
+        let start = Instant::now();
+        let (response, usage) = with_usage(self.inner.chat(messages)).await;
+        let response = response?;
+        self.meter
+            .record(&self.model, start, usage, || {
+                estimate(self.tokenizer, messages, &response)
+            })
+            .await;
+        Ok(response)
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[&MemoryEntry],
+        delta: mpsc::UnboundedSender<String>,
+    ) -> Result<String> {
+        let start = Instant::now();
+        let (response, usage) = with_usage(self.inner.chat_stream(messages, delta)).await;
+        let response = response?;
+        self.meter
+            .record(&self.model, start, usage, || {
+                estimate(self.tokenizer, messages, &response)
+            })
+            .await;
+        Ok(response)
+    }
+
+    async fn chat_with_tools(
+        &self,
+        messages: &[ToolMessage],
+        tools: &[ToolFunction],
+    ) -> Result<ToolReply> {
+        let start = Instant::now();
+        let (reply, usage) = with_usage(self.inner.chat_with_tools(messages, tools)).await;
+        let reply = reply?;
+        self.meter
+            .record(&self.model, start, usage, || {
+                let tokenizer = self.tokenizer;
+                let completion = match &reply {
+                    ToolReply::Text(text) => tokenizer.count(text),
+                    ToolReply::Calls { text, calls } => {
+                        tokenizer.tool_message_tokens(&ToolMessage::Calls {
+                            text: text.clone(),
+                            calls: calls.clone(),
+                        })
+                    }
+                };
+                TokenUsage {
+                    prompt_tokens: messages
+                        .iter()
+                        .map(|message| tokenizer.tool_message_tokens(message))
+                        .sum::<usize>() as u64,
+                    completion_tokens: completion as u64,
+                }
+            })
+            .await;
+        Ok(reply)
+    }
+
+    fn provider(&self) -> Option<String> {
+        self.inner.provider()
+    }
+
+    fn set_task(&self, task: Uuid) {
+        self.meter.set_task(task);
+    }
+}
+
+fn estimate(tokenizer: Tokenizer, messages: &[&MemoryEntry], response: &str) -> TokenUsage {
+    TokenUsage {
+        prompt_tokens: messages
+            .iter()
+            .map(|&entry| tokenizer.entry_tokens(entry))
+            .sum::<usize>() as u64,
+        completion_tokens: tokenizer.count(response) as u64,
+    }
+}
+
+#[derive(Debug)]
+pub struct MeteredEmbed {
+    inner: Box<dyn Embed>,
+    model: String,
+    tokenizer: Tokenizer,
+    meter: UsageMeter,
+}
+
+impl MeteredEmbed {
+    pub fn new(inner: Box<dyn Embed>, model: String, meter: UsageMeter) -> Self {
+        Self {
+            inner,
+            tokenizer: Tokenizer::from_name(&model),
+            model,
+            meter,
+        }
+    }
+}
+
+#[async_trait]
+impl Embed for MeteredEmbed {
+    async fn embed(&self, dimensions: u32, messages: Vec<String>) -> Result<Vec<Embedding>> {
+        let start = Instant::now();
+        let prompt_tokens = messages.iter().map(|message| self.tokenizer.count(message)).sum::<usize>();
+        let (embeddings, usage) = with_usage(self.inner.embed(dimensions, messages)).await;
+        let embeddings = embeddings?;
+        self.meter
+            .record(&self.model, start, usage, || TokenUsage {
+                prompt_tokens: prompt_tokens as u64,
+                completion_tokens: 0,
+            })
+            .await;
+        Ok(embeddings)
+    }
+}
@@ -0,0 +1,254 @@
+use anyhow::Context;
+use anyhow::Result;
+use async_trait::async_trait;
+use heleny_proto::Chat;
+use heleny_proto::EXECUTOR_SCHEMA;
+use heleny_proto::Embed;
+use heleny_proto::Embedding;
+use heleny_proto::HELENY_SCHEMA;
+use heleny_proto::MemoryEntry;
+use heleny_proto::PLANNER_SCHEMA;
+use heleny_proto::ToolCall;
+use heleny_proto::ToolFunction;
+use heleny_proto::ToolIntent;
+use heleny_proto::ToolMessage;
+use heleny_proto::ToolReply;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+use tracing::debug;
+
+/// base_url 以此开头时使用脚本回复, 后面接脚本路径
+pub const MOCK_SCHEME: &str = "mock://";
+/// 流式输出时每段的字符数和间隔
+const MOCK_STREAM_CHUNK: usize = 4;
+const MOCK_STREAM_INTERVAL: Duration = Duration::from_millis(30);
+
+/// 按 schema 区分的模型角色
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MockRole {
+    Heleny,
+    Planner,
+    Executor,
+}
+
+impl MockRole {
+    fn from_schema(schema: &str) -> Option<Self> {
+        if schema == HELENY_SCHEMA {
+            Some(Self::Heleny)
+        } else if schema == PLANNER_SCHEMA {
+            Some(Self::Planner)
+        } else if schema == EXECUTOR_SCHEMA {
+            Some(Self::Executor)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MockRuleConfig {
+    role: Option<MockRole>,
+    pattern: String,
+    reply: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct MockScriptConfig {
+    #[serde(default)]
+    rules: Vec<MockRuleConfig>,
+    default: Option<Value>,
+}
+
+#[derive(Debug)]
+struct MockRule {
+    role: Option<MockRole>,
+    pattern: Regex,
+    reply: String,
+}
+
+/// 离线的聊天模型, 用正则匹配最后一条消息, 返回脚本里预设的回复
+#[derive(Debug)]
+pub struct MockChat {
+    role: Option<MockRole>,
+    rules: Vec<MockRule>,
+    default: Option<String>,
+}
+
+impl MockChat {
+    pub async fn load<P: AsRef<Path>>(path: P, schema: &str) -> Result<Self> {
+        let script = tokio::fs::read_to_string(path.as_ref())
+            .await
+            .context(format!("读取 mock 脚本 {:?} 失败", path.as_ref()))?;
+        Self::parse(&script, schema)
+    }
+
+    pub fn parse(script: &str, schema: &str) -> Result<Self> {
+        let config: MockScriptConfig =
+            serde_json::from_str(script).context("解析 mock 脚本失败")?;
+        let rules = config
+            .rules
+            .into_iter()
+            .map(|rule| {
+                Ok(MockRule {
+                    role: rule.role,
+                    pattern: Regex::new(&rule.pattern)
+                        .context(format!("mock 脚本中的正则 {} 不合法", rule.pattern))?,
+                    reply: reply_to_string(rule.reply),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            role: MockRole::from_schema(schema),
+            rules,
+            default: config.default.map(reply_to_string),
+        })
+    }
+
+    fn reply(&self, messages: &[&MemoryEntry]) -> Result<&String> {
+        self.match_reply(last_message(messages))
+    }
+
+    fn match_reply(&self, last: &str) -> Result<&String> {
+        let reply = self
+            .rules
+            .iter()
+            .filter(|rule| rule.role.is_none_or(|role| Some(role) == self.role))
+            .find(|rule| rule.pattern.is_match(last))
+            .map(|rule| &rule.reply)
+            .or(self.default.as_ref())
+            .context(format!("mock 脚本没有匹配 {:?} 的规则: {}", self.role, last))?;
+        debug!("mock 回复 {:?}: {}", self.role, reply);
+        Ok(reply)
+    }
+}
+
+#[async_trait]
+impl Chat for MockChat {
+    async fn chat(&self, messages: &[&MemoryEntry]) -> Result<String> {
+        self.reply(messages).cloned()
+    }
+
+    /// 把预设回复切成小段逐段发送, 模拟流式输出
+    async fn chat_stream(
+        &self,
+        messages: &[&MemoryEntry],
+        delta: mpsc::UnboundedSender<String>,
+    ) -> Result<String> {
+        let reply = self.reply(messages)?.clone();
+        let chars: Vec<char> = reply.chars().collect();
+        for chunk in chars.chunks(MOCK_STREAM_CHUNK) {
+            let _ = delta.send(chunk.iter().collect());
+            sleep(MOCK_STREAM_INTERVAL).await;
+        }
+        Ok(reply)
+    }
+
+    /// 回复按 ToolIntent 解析, 有 tool 和 command 时转成对应函数的调用
+    async fn chat_with_tools(
+        &self,
+        messages: &[ToolMessage],
+        tools: &[ToolFunction],
+    ) -> Result<ToolReply> {
+        let last = messages
+            .iter()
+            .rev()
+            .find_map(|message| match message {
+                ToolMessage::Entry(entry) if entry.content.to_str().trim() != "." => {
+                    Some(entry.content.to_str())
+                }
+                ToolMessage::Result { content, .. } => Some(content.as_str()),
+                _ => None,
+            })
+            .unwrap_or("");
+        let reply = self.match_reply(last)?;
+        let intent: ToolIntent = serde_json::from_str(reply)
+            .context(format!("mock 回复不是 ToolIntent: {}", reply))?;
+        let (Some(tool), Some(command)) = (intent.tool, intent.command) else {
+            return Ok(ToolReply::Text(intent.reason));
+        };
+        let function = tools
+            .iter()
+            .find(|function| function.tool == tool && function.command == command)
+            .context(format!("没有提供 {} 的 {} 命令", tool, command))?;
+        let id = messages
+            .iter()
+            .filter(|message| matches!(message, ToolMessage::Calls { .. }))
+            .count();
+        Ok(ToolReply::Calls {
+            text: Some(intent.reason),
+            calls: vec![ToolCall {
+                id: format!("mock-{}", id),
+                name: function.name.clone(),
+                args: intent.args,
+                signature: None,
+            }],
+        })
+    }
+}
+
+/// 最后一条消息, 跳过 HelenyModel 在末尾填充的 "."
+fn last_message<'a>(messages: &[&'a MemoryEntry]) -> &'a str {
+    messages
+        .iter()
+        .rev()
+        .map(|entry| entry.content.to_str())
+        .find(|content| content.trim() != ".")
+        .unwrap_or("")
+}
+
+/// 字符串原样返回, 其他 JSON 值序列化后返回
+fn reply_to_string(reply: Value) -> String {
+    match reply {
+        Value::String(reply) => reply,
+        reply => reply.to_string(),
+    }
+}
+
+/// 离线的嵌入模型, 把字符二元组哈希到各维度, 相同文本总是得到相同向量
+#[derive(Debug)]
+pub struct MockEmbed;
+
+#[async_trait]
+impl Embed for MockEmbed {
+    async fn embed(&self, dimensions: u32, messages: Vec<String>) -> Result<Vec<Embedding>> {
+        let dimensions = (dimensions as usize).max(1);
+        Ok(messages
+            .iter()
+            .map(|message| Embedding::new(hash_embedding(message, dimensions)))
+            .collect())
+    }
+}
+
+fn hash_embedding(message: &str, dimensions: usize) -> Vec<f32> {
+    let mut vector = vec![0.0; dimensions];
+    let chars: Vec<char> = message.chars().filter(|c| !c.is_whitespace()).collect();
+    let grams: Vec<&[char]> = match chars.len() {
+        0 => Vec::new(),
+        1 => vec![&chars[..]],
+        _ => chars.windows(2).collect(),
+    };
+    for gram in grams {
+        let hash = fnv1a(gram);
+        let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        vector[(hash % dimensions as u64) as usize] += sign;
+    }
+    if vector.iter().all(|v| *v == 0.0) {
+        vector[0] = 1.0;
+    }
+    vector
+}
+
+fn fnv1a(chars: &[char]) -> u64 {
+    chars
+        .iter()
+        .flat_map(|c| (*c as u32).to_le_bytes())
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
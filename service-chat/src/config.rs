@@ -1,25 +1,127 @@
+use heleny_proto::ContextBudget;
+use heleny_proto::Tokenizer;
 use serde::Deserialize;
+use serde::Deserializer;
 use serde::Serialize;
 use std::path::PathBuf;
+use std::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RoleConfig {
-    pub api: usize,
+    /// ChatConfig.api 的索引, 按顺序尝试, 前一个失败时切换到下一个
+    #[serde(deserialize_with = "one_or_many")]
+    pub api: Vec<usize>,
     pub preset_path: PathBuf,
     #[serde(default)]
     pub preset: String,
     pub persona_path: Option<PathBuf>,
+    /// 单次请求的超时
     pub timeout_secs: u64,
+    /// 整次调用 (含重试和切换 API) 的超时, 不填时为 timeout_secs 的两倍
+    #[serde(default)]
+    pub total_timeout_secs: Option<u64>,
     #[serde(default)]
     pub rag_num: usize,
+    /// 只对 executor 有效, 使用后端的原生工具调用代替 ToolIntent JSON
+    #[serde(default)]
+    pub native_tools: bool,
+    /// 上下文的 token 预算, 超出时丢弃最旧的历史
+    #[serde(default = "default_context_tokens")]
+    pub context_tokens: usize,
+    /// 单条消息 (如工具结果, 任务日志) 的 token 上限, 超出时截断
+    #[serde(default = "default_message_tokens")]
+    pub message_tokens: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl RoleConfig {
+    /// 按第一个 API 的分词器计数, 切换到后面的 API 时不再重新计算
+    pub fn budget(&self, api: &[ApiConfig]) -> ContextBudget {
+        let tokenizer = self
+            .api
+            .first()
+            .and_then(|&index| api.get(index))
+            .map(ApiConfig::tokenizer)
+            .unwrap_or_default();
+        ContextBudget::new(self.context_tokens, self.message_tokens).with_tokenizer(tokenizer)
+    }
+
+    pub fn total_timeout_secs(&self) -> u64 {
+        self.total_timeout_secs
+            .unwrap_or(self.timeout_secs.saturating_mul(2))
+    }
+}
+
+fn default_context_tokens() -> usize {
+    ContextBudget::default().max_tokens
+}
+
+fn default_message_tokens() -> usize {
+    ContextBudget::default().max_message_tokens
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ChatConfig {
     pub api: Vec<ApiConfig>,
     pub heleny: RoleConfig,
     pub planner: RoleConfig,
     pub executor: RoleConfig,
+    #[serde(default)]
+    pub retry: RetryConfig,
+}
+
+/// 同一个 API 上各类错误的重试次数, 用完后切换到下一个 API
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// 429
+    pub rate_limit: u32,
+    /// 请求超时
+    pub timeout: u32,
+    /// 5xx
+    pub server: u32,
+    /// 连接失败等网络错误
+    pub network: u32,
+    /// 首次重试的等待时间, 之后每次翻倍
+    pub backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            rate_limit: 2,
+            timeout: 1,
+            server: 2,
+            network: 2,
+            backoff_ms: 1000,
+            max_backoff_ms: 30000,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// 第 count 次重试前的等待时间
+    pub fn backoff(&self, count: u32) -> Duration {
+        Duration::from_millis(
+            self.backoff_ms
+                .saturating_mul(1 << count.min(16))
+                .min(self.max_backoff_ms),
+        )
+    }
+}
+
+/// 兼容只写一个索引的旧配置
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<usize>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(usize),
+        Many(Vec<usize>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(index) => vec![index],
+        OneOrMany::Many(indexes) => indexes,
+    })
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -29,4 +131,17 @@ pub struct ApiConfig {
     pub api_key_env_var: String,
     #[serde(default)]
     pub api_key: String,
+    /// 支持图片输入, 图片以缩略图发送; 否则只发送文字描述
+    #[serde(default)]
+    pub vision: bool,
+    /// 计算 token 用的 tiktoken 编码名 (如 "o200k_base"), 不填时按 model 推断,
+    /// 都推断不出时按字符规律估算
+    #[serde(default)]
+    pub tokenizer: Option<String>,
+}
+
+impl ApiConfig {
+    pub fn tokenizer(&self) -> Tokenizer {
+        Tokenizer::from_name(self.tokenizer.as_deref().unwrap_or(&self.model))
+    }
 }
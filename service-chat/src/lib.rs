@@ -11,6 +11,7 @@ use heleny_proto::Resource;
 use heleny_proto::ServiceRole;
 use heleny_proto::TASK_SERVICE;
 use heleny_service::ChatServiceMessage;
+use heleny_service::Dispatch;
 use heleny_service::Service;
 use heleny_service::TaskServiceMessage;
 use heleny_service::get_from_config_service;
@@ -19,7 +20,6 @@ use heleny_service::read_via_fs_service;
 use heleny_service::update_config_service;
 use tokio::time::Instant;
 use tracing::info;
-use tracing::warn;
 
 use crate::config::ChatConfig;
 use crate::model::HelenyModel;
@@ -33,7 +33,7 @@ use backend::*;
 pub use heleny_proto::HELENY_SCHEMA;
 pub use heleny_proto::PLANNER_SCHEMA;
 
-#[base_service(deps=["ConfigService","FsService","MemoryService","ToolkitService"])]
+#[base_service(deps=["ConfigService","FsService","MemoryService","ToolkitService"], concurrency=4, tick=none)]
 pub struct ChatService {
     endpoint: Endpoint,
     config: ChatConfig,
@@ -48,22 +48,7 @@ impl Service for ChatService {
     type MessageType = ChatServiceMessage;
     async fn new(endpoint: Endpoint) -> Result<Box<Self>> {
         let config=get_config(&endpoint).await?;
-        // 构造 Heleny
-        let api=config
-                .api
-                .get(config.heleny.api)
-                .context("没有此 API 配置")?
-                .to_owned();
-        if api.api_key.is_empty() {
-            warn!("注意, Heleny 使用的 API 没有 API_KEY");
-        }
-        let heleny = HelenyModel::new(
-            &config.heleny.preset,
-            endpoint.create_sender_endpoint(),
-            config.heleny.timeout_secs,
-            config.heleny.rag_num,
-            get_chat_model(api, HELENY_SCHEMA).await?
-        );
+        let heleny = build_heleny(&config, &endpoint).await?;
         // 构造实例
         let instance = Self {
             endpoint,
@@ -93,36 +78,12 @@ impl Service for ChatService {
                     )
                     .await
             }
-            ChatServiceMessage::GetPlanner { feedback } => {
-                let api_config = self
-                    .config
-                    .api
-                    .get(self.config.planner.api)
-                    .context("没有此 API 配置")?
-                    .to_owned();
-                let tool_descriptions = get_tool_descriptions(&self.endpoint).await?;
-                let planner = PlannerModel::new(
-                    self.config.planner.preset.clone() + &tool_descriptions,
-                    self.config.planner.timeout_secs,
-                    get_chat_model(api_config, PLANNER_SCHEMA).await?
-                );
-                let _ = feedback.send(planner);
-                Ok(())
-            }
-            ChatServiceMessage::GetExecutor { feedback } => {
-                let api_config = self
-                    .config
-                    .api
-                    .get(self.config.executor.api)
-                    .context("没有此 API 配置")?
-                    .to_owned();
-                let executor = ExecutorModel::new(&self.config.executor.preset,self.config.executor.timeout_secs,get_chat_model(api_config, EXECUTOR_SCHEMA).await?);
-                let _ = feedback.send(executor);
-                Ok(())
+            ChatServiceMessage::GetPlanner { .. } | ChatServiceMessage::GetExecutor { .. } => {
+                Err(anyhow::anyhow!("GetPlanner/GetExecutor 应由 dispatch 并发处理"))
             }
             ChatServiceMessage::TaskFinished { log } => self.heleny.explain_task_result(log).await,
             ChatServiceMessage::GetEmbedModel { base_url, model, api_key, feedback }=>{
-                let embed=get_embed_model(base_url, model, api_key)?;
+                let embed=get_metered_embed_model(base_url, model, api_key, &self.endpoint)?;
                 let _=feedback.send(embed);
                 Ok(())
             }
@@ -131,6 +92,33 @@ impl Service for ChatService {
             }
         }
     }
+    /// 构建 Planner/Executor 耗时较长, 放到独立任务中, 对话相关的消息仍按顺序处理
+    fn dispatch(
+        &self,
+        _name: &str,
+        _role: &ServiceRole,
+        msg: ChatServiceMessage,
+    ) -> Dispatch<ChatServiceMessage> {
+        match msg {
+            ChatServiceMessage::GetPlanner { feedback } => {
+                let config = self.config.clone();
+                let endpoint = self.endpoint.create_sender_endpoint();
+                Dispatch::Concurrent(Box::pin(async move {
+                    let _ = feedback.send(build_planner(&config, &endpoint).await?);
+                    Ok::<(), anyhow::Error>(())
+                }))
+            }
+            ChatServiceMessage::GetExecutor { feedback } => {
+                let config = self.config.clone();
+                let endpoint = self.endpoint.create_sender_endpoint();
+                Dispatch::Concurrent(Box::pin(async move {
+                    let _ = feedback.send(build_executor(&config, &endpoint).await?);
+                    Ok::<(), anyhow::Error>(())
+                }))
+            }
+            msg => Dispatch::Serial(msg),
+        }
+    }
     async fn stop(&mut self) {}
     async fn handle_sub_endpoint(&mut self, _msg: Box<dyn AnyMessage>) -> Result<()> {
         Ok(())
@@ -147,28 +135,39 @@ impl ChatService {
     async fn reload(&mut self)->Result<()>{
         update_config_service(&self.endpoint).await.context("重载失败: 更新 config 失败")?;
         let config=get_config(&self.endpoint).await?;
-        // 构造 Heleny
-        let api=config
-                .api
-                .get(config.heleny.api)
-                .context("没有此 API 配置")?
-                .to_owned();
-        if api.api_key.is_empty() {
-            warn!("注意, Heleny 使用的 API 没有 API_KEY");
-        }
-        let heleny = HelenyModel::new(
-            &config.heleny.preset,
-            self.endpoint.create_sender_endpoint(),
-            config.heleny.timeout_secs,
-            config.heleny.rag_num,
-            get_chat_model(api, HELENY_SCHEMA).await?
-        );
+        let heleny = build_heleny(&config, &self.endpoint).await?;
         self.config=config;
         self.heleny=heleny;
         Ok(())
     }
 }
 
+async fn build_heleny(config: &ChatConfig, endpoint: &Endpoint) -> Result<HelenyModel> {
+    Ok(HelenyModel::new(
+        &config.heleny.preset,
+        endpoint.create_sender_endpoint(),
+        config.heleny.total_timeout_secs(),
+        config.heleny.rag_num,
+        config.heleny.budget(&config.api),
+        get_role_chat_model(config, &config.heleny, "Heleny", HELENY_SCHEMA, endpoint).await?
+    ))
+}
+
+async fn build_planner(config: &ChatConfig, endpoint: &Endpoint) -> Result<PlannerModel> {
+    let tool_descriptions = get_tool_descriptions(endpoint).await?;
+    Ok(PlannerModel::new(
+        config.planner.preset.clone() + &tool_descriptions,
+        config.planner.total_timeout_secs(),
+        get_role_chat_model(config, &config.planner, "Planner", PLANNER_SCHEMA, endpoint).await?
+    ))
+}
+
+async fn build_executor(config: &ChatConfig, endpoint: &Endpoint) -> Result<ExecutorModel> {
+    Ok(ExecutorModel::new(&config.executor.preset,config.executor.total_timeout_secs(),get_role_chat_model(config, &config.executor, "Executor", EXECUTOR_SCHEMA, endpoint).await?)
+        .with_native_tools(config.executor.native_tools)
+        .with_budget(config.executor.budget(&config.api)))
+}
+
 async fn get_config(endpoint:&Endpoint)->Result<ChatConfig>{
     let mut config: ChatConfig = get_from_config_service(&endpoint).await?;
     // 读取 API KEY
@@ -199,5 +198,7 @@ async fn get_config(endpoint:&Endpoint)->Result<ChatConfig>{
     Ok(config)
 }
 
+#[cfg(test)]
+mod mock_tests;
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,229 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use heleny_proto::Chat;
+use heleny_proto::ChatRole;
+use heleny_proto::ContentExtractor;
+use heleny_proto::EXECUTOR_SCHEMA;
+use heleny_proto::Embedding;
+use heleny_proto::ExecutorModel;
+use heleny_proto::HELENY_SCHEMA;
+use heleny_proto::HelenyReply;
+use heleny_proto::MemoryEntry;
+use heleny_proto::PLANNER_SCHEMA;
+use heleny_proto::PlannerModel;
+use heleny_proto::ToolManual;
+use heleny_proto::ToolReply;
+use reqwest::header::HeaderMap;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::backend::fallback_backend::ApiFailure;
+use crate::backend::fallback_backend::FallbackChat;
+use crate::backend::fallback_backend::retry_after;
+use crate::backend::get_chat_model;
+use crate::backend::get_embed_model;
+use crate::config::ApiConfig;
+use crate::config::RetryConfig;
+
+fn mock_api() -> ApiConfig {
+    ApiConfig {
+        base_url: concat!(
+            "mock://",
+            env!("CARGO_MANIFEST_DIR"),
+            "/scripts/mock_chat.json"
+        )
+        .into(),
+        model: "mock".into(),
+        api_key_env_var: "".into(),
+        api_key: "".into(),
+        vision: false,
+        tokenizer: None,
+    }
+}
+
+#[tokio::test]
+async fn test_mock_chat() -> Result<()> {
+    let planner = PlannerModel::new(
+        "".into(),
+        5,
+        get_chat_model(mock_api(), PLANNER_SCHEMA, None).await?,
+    );
+    let tools = planner.get_tools_list("今天上海天气怎么样").await?;
+    assert_eq!(tools.tools, Some(vec!["weather".to_string()]));
+
+    let mut executor = ExecutorModel::new(
+        "",
+        5,
+        get_chat_model(mock_api(), EXECUTOR_SCHEMA, None).await?,
+    );
+    let intent = executor.get_intent("查询上海今天的天气").await?;
+    assert_eq!(intent.tool.as_deref(), Some("weather"));
+    let intent = executor.get_intent("晴, 25 度").await?;
+    assert_eq!(intent.tool, None);
+
+    let heleny = get_chat_model(mock_api(), HELENY_SCHEMA, None).await?;
+    let padding = MemoryEntry::temp(ChatRole::System, ".");
+    let message = MemoryEntry::temp(ChatRole::User, "你好呀");
+    let reply: HelenyReply = serde_json::from_str(&heleny.chat(&[&message, &padding]).await?)?;
+    assert_eq!(reply.content, "你好, 我是 Heleny.");
+    let message = MemoryEntry::temp(ChatRole::User, "随便说点什么");
+    let reply: HelenyReply = serde_json::from_str(&heleny.chat(&[&message]).await?)?;
+    assert_eq!(reply.content, "我没听懂.");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_chat_stream() -> Result<()> {
+    let heleny = get_chat_model(mock_api(), HELENY_SCHEMA, None).await?;
+    let message = MemoryEntry::temp(ChatRole::User, "你好呀");
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let response = heleny.chat_stream(&[&message], tx).await?;
+    let mut extractor = ContentExtractor::default();
+    let mut updates = 0;
+    while let Some(delta) = rx.recv().await {
+        if extractor.push(&delta) {
+            updates += 1;
+        }
+    }
+    assert!(updates > 1);
+    assert_eq!(extractor.buffer(), response);
+    assert_eq!(extractor.content(), "你好, 我是 Heleny.");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_native_tools() -> Result<()> {
+    let manual: ToolManual = serde_json::from_str(
+        r#"{"name": "weather", "description": "天气", "commands": [
+            {"name": "today", "description": "今天的天气", "args": [
+                {"name": "city", "description": "城市", "type": "string", "required": true, "default": null}
+            ]}
+        ]}"#,
+    )?;
+    let mut executor = ExecutorModel::new(
+        "",
+        5,
+        get_chat_model(mock_api(), EXECUTOR_SCHEMA, None).await?,
+    )
+    .with_native_tools(true);
+    executor.set_tools(manual.get_functions());
+    let ToolReply::Calls { calls, .. } =
+        executor.get_tool_calls(Some("查询上海今天的天气")).await?
+    else {
+        panic!("应当发起工具调用");
+    };
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].name, "weather__today");
+    assert_eq!(calls[0].args["city"], "上海");
+    executor.push_tool_result(&calls[0], "晴, 25 度".into());
+    let ToolReply::Text(text) = executor.get_tool_calls(None).await? else {
+        panic!("应当结束调用");
+    };
+    assert_eq!(text, "已经得到结果");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_embed() -> Result<()> {
+    let embed = get_embed_model("mock://".into(), "mock".into(), "".into())?;
+    let texts = vec![
+        "今天天气很好".to_string(),
+        "今天天气不错".to_string(),
+        "帮我写一段代码".to_string(),
+    ];
+    let first = embed.embed(64, texts.clone()).await?;
+    let second = embed.embed(64, texts).await?;
+    assert_eq!(first, second);
+    let dot = |a: &Embedding, b: &Embedding| {
+        a.vector
+            .iter()
+            .zip(&b.vector)
+            .map(|(x, y)| x * y)
+            .sum::<f32>()
+    };
+    assert!(dot(&first[0], &first[1]) > dot(&first[0], &first[2]));
+    Ok(())
+}
+
+#[derive(Debug)]
+struct RateLimitedChat {
+    calls: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl Chat for RateLimitedChat {
+    async fn chat(&self, _messages: &[&MemoryEntry]) -> Result<String> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Err(ApiFailure::from_status(
+            429,
+            Some(Duration::from_millis(10)),
+            "too many requests".into(),
+        )
+        .into())
+    }
+}
+
+#[tokio::test]
+async fn test_fallback_chat() -> Result<()> {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let retry = RetryConfig::default();
+    let chat = FallbackChat::new(retry.clone(), Duration::from_secs(5), Duration::from_secs(5))
+        .with_provider(
+            "grok (api 0)".into(),
+            Box::new(RateLimitedChat {
+                calls: calls.clone(),
+            }),
+        )
+        .with_provider(
+            "mock (api 1)".into(),
+            get_chat_model(mock_api(), PLANNER_SCHEMA, None).await?,
+        );
+    let planner = PlannerModel::new("".into(), 5, Box::new(chat));
+    let tools = planner.get_tools_list("今天上海天气怎么样").await?;
+    assert_eq!(tools.tools, Some(vec!["weather".to_string()]));
+    assert_eq!(calls.load(Ordering::SeqCst), retry.rate_limit as usize + 1);
+    assert_eq!(planner.provider().as_deref(), Some("mock (api 1)"));
+
+    let mut headers = HeaderMap::new();
+    headers.insert("retry-after", "3".parse()?);
+    assert_eq!(retry_after(&headers), Some(Duration::from_secs(3)));
+    headers.insert("retry-after-ms", "1500".parse()?);
+    assert_eq!(retry_after(&headers), Some(Duration::from_millis(1500)));
+    Ok(())
+}
+
+#[derive(Debug)]
+struct SlowChat;
+
+#[async_trait]
+impl Chat for SlowChat {
+    async fn chat(&self, _messages: &[&MemoryEntry]) -> Result<String> {
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        Ok("太慢了".into())
+    }
+}
+
+#[tokio::test]
+async fn test_fallback_chat_deadline() -> Result<()> {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let chat = FallbackChat::new(
+        RetryConfig::default(),
+        Duration::from_secs(5),
+        Duration::from_millis(100),
+    )
+    .with_provider("slow (api 0)".into(), Box::new(SlowChat))
+    .with_provider(
+        "grok (api 1)".into(),
+        Box::new(RateLimitedChat {
+            calls: calls.clone(),
+        }),
+    );
+    let start = std::time::Instant::now();
+    assert!(chat.chat(&[]).await.is_err());
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+    Ok(())
+}
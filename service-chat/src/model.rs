@@ -5,6 +5,8 @@ use anyhow::Result;
 use heleny_bus::endpoint::Endpoint;
 use heleny_proto::Chat;
 use heleny_proto::ChatRole;
+use heleny_proto::ContentExtractor;
+use heleny_proto::ContextBudget;
 use heleny_proto::HelenyReply;
 use heleny_proto::MEMORY_SERVICE;
 use heleny_proto::MemoryContent;
@@ -12,27 +14,34 @@ use heleny_proto::MemoryEntry;
 use heleny_proto::trim_response;
 use heleny_service::MemoryServiceMessage;
 use heleny_service::get_tool_descriptions;
+use tokio::sync::mpsc;
 use tokio::sync::oneshot;
 use tokio::time::timeout;
 use tracing::debug;
+use tracing::info;
 use tracing::warn;
 
+/// 末尾填充的 "." 条数
+const PADDING_NUM: usize = 10;
+
 pub struct HelenyModel {
     preset: MemoryEntry,
     endpoint: Endpoint,
     timeout: Duration,
     chat_model: Box<dyn Chat>,
     rag_num:usize,
+    budget: ContextBudget,
 }
 
 impl HelenyModel {
-    pub fn new(preset: &str, endpoint: Endpoint, timeout:u64, rag_num:usize, chat_model: Box<dyn Chat>) -> Self {
+    pub fn new(preset: &str, endpoint: Endpoint, timeout:u64, rag_num:usize, budget: ContextBudget, chat_model: Box<dyn Chat>) -> Self {
         Self {
             preset:MemoryEntry::temp(ChatRole::System, preset),
             endpoint,
             timeout:Duration::from_secs(timeout),
             rag_num,
             chat_model,
+            budget,
         }
     }
 
@@ -44,31 +53,30 @@ impl HelenyModel {
             .await?;
         // 构造聊天信息
         let tool_descriptions = MemoryEntry::temp(ChatRole::System, get_tool_descriptions(&self.endpoint).await?);
-        let mut messages: Vec<&MemoryEntry> = vec![&self.preset,&tool_descriptions];
-        // rag 检索获取长期记忆
-        let mut rag_messages=None;
+        // rag 检索获取长期记忆, 每条记忆可能由说明和图片两条消息组成
+        let mut rag_messages:Vec<Vec<MemoryEntry>>=Vec::new();
         if self.rag_num>0 {
             let (tx, rx) = oneshot::channel();
             if let Err(e) =self.endpoint.send(MEMORY_SERVICE, MemoryServiceMessage::GetSimilarMemoryEntries { content: message, num: self.rag_num, feedback: tx }).await {
                 warn!("发送获取相似记忆失败: {}",e);
             };
             if let Ok(msgs)=rx.await {
-                rag_messages=Some(msgs.into_iter().filter_map(|mut entry| {
+                rag_messages=msgs.into_iter().filter_map(|mut entry| {
                     match &mut entry.content {
                         MemoryContent::Text(msg)=>{
                             *msg=format!("<Memory>{msg}</Memory>");
-                            Some(entry)
+                            Some(vec![entry])
+                        }
+                        // 图片本身无法加标记, 在前面加一条说明; 支持图片输入的 API 会收到图片本身
+                        MemoryContent::Image(_)=>{
+                            let marker=MemoryEntry::new(entry.id, entry.role, entry.time, MemoryContent::Text("<Memory>下一张图片来自长期记忆</Memory>".into()));
+                            Some(vec![marker,entry])
                         }
-                        MemoryContent::Image(_)=>None,
                         MemoryContent::File(_)=>None,
                     }
-                }).collect::<Vec<_>>());
+                }).collect::<Vec<_>>();
             };
         }
-        if let Some(rag_messages) = &rag_messages {
-            messages.extend(rag_messages.iter().collect::<Vec<_>>());
-            debug!("本次聊天长期记忆消息: {:?}",rag_messages);
-        }
         // 获取短期记忆
         let (tx, rx) = oneshot::channel();
         self.endpoint
@@ -78,20 +86,34 @@ impl HelenyModel {
             )
             .await?;
         let history: Vec<MemoryEntry> = rx.await.context("获取历史信息失败")?;
-        messages.extend(history.iter().collect::<Vec<_>>());
+        // 按预算裁剪: 优先保留最近的短期记忆, 剩余预算按相关度放入长期记忆
         let entry =MemoryEntry::temp(ChatRole::System, ".");
-        for _ in 0..10 {
+        let tokenizer = self.budget.tokenizer;
+        let fixed = tokenizer.entry_tokens(&self.preset) + tokenizer.entry_tokens(&tool_descriptions) + tokenizer.entry_tokens(&entry) * PADDING_NUM;
+        let history_tokens: Vec<usize> = history.iter().map(|entry| tokenizer.entry_tokens(entry)).collect();
+        let skip = self.budget.fit(fixed, &history_tokens);
+        let mut remaining = self.budget.max_tokens.saturating_sub(fixed + history_tokens[skip..].iter().sum::<usize>());
+        let rag_num = rag_messages.len();
+        let rag_messages: Vec<&Vec<MemoryEntry>> = rag_messages.iter().take_while(|group| {
+            let tokens = group.iter().map(|entry| tokenizer.entry_tokens(entry)).sum::<usize>();
+            let fits = tokens <= remaining;
+            if fits {
+                remaining -= tokens;
+            }
+            fits
+        }).collect();
+        if skip > 0 || rag_messages.len() < rag_num {
+            info!("Heleny 上下文超出预算, 省略了 {} 条较早的消息和 {} 条长期记忆", skip, rag_num - rag_messages.len());
+        }
+        debug!("本次聊天长期记忆消息: {:?}",rag_messages);
+        let mut messages: Vec<&MemoryEntry> = vec![&self.preset,&tool_descriptions];
+        messages.extend(rag_messages.into_iter().flatten());
+        messages.extend(history[skip..].iter());
+        for _ in 0..PADDING_NUM {
             messages.push(&entry);
         }
         // 获取响应
-        let response = self.chat_model.chat(&messages).await?;
-        let heleny_reply: HelenyReply =
-            match serde_json::from_str(trim_response(&response)?) {
-                Ok(resp)=>resp,
-                Err(e)=>{
-                    return Err(anyhow::anyhow!("解析 {} 为 HelenyReply失败: {}",response,e));
-                }
-            };
+        let heleny_reply = self.get_reply(&messages, None).await?;
         // Post 回复
         let HelenyReply { content, need_help } = heleny_reply;
         self.endpoint
@@ -103,16 +125,62 @@ impl HelenyModel {
     /// 发送任务结果给 Heleny, 由 Heleny 来解释给 User
     pub async fn explain_task_result(&self, log: Vec<String>) -> Result<()> {
         // 构造聊天信息
+        let log = self.fit_task_log(log);
         let log = MemoryEntry::temp(ChatRole::System, format!("<task_log>{:?}</task_log>", log));        
         let message = vec![&self.preset,&log];
         // 获取响应
-        let response = timeout(self.timeout, self.chat_model.chat(&message)).await.context("获取 Heleny 回复超时")?.context("获取 Heleny 回复失败")?;
-        let heleny_reply: HelenyReply =
-            serde_json::from_str(trim_response(&response)?).context("解析 Response 为 HelenyReply 失败")?;
+        let heleny_reply = self.get_reply(&message, Some(self.timeout)).await?;
         // Post 回复
         self.endpoint
             .send(MEMORY_SERVICE, MemoryServiceMessage::Post { role:ChatRole::Assistant,content:heleny_reply.content.into() })
             .await?;
         Ok(())
     }
+
+    /// 过长的日志逐条截断, 总量超出预算时丢弃最早的日志, 保留结尾的结果
+    fn fit_task_log(&self, log: Vec<String>) -> Vec<String> {
+        let log: Vec<String> = log.into_iter().map(|line| self.budget.truncate(&line).unwrap_or(line)).collect();
+        let tokens: Vec<usize> = log.iter().map(|line| self.budget.tokenizer.count(line)).collect();
+        let skip = self.budget.fit(self.budget.tokenizer.entry_tokens(&self.preset), &tokens);
+        if skip == 0 {
+            return log;
+        }
+        info!("任务日志超出预算, 省略了最早的 {} 条", skip);
+        std::iter::once(format!("[省略了最早的 {} 条日志]", skip)).chain(log.into_iter().skip(skip)).collect()
+    }
+
+    /// 流式获取并解析回复, 生成过程中已有的 content 会先推给 MemoryService 展示
+    async fn get_reply(&self, messages: &[&MemoryEntry], limit: Option<Duration>) -> Result<HelenyReply> {
+        let reply = self._get_reply(messages, limit).await;
+        if reply.is_err() {
+            // 失败时撤下生成中的回复
+            let _ = self.endpoint.send(MEMORY_SERVICE, MemoryServiceMessage::Streaming { content: None }).await;
+        }
+        reply
+    }
+
+    async fn _get_reply(&self, messages: &[&MemoryEntry], limit: Option<Duration>) -> Result<HelenyReply> {
+        let response = match limit {
+            Some(limit) => timeout(limit, self.chat_streaming(messages)).await.context("获取 Heleny 回复超时")?.context("获取 Heleny 回复失败")?,
+            None => self.chat_streaming(messages).await?,
+        };
+        serde_json::from_str(trim_response(&response)?).context(format!("解析 {} 为 HelenyReply 失败", response))
+    }
+
+    async fn chat_streaming(&self, messages: &[&MemoryEntry]) -> Result<String> {
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        let forward = async {
+            let mut extractor = ContentExtractor::default();
+            while let Some(delta) = rx.recv().await {
+                if extractor.push(&delta) {
+                    let content = Some(extractor.content().to_string());
+                    if let Err(e) = self.endpoint.send(MEMORY_SERVICE, MemoryServiceMessage::Streaming { content }).await {
+                        warn!("发送生成中的回复失败: {}", e);
+                    }
+                }
+            }
+        };
+        let (response, _) = tokio::join!(self.chat_model.chat_stream(messages, tx), forward);
+        response
+    }
 }
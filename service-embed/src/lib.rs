@@ -29,7 +29,7 @@ use hnsw_rs::prelude::*;
 use tracing::info;
 use tracing::warn;
 
-#[base_service(deps=["ChatService","ConfigService","MemoryService","FsService"])]
+#[base_service(deps=["ChatService","ConfigService","MemoryService","FsService"], tick=none)]
 pub struct EmbedService{
     endpoint:Endpoint,
     embed_model:Box<dyn Embed>,
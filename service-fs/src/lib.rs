@@ -41,7 +41,7 @@ mod cache_entry;
 mod config;
 mod tool;
 
-#[base_service(deps=["ConfigService"])]
+#[base_service(deps=["ConfigService"], tick=none)]
 pub struct FsService {
     endpoint: Endpoint,
     temp_dir: PathBuf,
@@ -19,7 +19,7 @@ use crate::provider::Provider;
 
 mod provider;
 
-#[base_service(deps=[])]
+#[base_service(deps=[], tick=none)]
 pub struct HubService {
     endpoint: Endpoint,
     // 资源提供者
@@ -20,7 +20,7 @@ use crate::tool::McpToolFactory;
 mod config;
 mod tool;
 
-#[base_service(deps=["ConfigService"])]
+#[base_service(deps=["ConfigService"], tick=none)]
 pub struct McpService {
     endpoint: Endpoint,
 }
@@ -10,12 +10,14 @@ use heleny_bus::endpoint::Endpoint;
 use heleny_macros::base_service;
 use heleny_proto::AnyMessage;
 use heleny_proto::CONFIG_STORAGE_DIR;
+use heleny_proto::ChatRole;
 use heleny_proto::DISPLAY_MESSAGES;
 use heleny_proto::EMBED_SERVICE;
 use heleny_proto::MemoryContent;
 use heleny_proto::MemoryEntry;
 use heleny_proto::Resource;
 use heleny_proto::ResourcePayload;
+use heleny_proto::STREAMING_MESSAGE_ID;
 use heleny_proto::ServiceRole;
 use heleny_service::EmbedServiceMessage;
 use heleny_service::MemoryServiceMessage;
@@ -36,7 +38,7 @@ use crate::memory_db::MemoryDb;
 mod config;
 mod memory_db;
 
-#[base_service(deps=["ConfigService","HubService","FsService"])]
+#[base_service(deps=["ConfigService","HubService","FsService"], tick=none)]
 pub struct MemoryService {
     endpoint: Endpoint,
     config: MemoryConfig,
@@ -64,6 +66,7 @@ impl Service for MemoryService {
         let (tx, rx) = watch::channel(ResourcePayload::DisplayMessages {
             new: true,
             messages: Vec::new(),
+            streaming: false,
         });
         publish_resource(&endpoint, DISPLAY_MESSAGES, rx).await?;
         // 新建实例
@@ -108,6 +111,17 @@ impl Service for MemoryService {
                     .send(ResourcePayload::DisplayMessages {
                         new: true,
                         messages: vec![display_message],
+                        streaming: false,
+                    })
+                    .context("更新 DisplayMessages 失败")?;
+            }
+            MemoryServiceMessage::Streaming { content }=>{
+                let messages=content.into_iter().map(|content| MemoryEntry::new(STREAMING_MESSAGE_ID,ChatRole::Assistant,Local::now(),content.into())).collect();
+                self.publisher
+                    .send(ResourcePayload::DisplayMessages {
+                        new: true,
+                        messages,
+                        streaming: true,
                     })
                     .context("更新 DisplayMessages 失败")?;
             }
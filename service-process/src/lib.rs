@@ -12,7 +12,7 @@ use tokio::time::Instant;
 #[cfg(test)]
 mod tests;
 
-#[base_service(deps=[])]
+#[base_service(deps=[], tick=none)]
 pub struct ProcessService {
     endpoint: Endpoint,
 }
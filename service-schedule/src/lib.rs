@@ -8,12 +8,14 @@ use heleny_macros::base_service;
 use heleny_proto::AnyMessage;
 use heleny_proto::CONFIG_STORAGE_DIR;
 use heleny_proto::FS_SERVICE;
+use heleny_proto::HUB_SERVICE;
 use heleny_proto::Resource;
 use heleny_proto::ResourcePayload;
 use heleny_proto::SCHEDULE;
 use heleny_proto::ScheduledTask;
 use heleny_proto::ServiceRole;
 use heleny_proto::TASK_SERVICE;
+use heleny_proto::TOOLKIT_SERVICE;
 use heleny_proto::downcast;
 use heleny_service::FsServiceMessage;
 use heleny_service::ScheduleServiceMessage;
@@ -41,7 +43,7 @@ use crate::tool::ScheduleToolFactory;
 mod config;
 mod tool;
 
-#[base_service(deps=["ConfigService","FsService","TaskService","ToolkitService","HubService"])]
+#[base_service(deps=["ConfigService","FsService","TaskService","ToolkitService","HubService"], tick=none)]
 pub struct ScheduleService {
     endpoint: Endpoint,
     offset: FixedOffset,
@@ -72,16 +74,13 @@ impl Service for ScheduleService {
         schedule
             .values_mut()
             .for_each(|task| task.update_next_trigger());
-        // 向工具服务注册
-        let factory = ScheduleToolFactory::new(endpoint.create_sender_endpoint(), config.offset);
-        register_tool_factory(&endpoint, factory).await;
         // 向 Hub 服务注册
         let (tx, rx) = watch::channel(ResourcePayload::Schedules {
             schedules: schedule.clone(),
         });
         publish_resource(&endpoint, SCHEDULE, rx).await?;
         // 实例化
-        let mut instance = Self {
+        let instance = Self {
             endpoint,
             offset,
             scheduled_tasks: schedule,
@@ -89,9 +88,24 @@ impl Service for ScheduleService {
             schedule_path,
             schedule_tx: tx,
         };
-        instance.find_next_trigger();
+        // 向工具服务注册
+        instance.register_tools().await;
         Ok(Box::new(instance))
     }
+    /// 就绪后才开始计时, 避免服务就绪前就向 TaskService 派发到期的任务
+    async fn on_ready(&mut self) -> Result<()> {
+        self.find_next_trigger();
+        Ok(())
+    }
+    /// 重启后的 ToolkitService 和 HubService 不再有之前注册的工具和资源
+    async fn on_dependency_restarted(&mut self, name: &str) -> Result<()> {
+        if name == TOOLKIT_SERVICE {
+            self.register_tools().await;
+        } else if name == HUB_SERVICE {
+            publish_resource(&self.endpoint, SCHEDULE, self.schedule_tx.subscribe()).await?;
+        }
+        Ok(())
+    }
     async fn handle(
         &mut self,
         _name: String,
@@ -169,6 +183,13 @@ impl Service for ScheduleService {
 }
 
 impl ScheduleService {
+    async fn register_tools(&self) {
+        let factory = ScheduleToolFactory::new(
+            self.endpoint.create_sender_endpoint(),
+            self.offset.local_minus_utc(),
+        );
+        register_tool_factory(&self.endpoint, factory).await;
+    }
     /// 持久化 schedule
     async fn persist(&self) -> Result<()> {
         self.push_schedule_resource();
@@ -6,7 +6,10 @@ use std::time::Duration;
 use anyhow::Result;
 use chrono::DateTime;
 use chrono::Local;
+use heleny_proto::LatencyTable;
 use heleny_proto::ResourcePayload;
+use heleny_proto::RouteEvent;
+use heleny_proto::TrafficMatrix;
 use tokio::sync::mpsc;
 use tokio::sync::watch;
 use tokio::task::JoinHandle;
@@ -15,27 +18,39 @@ use tokio::time::interval;
 // use tracing::debug;
 use tracing::warn;
 
+/// 每秒经过 Bus 的消息数, 最早的在前
+type TotalTraffic = Arc<Mutex<VecDeque<(DateTime<Local>, usize)>>>;
+
 /// 主要职责是监控Bus的流量进行统计
 pub struct BusWatcherHandle {
     _handle: JoinHandle<Result<()>>,
-    total_traffic: Arc<Mutex<VecDeque<(DateTime<Local>, usize)>>>,
+    total_traffic: TotalTraffic,
 }
 
 impl BusWatcherHandle {
     pub fn new(
         duration: usize,
-        mut bus_rx: mpsc::Receiver<(String, String)>,
-    ) -> Result<(BusWatcherHandle, watch::Receiver<ResourcePayload>)> {
+        mut bus_rx: mpsc::Receiver<RouteEvent>,
+        latency: LatencyTable,
+    ) -> Result<(
+        BusWatcherHandle,
+        watch::Receiver<ResourcePayload>,
+        watch::Receiver<ResourcePayload>,
+    )> {
         let (tx, rx) = watch::channel(ResourcePayload::TotalBusTraffic(VecDeque::new()));
+        let (matrix_tx, matrix_rx) =
+            watch::channel(ResourcePayload::TrafficMatrix(TrafficMatrix::default()));
         let total_traffic = Arc::new(Mutex::new(VecDeque::new()));
         let total_traffic_ = total_traffic.clone();
         let handle = tokio::spawn(async move {
             let mut bus_watcher = BusWatcher::new(duration, total_traffic_, tx);
+            let mut route_watcher = RouteWatcher::new(latency, matrix_tx);
             let mut tick_interval = interval(Duration::from_secs(1));
             tick_interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
             loop {
                 tokio::select! {
                     Some(msg) = bus_rx.recv()=>{
+                        route_watcher.handle(&msg);
                         if let Err(e) =bus_watcher.handle(msg) {
                             warn!("BusWatcher: {}",e)
                         }
@@ -44,6 +59,9 @@ impl BusWatcherHandle {
                         if let Err(e) =bus_watcher.handle_tick() {
                             warn!("BusWatcher: {}",e)
                         }
+                        if let Err(e) =route_watcher.handle_tick() {
+                            warn!("RouteWatcher: {}",e)
+                        }
                     }
                 }
             }
@@ -54,6 +72,7 @@ impl BusWatcherHandle {
                 total_traffic,
             },
             rx,
+            matrix_rx,
         ))
     }
 
@@ -73,14 +92,14 @@ pub struct BusWatcher {
     duration: usize,
     current_time: DateTime<Local>,
     count: usize,
-    total_traffic: Arc<Mutex<VecDeque<(DateTime<Local>, usize)>>>,
+    total_traffic: TotalTraffic,
     tx: watch::Sender<ResourcePayload>,
 }
 
 impl BusWatcher {
     pub fn new(
         duration: usize,
-        total_traffic: Arc<Mutex<VecDeque<(DateTime<Local>, usize)>>>,
+        total_traffic: TotalTraffic,
         tx: watch::Sender<ResourcePayload>,
     ) -> Self {
         if let Ok(mut traffic) = total_traffic.lock() {
@@ -98,8 +117,8 @@ impl BusWatcher {
         }
     }
 
-    pub fn handle(&mut self, _msg: (String, String)) -> Result<()> {
-        self.count = self.count + 1;
+    pub fn handle(&mut self, _msg: RouteEvent) -> Result<()> {
+        self.count += 1;
         Ok(())
     }
 
@@ -121,3 +140,53 @@ impl BusWatcher {
         }
     }
 }
+
+/// 按路由统计流量, 并汇总各服务的收件箱长度和处理耗时
+pub struct RouteWatcher {
+    matrix: TrafficMatrix,
+    latency: LatencyTable,
+    tx: watch::Sender<ResourcePayload>,
+}
+
+impl RouteWatcher {
+    pub fn new(latency: LatencyTable, tx: watch::Sender<ResourcePayload>) -> Self {
+        Self {
+            matrix: TrafficMatrix::default(),
+            latency,
+            tx,
+        }
+    }
+
+    pub fn handle(&mut self, msg: &RouteEvent) {
+        *self
+            .matrix
+            .routes
+            .entry(msg.source.clone())
+            .or_default()
+            .entry(msg.target.clone())
+            .or_default() += 1;
+        self.matrix
+            .services
+            .entry(msg.target.clone())
+            .or_default()
+            .queue_depth = msg.queue_depth;
+    }
+
+    pub fn handle_tick(&mut self) -> Result<()> {
+        match self.latency.lock() {
+            Ok(latency) => {
+                for (name, histogram) in latency.iter() {
+                    self.matrix
+                        .services
+                        .entry(name.clone())
+                        .or_default()
+                        .latency = histogram.clone();
+                }
+            }
+            Err(e) => return Err(anyhow::anyhow!("{}", e)),
+        }
+        self.tx
+            .send(ResourcePayload::TrafficMatrix(self.matrix.clone()))?;
+        Ok(())
+    }
+}
@@ -1,6 +1,10 @@
 use serde::Deserialize;
 
+use crate::usage::UsageConfig;
+
 #[derive(Deserialize, Debug)]
 pub struct StatsConfig {
     pub duration: usize,
+    #[serde(default)]
+    pub usage: UsageConfig,
 }
@@ -3,30 +3,45 @@ use async_trait::async_trait;
 use heleny_bus::endpoint::Endpoint;
 use heleny_macros::base_service;
 use heleny_proto::AnyMessage;
+use heleny_proto::CONFIG_STORAGE_DIR;
+use heleny_proto::DEAD_LETTERS;
 use heleny_proto::KERNEL_NAME;
+use heleny_proto::LLM_USAGE;
+use heleny_proto::LlmUsage;
 use heleny_proto::Resource;
 use heleny_proto::ServiceRole;
 use heleny_proto::TOTAL_BUS_TRAFFIC;
+use heleny_proto::TRAFFIC_MATRIX;
 use heleny_service::KernelMessage;
 use heleny_service::Service;
+use heleny_service::CALL_TIMEOUT;
 use heleny_service::StatsServiceMessage;
 use heleny_service::get_from_config_service;
+use heleny_service::import_from_config_service;
 use heleny_service::publish_resource;
+use heleny_service::read_via_fs_service;
+use heleny_service::write_via_fs_service;
+use std::path::Path;
+use std::path::PathBuf;
 use tokio::sync::mpsc;
 use tokio::time::Instant;
 use tracing::debug;
+use tracing::warn;
 
 use crate::bus_watcher::BusWatcherHandle;
 use crate::config::StatsConfig;
+use crate::usage::UsageLedger;
 
 mod bus_watcher;
 mod config;
+mod usage;
 
-#[base_service(deps=["ConfigService","HubService"])]
+#[base_service(deps=["ConfigService","FsService","HubService"], tick="30s")]
 pub struct StatsService {
     endpoint: Endpoint,
-    _stats_config: StatsConfig,
     bus_watcher: BusWatcherHandle,
+    usage: UsageLedger,
+    usage_path: PathBuf,
 }
 
 #[derive(Debug)]
@@ -43,12 +58,36 @@ impl Service for StatsService {
         let _ = endpoint
             .send(KERNEL_NAME, KernelMessage::GetBusStatsRx { sender: tx })
             .await?;
-        let (bus_watcher, bus_watch_rx) = BusWatcherHandle::new(config.duration, rx)?;
+        let latency = endpoint
+            .call(
+                KERNEL_NAME,
+                |sender| KernelMessage::GetLatencyTable { sender },
+                CALL_TIMEOUT,
+            )
+            .await?;
+        let (bus_watcher, bus_watch_rx, matrix_rx) =
+            BusWatcherHandle::new(config.duration, rx, latency)?;
         publish_resource(&endpoint, TOTAL_BUS_TRAFFIC, bus_watch_rx).await?;
+        publish_resource(&endpoint, TRAFFIC_MATRIX, matrix_rx).await?;
+        let dead_letters_rx = endpoint
+            .call(
+                KERNEL_NAME,
+                |sender| KernelMessage::GetDeadLettersRx { sender },
+                CALL_TIMEOUT,
+            )
+            .await?;
+        publish_resource(&endpoint, DEAD_LETTERS, dead_letters_rx).await?;
+        // 读取之前的 LLM 用量
+        let storage_dir: PathBuf = import_from_config_service(&endpoint, CONFIG_STORAGE_DIR).await?;
+        let usage_path = storage_dir.join("usage.json");
+        let usage = load_usage(&endpoint, &usage_path).await;
+        let (usage, usage_rx) = UsageLedger::new(config.usage, usage);
+        publish_resource(&endpoint, LLM_USAGE, usage_rx).await?;
         let instance = Self {
             endpoint,
-            _stats_config: config,
             bus_watcher,
+            usage,
+            usage_path,
         };
         Ok(Box::new(instance))
     }
@@ -62,19 +101,59 @@ impl Service for StatsService {
             StatsServiceMessage::GetBusStats { sender } => {
                 let _ = sender.send(self.bus_watcher.get_total_traffic()?);
             }
+            StatsServiceMessage::RecordUsage { record } => {
+                self.usage.record(record);
+            }
+            StatsServiceMessage::CheckBudget { feedback } => {
+                let _ = feedback.send(self.usage.check_budget());
+            }
         }
         Ok(())
     }
-    async fn stop(&mut self) {}
+    async fn stop(&mut self) {
+        if let Err(e) = self.persist_usage().await {
+            warn!("保存 LLM 用量失败: {}", e);
+        }
+    }
     async fn handle_sub_endpoint(&mut self, _msg: Box<dyn AnyMessage>) -> Result<()> {
         Ok(())
     }
     async fn handle_tick(&mut self, _tick: Instant) -> Result<()> {
-        Ok(())
+        self.persist_usage().await
     }
     async fn handle_resource(&mut self, _resource: Resource) -> Result<()> {
         Ok(())
     }
 }
 
-impl StatsService {}
+impl StatsService {
+    /// 有新的用量记录时写入 usage.json
+    async fn persist_usage(&mut self) -> Result<()> {
+        let Some(content) = self.usage.take_dirty() else {
+            return Ok(());
+        };
+        if let Err(e) = write_via_fs_service(&self.endpoint, &self.usage_path, content).await {
+            self.usage.mark_dirty();
+            return Err(e.context("写入 usage.json 失败"));
+        }
+        Ok(())
+    }
+}
+
+/// usage.json 损坏时另存为 usage.json.bad, 从空的用量重新记录, 不影响服务启动
+async fn load_usage(endpoint: &Endpoint, path: &Path) -> LlmUsage {
+    let Ok(content) = read_via_fs_service(endpoint, path).await else {
+        return LlmUsage::default();
+    };
+    match serde_json::from_str(&content) {
+        Ok(usage) => usage,
+        Err(e) => {
+            let bad_path = path.with_extension("json.bad");
+            warn!("解析 {:?} 失败, 另存为 {:?} 后重新记录用量: {}", path, bad_path, e);
+            if let Err(e) = write_via_fs_service(endpoint, &bad_path, content).await {
+                warn!("另存损坏的用量记录失败: {:#}", e);
+            }
+            LlmUsage::default()
+        }
+    }
+}
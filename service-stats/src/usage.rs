@@ -0,0 +1,177 @@
+use heleny_proto::LlmUsage;
+use heleny_proto::ResourcePayload;
+use heleny_proto::UsageRecord;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tokio::sync::watch;
+use tracing::warn;
+
+/// 每百万 token 的价格
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ModelPrice {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct UsageConfig {
+    /// 模型名 -> 价格, 没有配置的模型花费记为 0
+    pub prices: HashMap<String, ModelPrice>,
+    /// 每日 token 上限, 超出后拒绝新任务
+    pub daily_token_cap: Option<u64>,
+    /// 每日花费上限, 超出后拒绝新任务
+    pub daily_cost_cap: Option<f64>,
+    /// 保留最近多少天的统计
+    pub keep_days: usize,
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        Self {
+            prices: HashMap::new(),
+            daily_token_cap: None,
+            daily_cost_cap: None,
+            keep_days: 30,
+        }
+    }
+}
+
+/// 汇总模型调用的用量, 变化时推送 LlmUsage 资源
+pub struct UsageLedger {
+    config: UsageConfig,
+    usage: LlmUsage,
+    usage_tx: watch::Sender<ResourcePayload>,
+    /// 有未持久化的记录
+    dirty: bool,
+}
+
+impl UsageLedger {
+    pub fn new(config: UsageConfig, usage: LlmUsage) -> (Self, watch::Receiver<ResourcePayload>) {
+        let (usage_tx, usage_rx) = watch::channel(ResourcePayload::LlmUsage(usage.clone()));
+        (
+            Self {
+                config,
+                usage,
+                usage_tx,
+                dirty: false,
+            },
+            usage_rx,
+        )
+    }
+
+    pub fn record(&mut self, record: UsageRecord) {
+        let cost = self
+            .config
+            .prices
+            .get(&record.model)
+            .map(|price| {
+                (record.usage.prompt_tokens as f64 * price.prompt
+                    + record.usage.completion_tokens as f64 * price.completion)
+                    / 1_000_000.0
+            })
+            .unwrap_or_default();
+        self.usage.record(&record, cost);
+        self.usage.prune(self.config.keep_days);
+        self.dirty = true;
+        if let Err(e) = self
+            .usage_tx
+            .send(ResourcePayload::LlmUsage(self.usage.clone()))
+        {
+            warn!("推送 LlmUsage 资源失败: {}", e);
+        }
+    }
+
+    /// 今日用量超出上限时返回原因
+    pub fn check_budget(&self) -> Option<String> {
+        let today = self.usage.today()?;
+        if let Some(cap) = self.config.daily_token_cap
+            && today.total.tokens() >= cap
+        {
+            return Some(format!(
+                "今日 LLM 用量 {} tokens 已达到上限 {}",
+                today.total.tokens(),
+                cap
+            ));
+        }
+        if let Some(cap) = self.config.daily_cost_cap
+            && today.total.cost >= cap
+        {
+            return Some(format!(
+                "今日 LLM 花费 {:.4} 已达到上限 {}",
+                today.total.cost, cap
+            ));
+        }
+        None
+    }
+
+    /// 有未持久化的记录时返回要写入的内容
+    pub fn take_dirty(&mut self) -> Option<String> {
+        if !self.dirty {
+            return None;
+        }
+        match serde_json::to_string(&self.usage) {
+            Ok(content) => {
+                self.dirty = false;
+                Some(content)
+            }
+            Err(e) => {
+                warn!("序列化 LlmUsage 失败: {}", e);
+                None
+            }
+        }
+    }
+
+    /// 写入失败时调用, 下次再尝试持久化
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+    use heleny_proto::TokenUsage;
+
+    #[test]
+    fn test_usage_cost_and_cap() {
+        let config = UsageConfig {
+            prices: HashMap::from([(
+                "grok".to_string(),
+                ModelPrice {
+                    prompt: 2.0,
+                    completion: 10.0,
+                },
+            )]),
+            daily_token_cap: None,
+            daily_cost_cap: Some(0.01),
+            keep_days: 30,
+        };
+        let (mut ledger, rx) = UsageLedger::new(config, LlmUsage::default());
+        let record = |model: &str| UsageRecord {
+            time: Local::now(),
+            role: "heleny".into(),
+            model: model.into(),
+            task: None,
+            usage: TokenUsage {
+                prompt_tokens: 1000,
+                completion_tokens: 500,
+            },
+            estimated: false,
+            latency_ms: 10,
+        };
+        ledger.record(record("mock"));
+        ledger.record(record("grok"));
+        assert!(ledger.check_budget().is_none());
+        ledger.record(record("grok"));
+        assert!(ledger.check_budget().is_some());
+        let ResourcePayload::LlmUsage(usage) = &*rx.borrow() else {
+            panic!("应当推送 LlmUsage");
+        };
+        assert_eq!(usage.today().unwrap().total.calls, 3);
+        assert!(ledger.take_dirty().is_some());
+        assert!(ledger.take_dirty().is_none());
+    }
+}
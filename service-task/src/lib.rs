@@ -8,13 +8,16 @@ use heleny_proto::CHAT_SERVICE;
 use heleny_proto::ExecutorModel;
 use heleny_proto::PlannerModel;
 use heleny_proto::Resource;
+use heleny_proto::STATS_SERVICE;
 use heleny_proto::ServiceRole;
 use heleny_proto::TASK_ABSTRACT;
 use heleny_proto::TOOLKIT_SERVICE;
 use heleny_proto::TaskStatus;
 use heleny_proto::downcast;
+use heleny_service::CALL_TIMEOUT;
 use heleny_service::ChatServiceMessage;
 use heleny_service::Service;
+use heleny_service::StatsServiceMessage;
 use heleny_service::TaskServiceMessage;
 use heleny_service::Toolkit;
 use heleny_service::ToolkitServiceMessage;
@@ -24,6 +27,7 @@ use std::collections::HashMap;
 use std::collections::VecDeque;
 use tokio::sync::oneshot;
 use tokio::time::Instant;
+use tracing::debug;
 use tracing::warn;
 
 mod task;
@@ -35,7 +39,7 @@ use uuid::Uuid;
 mod task_logger;
 pub use task_logger::*;
 
-#[base_service(deps=["ConfigService","ChatService","HubService"])]
+#[base_service(deps=["ConfigService","ChatService","HubService"], tick=none)]
 pub struct TaskService {
     endpoint: Endpoint,
     running_tasks: HashMap<Uuid, TaskHandle>,
@@ -99,6 +103,19 @@ impl Service for TaskService {
                     .task_logs
                     .add_task(task.id, task.task_description.clone())
                     .await;
+                if let Some(reason) = self.over_budget().await {
+                    warn!("拒绝任务 {}: {}", task.id, reason);
+                    let _ = self
+                        .task_logs
+                        .get_log_sender()
+                        .send(TaskLoggerMessage::Log {
+                            id: task.id,
+                            context: format!("任务被拒绝: {}", reason),
+                        })
+                        .await;
+                    let _ = self.task_logs.set_status(task.id, TaskStatus::Fail).await;
+                    return Ok(());
+                }
                 info!("已添加新任务 {} : {}", task.id, task.task_description);
                 self.pending_tasks.push_back(task);
                 self.launch_tasks().await;
@@ -190,6 +207,25 @@ impl Service for TaskService {
 }
 
 impl TaskService {
+    /// 今日 LLM 用量超出预算时返回原因, StatsService 不可用时不限制
+    async fn over_budget(&self) -> Option<String> {
+        match self
+            .endpoint
+            .call(
+                STATS_SERVICE,
+                |feedback| StatsServiceMessage::CheckBudget { feedback },
+                CALL_TIMEOUT,
+            )
+            .await
+        {
+            Ok(reason) => reason,
+            Err(e) => {
+                debug!("查询用量预算失败, 不做限制: {}", e);
+                None
+            }
+        }
+    }
+
     async fn launch_tasks(&mut self) {
         while self.running_tasks.len() < self.config.max_running_tasks {
             let Some(task) = self.pending_tasks.pop_front() else {
@@ -1,8 +1,11 @@
 use anyhow::Context;
 use anyhow::Result;
 use heleny_bus::endpoint::SubEndpoint;
+use heleny_bus::trace;
 use heleny_proto::ExecutorModel;
 use heleny_proto::PlannerModel;
+use heleny_proto::ToolReply;
+use heleny_proto::TraceContext;
 use heleny_service::Toolkit;
 use tokio::sync::mpsc;
 use tokio::sync::oneshot;
@@ -21,6 +24,10 @@ pub struct Task {
     log_tx: mpsc::Sender<TaskLoggerMessage>,
     max_working_loop: usize,
     current: usize,
+    /// 创建任务的请求所在的 trace
+    trace: Option<TraceContext>,
+    /// Executor 上一次使用的 API, 变化时记入任务日志
+    provider: Option<String>,
 }
 
 pub struct TaskHandle {
@@ -43,13 +50,15 @@ impl Task {
             log_tx,
             max_working_loop,
             current: 0,
+            trace: trace::current(),
+            provider: None,
         }
     }
 
     pub fn launch(mut self) -> TaskHandle {
         let id = self.id;
         info!("启动任务 {}, 描述: {}", id, self.task_description);
-        let handle = tokio::spawn(async move {
+        let handle = tokio::spawn(trace::within(self.trace, async move {
             let success;
             match self.run().await {
                 Ok(_) => {
@@ -70,12 +79,15 @@ impl Task {
             {
                 warn!("发送任务结束信息失败: {}", e);
             };
-        });
+        }));
         TaskHandle { id, handle }
     }
 
     pub async fn run(&mut self) -> Result<()> {
         let (mut executor, mut toolkit) = self.preprocess().await?;
+        if executor.native_tools() {
+            return self.run_native(executor, toolkit).await;
+        }
         let mut input = self.task_description.clone();
         while self.current < self.max_working_loop {
             let intent = match executor.get_intent(&input).await {
@@ -86,6 +98,7 @@ impl Task {
                     continue;
                 }
             };
+            self.log_provider(executor.provider()).await;
             if intent.tool.is_none() && intent.command.is_none() {
                 self.log(intent.reason).await;
                 return Ok(());
@@ -103,9 +116,49 @@ impl Task {
         Err(anyhow::anyhow!(context))
     }
 
+    /// 原生工具调用模式, 一轮可能包含多个调用, 结果以工具消息交回模型
+    async fn run_native(&mut self, mut executor: ExecutorModel, mut toolkit: Toolkit) -> Result<()> {
+        let mut message = Some(self.task_description.clone());
+        while self.current < self.max_working_loop {
+            let reply = match executor.get_tool_calls(message.as_deref()).await {
+                Ok(reply) => reply,
+                Err(e) => {
+                    self.log(format!("获取工具调用失败, 重试: {}", e)).await;
+                    self.current += 1;
+                    continue;
+                }
+            };
+            self.log_provider(executor.provider()).await;
+            message = None;
+            let (text, calls) = match reply {
+                ToolReply::Text(text) => {
+                    self.log(text).await;
+                    return Ok(());
+                }
+                ToolReply::Calls { text, calls } => (text.unwrap_or_default(), calls),
+            };
+            if !text.is_empty() {
+                self.log(&text).await;
+            }
+            for call in calls {
+                if let Ok(call) = serde_json::to_string(&call) {
+                    self.log(call).await;
+                }
+                let result = toolkit.call(call.clone(), text.clone()).await;
+                self.log(format!("<tool_result>{}</tool_result>", result)).await;
+                executor.push_tool_result(&call, result);
+            }
+            self.current += 1;
+        }
+        let context = "达到最大工作循环限制";
+        self.log(context).await;
+        Err(anyhow::anyhow!(context))
+    }
+
     async fn preprocess(&self) -> Result<(ExecutorModel, Toolkit)> {
         let planner = match self.get_planner().await {
             Ok(planner) => {
+                planner.set_task(self.id);
                 self.log("成功获取 Planner").await;
                 planner
             }
@@ -119,6 +172,9 @@ impl Task {
         for i in 0..3 {
             tools_list = match planner.get_tools_list(&self.task_description).await {
                 Ok(tools_list) => {
+                    if let Some(provider) = planner.provider() {
+                        self.log(format!("Planner 使用 API: {}", provider)).await;
+                    }
                     self.log(format!("成功获取所需工具列表: {:?}", tools_list))
                         .await;
                     Some(tools_list)
@@ -154,7 +210,12 @@ impl Task {
         };
         let executor = match self.get_executor().await {
             Ok(mut executor) => {
-                executor.add_preset(toolkit.get_manuals());
+                executor.set_task(self.id);
+                if executor.native_tools() {
+                    executor.set_tools(toolkit.get_functions().to_vec());
+                } else {
+                    executor.add_preset(toolkit.get_manuals());
+                }
                 self.log("成功获取所需 Executor").await;
                 executor
             }
@@ -184,6 +245,16 @@ impl Task {
             .await;
     }
 
+    async fn log_provider(&mut self, provider: Option<String>) {
+        let Some(provider) = provider else {
+            return;
+        };
+        if self.provider.as_ref() != Some(&provider) {
+            self.log(format!("Executor 使用 API: {}", provider)).await;
+            self.provider = Some(provider);
+        }
+    }
+
     async fn get_planner(&self) -> Result<PlannerModel> {
         let (tx, rx) = oneshot::channel();
         self.send(WorkerMessage::GetPlanner { feedback: tx })
@@ -12,7 +12,7 @@ use heleny_service::ToolkitEndpoint;
 use tokio::time::Instant;
 use uuid::Uuid;
 
-#[base_service(deps=["ToolkitService","UserService"])]
+#[base_service(deps=["ToolkitService","UserService"], tick=none)]
 pub struct TestService {
     endpoint: Endpoint,
 }
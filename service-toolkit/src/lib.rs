@@ -15,7 +15,9 @@ use heleny_proto::ServiceRole;
 use heleny_proto::TOOL_ABSTRACTS;
 use heleny_proto::ToolAbstract;
 use heleny_proto::ToolDescription;
+use heleny_proto::ToolFunction;
 use heleny_proto::ToolManual;
+use heleny_proto::dedup_function_names;
 use heleny_service::ConfigServiceMessage;
 use heleny_service::Service;
 use heleny_service::Toolkit;
@@ -34,7 +36,7 @@ use crate::config::*;
 
 mod config;
 
-#[base_service(deps=["ConfigService","FsService","HubService"])]
+#[base_service(deps=["ConfigService","FsService","HubService"], tick=none)]
 pub struct ToolkitService {
     endpoint: Endpoint,
     tool_manuals: HashMap<String, ToolManual>,
@@ -117,11 +119,19 @@ impl Service for ToolkitService {
                     manuals.push(manual);
                     tools.insert(name.clone(), tool);
                 }
+                let mut functions: Vec<ToolFunction> = manuals
+                    .iter()
+                    .flat_map(|manual| manual.get_functions())
+                    .collect();
+                for (name, renamed) in dedup_function_names(&mut functions) {
+                    warn!("工具函数名 {} 重复, 改为 {}", name, renamed);
+                }
                 let toolkit = Toolkit::new(
                     task_id,
                     task_description,
                     self.endpoint.create_sender_endpoint(),
                     serde_json::to_string(&manuals).context("序列化工具手册失败")?,
+                    functions,
                     tools,
                 );
                 if let Err(_) = feedback.send(toolkit) {
@@ -20,7 +20,7 @@ use crate::config::Config;
 mod comfyui;
 mod config;
 
-#[base_service(deps=["ConfigService","FsService","ToolkitService"])]
+#[base_service(deps=["ConfigService","FsService","ToolkitService"], tick=none)]
 pub struct ToolsService{
     endpoint:Endpoint,
 }
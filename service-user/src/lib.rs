@@ -9,6 +9,8 @@ use heleny_proto::AnyMessage;
 use heleny_proto::ConsentRequestion;
 use heleny_proto::DISPLAY_MESSAGES;
 use heleny_proto::HEALTH;
+use heleny_proto::HUB_SERVICE;
+use heleny_proto::LLM_USAGE;
 use heleny_proto::KERNEL_NAME;
 use heleny_proto::Resource;
 use heleny_proto::SCHEDULE;
@@ -16,7 +18,9 @@ use heleny_proto::ServiceRole;
 use heleny_proto::TASK_ABSTRACT;
 use heleny_proto::TOOL_ABSTRACTS;
 use heleny_proto::TOTAL_BUS_TRAFFIC;
+use heleny_proto::TRAFFIC_MATRIX;
 use heleny_proto::UserDecision;
+use heleny_service::CALL_TIMEOUT;
 use heleny_service::CommonMessage;
 use heleny_service::KernelMessage;
 use heleny_service::Service;
@@ -33,16 +37,18 @@ use crate::user::User;
 
 mod user;
 
-static RESOURCES: [&'static str; 6] = [
+static RESOURCES: [&'static str; 8] = [
     DISPLAY_MESSAGES,
     TOTAL_BUS_TRAFFIC,
     HEALTH,
     TASK_ABSTRACT,
     SCHEDULE,
     TOOL_ABSTRACTS,
+    TRAFFIC_MATRIX,
+    LLM_USAGE,
 ];
 
-#[base_service(deps=["HubService"])]
+#[base_service(deps=["HubService"], tick=none)]
 pub struct UserService {
     endpoint: Endpoint,
     users: Vec<User>,
@@ -66,6 +72,15 @@ impl Service for UserService {
         };
         Ok(Box::new(instance))
     }
+    /// 重启后的 HubService 不再有之前的订阅
+    async fn on_dependency_restarted(&mut self, name: &str) -> Result<()> {
+        if name == HUB_SERVICE {
+            for resource in RESOURCES {
+                subscribe_resource(&self.endpoint, resource).await?;
+            }
+        }
+        Ok(())
+    }
     async fn handle(
         &mut self,
         name: String,
@@ -73,20 +88,27 @@ impl Service for UserService {
         msg: UserServiceMessage,
     ) -> Result<()> {
         match msg {
-            UserServiceMessage::Login(_frontend_type) => {
+            UserServiceMessage::Login { frontend, feedback } => {
                 info!("用户 {} 登陆", name);
-                self.users.push(User {
-                    name: name.to_string(),
-                    _frontend_type,
-                });
-                self.endpoint
-                    .send(
+                let token = self
+                    .endpoint
+                    .call(
                         KERNEL_NAME,
-                        KernelMessage::SetUser {
+                        |feedback| KernelMessage::SetUser {
                             name: name.to_string(),
+                            feedback,
                         },
+                        CALL_TIMEOUT,
                     )
                     .await
+                    .context("签发用户 token 失败")?;
+                self.users.push(User {
+                    name: name.to_string(),
+                    token,
+                    _frontend_type: frontend,
+                });
+                let _ = feedback.send(token);
+                Ok(())
             }
             UserServiceMessage::RequestConsent { body } => {
                 let request_id = Uuid::new_v4();
@@ -108,7 +130,16 @@ impl Service for UserService {
                 Ok(())
             }
             UserServiceMessage::Logout => {
-                self.users.retain(|user| user.name != name);
+                info!("用户 {} 登出", name);
+                let (logout, users): (Vec<User>, Vec<User>) = std::mem::take(&mut self.users)
+                    .into_iter()
+                    .partition(|user| user.name == name);
+                self.users = users;
+                for user in logout {
+                    self.endpoint
+                        .send(KERNEL_NAME, KernelMessage::RevokeToken { token: user.token })
+                        .await?;
+                }
                 Ok(())
             }
             UserServiceMessage::MakeDecision { req_id, approval } => {
@@ -1,6 +1,8 @@
 use heleny_proto::FrontendType;
+use uuid::Uuid;
 
 pub struct User {
     pub name: String,
+    pub token: Uuid,
     pub _frontend_type: FrontendType,
 }
@@ -57,7 +57,7 @@ impl WebuiService {
                 .await
             }
             FrontendCommand::Shutdown => {
-                self.endpoint
+                self.user
                     .send(KERNEL_NAME, KernelMessage::Shutdown)
                     .await
             }
@@ -148,6 +148,21 @@ impl WebuiService {
                 let data = BASE64_STANDARD.decode(data_base64).unwrap_or_default();
                 send_file(&self.endpoint, ChatRole::User, "webui", &file_name, data).await
             }
+            FrontendCommand::StartService { name } => {
+                self.user
+                    .send(KERNEL_NAME, KernelMessage::StartService { name })
+                    .await
+            }
+            FrontendCommand::StopService { name } => {
+                self.user
+                    .send(KERNEL_NAME, KernelMessage::StopService { name })
+                    .await
+            }
+            FrontendCommand::RestartService { name } => {
+                self.user
+                    .send(KERNEL_NAME, KernelMessage::RestartService { name })
+                    .await
+            }
         }
     }
 }
@@ -29,6 +29,7 @@ impl WebuiService {
                 payload: ResourcePayload::DisplayMessages {
                     new: false,
                     messages: history,
+                    streaming: false,
                 },
             }),
         )
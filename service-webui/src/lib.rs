@@ -25,6 +25,7 @@ use heleny_proto::Resource;
 use heleny_proto::ServiceRole;
 use heleny_proto::USER_SERVICE;
 use heleny_proto::downcast;
+use heleny_service::CALL_TIMEOUT;
 use heleny_service::Service;
 use heleny_service::UserServiceMessage;
 use heleny_service::WebuiServiceMessage;
@@ -50,9 +51,11 @@ mod config;
 mod message;
 mod register;
 
-#[base_service(deps=["ConfigService","UserService"])]
+#[base_service(deps=["ConfigService","UserService"], tick=none)]
 pub struct WebuiService {
     endpoint: Endpoint,
+    /// 携带登录时签发的用户 token, 用于关机, 启停服务等需要用户身份的操作
+    user: Endpoint,
     router: HashMap<Uuid, mpsc::Sender<FrontendMessage>>,
     app_handle: JoinHandle<()>,
     /// 第一层session id，第二层task id
@@ -65,9 +68,18 @@ impl Service for WebuiService {
     async fn new(endpoint: Endpoint) -> Result<Box<Self>> {
         let config = get_from_config_service::<WebuiConfig>(&endpoint).await?;
         // 向User服务注册
-        endpoint
-            .send(USER_SERVICE, UserServiceMessage::Login(FrontendType::WEB))
-            .await?;
+        let token = endpoint
+            .call(
+                USER_SERVICE,
+                |feedback| UserServiceMessage::Login {
+                    frontend: FrontendType::WEB,
+                    feedback,
+                },
+                CALL_TIMEOUT,
+            )
+            .await
+            .context("登录 UserService 失败")?;
+        let user = endpoint.create_token_endpoint(token);
         // 开启 Web 服务
         let serve_dir = ServeDir::new("heleny-webui/dist")
             .not_found_service(ServeFile::new("heleny-webui/dist/index.html"));
@@ -99,6 +111,7 @@ impl Service for WebuiService {
         // 新建实例
         let instance = Self {
             endpoint,
+            user,
             router: HashMap::new(),
             app_handle,
             session_task_logs: HashMap::new(),
@@ -0,0 +1,136 @@
+use anyhow::Result;
+use heleny_bus::endpoint::Endpoint;
+use heleny_bus::recorder;
+use heleny_kernel::Kernel;
+use heleny_proto::AnyMessage;
+use heleny_proto::TrafficRecord;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+use tracing::debug;
+use tracing::info;
+use tracing::warn;
+
+/// 把一条记录还原成消息, 返回 None 表示跳过
+pub type Decoder = Box<dyn Fn(&TrafficRecord) -> Option<Box<dyn AnyMessage>>>;
+
+/// 把录下的 Bus 流量重新喂给只运行部分服务的内核
+pub struct Replayer {
+    script: Vec<TrafficRecord>,
+    running: HashSet<String>,
+    decoders: HashMap<String, Decoder>,
+    endpoints: HashMap<String, Endpoint>,
+}
+
+impl Replayer {
+    /// 读取脚本, 并让内核只运行 `running` 里的服务
+    pub fn load<P: AsRef<Path>>(kernel: &mut Kernel, path: P, running: &[&str]) -> Result<Self> {
+        let script = recorder::load(path)?;
+        kernel.select_services(running.iter().copied());
+        Ok(Self {
+            script,
+            running: running.iter().map(|name| name.to_string()).collect(),
+            decoders: HashMap::new(),
+            endpoints: HashMap::new(),
+        })
+    }
+
+    /// 注册解码器, `message` 为 "Type" 或 "Type::Variant"
+    pub fn decoder<F>(mut self, message: &str, decoder: F) -> Self
+    where
+        F: Fn(&TrafficRecord) -> Option<Box<dyn AnyMessage>> + 'static,
+    {
+        self.decoders.insert(message.to_string(), Box::new(decoder));
+        self
+    }
+
+    /// 需要重放的记录: 发往运行中的服务, 且来源不在运行中
+    fn inputs(&self) -> impl Iterator<Item = &TrafficRecord> {
+        self.script.iter().filter(|record| {
+            self.running.contains(&record.target) && !self.running.contains(&record.source)
+        })
+    }
+
+    /// 按记录里的来源和身份准备 Endpoint, 需在内核 run 之前调用
+    pub async fn prepare(&mut self, kernel: &mut Kernel) -> Result<()> {
+        let sources: HashMap<String, _> = self
+            .inputs()
+            .map(|record| (record.source.clone(), record.role))
+            .collect();
+        for (source, role) in sources {
+            let mut endpoint = kernel.get_endpoint(source.clone(), 32, role).await?;
+            // 重放端不关心回复, 但要把收件箱清空, 免得阻塞 Bus
+            let (mut from_bus, _) = endpoint.get_rx()?;
+            tokio::spawn(async move {
+                while let Some(msg) = from_bus.recv().await {
+                    debug!("重放端收到 {} 的回复: {:?}", msg.name, msg.payload);
+                }
+            });
+            self.endpoints.insert(source, endpoint);
+        }
+        Ok(())
+    }
+
+    /// 依次重放, `keep_timing` 为 true 时按原始间隔发送, 返回实际发送的条数
+    pub async fn replay(&self, keep_timing: bool) -> Result<usize> {
+        let mut sent = 0;
+        let mut last: Option<&TrafficRecord> = None;
+        for record in self.inputs() {
+            let key = format!("{}::{}", record.message, record.variant);
+            let decoder = match self
+                .decoders
+                .get(&key)
+                .or_else(|| self.decoders.get(&record.message))
+            {
+                Some(decoder) => decoder,
+                None => {
+                    debug!("没有 {} 的解码器, 跳过", key);
+                    continue;
+                }
+            };
+            let payload = match decoder(record) {
+                Some(payload) => payload,
+                None => continue,
+            };
+            if keep_timing && let Some(last) = last {
+                let gap = (record.time - last.time).to_std().unwrap_or(Duration::ZERO);
+                tokio::time::sleep(gap).await;
+            }
+            last = Some(record);
+            let endpoint = self
+                .endpoints
+                .get(&record.source)
+                .ok_or_else(|| anyhow::anyhow!("{} 的 Endpoint 未准备", record.source))?;
+            match endpoint.send_box(&record.target, payload).await {
+                Ok(()) => sent += 1,
+                Err(e) => warn!(
+                    "重放 {} -> {} 的 {} 失败: {}",
+                    record.source, record.target, key, e
+                ),
+            }
+        }
+        info!("重放完成, 共发送 {} 条", sent);
+        Ok(sent)
+    }
+}
+
+/// 从 Debug 输出里取出一个字符串字段, 例如 `path: "a.txt"`
+pub fn debug_str_field(payload: &str, field: &str) -> Option<String> {
+    let start = payload.find(&format!("{}: \"", field))? + field.len() + 3;
+    let mut value = String::new();
+    let mut chars = payload[start..].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                other => value.push(other),
+            },
+            c => value.push(c),
+        }
+    }
+    None
+}
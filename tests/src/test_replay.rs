@@ -0,0 +1,73 @@
+mod replay;
+
+use heleny_kernel::Kernel;
+use heleny_proto::AnyMessage;
+use heleny_proto::ServiceRole;
+use heleny_service::FsServiceMessage;
+use heleny_utils::init_tracing;
+use replay::Replayer;
+use replay::debug_str_field;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tracing::info;
+use tracing::info_span;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    info!("test start!");
+    dotenvy::dotenv().ok();
+    let _ = init_tracing("./logs".into());
+    let span = info_span!("Kernel");
+    let _guard = span.enter();
+
+    let script = std::env::var("HELENIUM_REPLAY")
+        .unwrap_or_else(|_| concat!(env!("CARGO_MANIFEST_DIR"), "/scripts/replay_fs.jsonl").into());
+    let mut kernel = Kernel::new(64, 32).await.expect("kernel启动失败");
+    let mut replayer = Replayer::load(&mut kernel, &script, &["FsService"])?.decoder(
+        "FsServiceMessage::Write",
+        |record| {
+            let (feedback, _) = oneshot::channel();
+            let payload: Box<dyn AnyMessage> = Box::new(FsServiceMessage::Write {
+                path: debug_str_field(&record.payload, "path")?.into(),
+                content: debug_str_field(&record.payload, "content")?,
+                feedback,
+            });
+            Some(payload)
+        },
+    );
+    replayer.prepare(&mut kernel).await?;
+    let endpoint = kernel
+        .get_endpoint("Test".to_string(), 32, ServiceRole::Standard)
+        .await
+        .expect("未获取endpoint");
+    let rx = kernel.wait_for("FsService".to_string()).await;
+
+    tokio::spawn(async move {
+        kernel.run().await;
+    });
+
+    info!("wait for FsService");
+    rx.await.expect("等待失败").unwrap();
+
+    let sent = replayer.replay(true).await?;
+    assert_eq!(sent, 2, "应当重放两条写入");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let (tx, rx) = oneshot::channel();
+    endpoint
+        .send(
+            "FsService",
+            FsServiceMessage::Read {
+                path: PathBuf::from("replay.txt"),
+                feedback: tx,
+            },
+        )
+        .await
+        .expect("FsService通信失败");
+    let content = rx.await.expect("读取失败");
+    assert_eq!(content, "second\nline");
+
+    info!("test pass!");
+    Ok(())
+}
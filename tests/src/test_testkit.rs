@@ -0,0 +1,66 @@
+use heleny_proto::ServiceRole;
+use heleny_service::FsServiceMessage;
+use heleny_testkit::MockService;
+use heleny_testkit::TestKit;
+use heleny_utils::init_tracing;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tracing::info;
+use tracing::info_span;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    info!("test start!");
+    let _ = init_tracing("./logs".into());
+    let span = info_span!("Kernel");
+    let _guard = span.enter();
+
+    // 用替身代替 FsService, 不读写磁盘
+    let fs = MockService::new("FsService").on(|msg: FsServiceMessage| {
+        if let FsServiceMessage::Read { path, feedback } = msg {
+            let _ = feedback.send(format!("mock: {}", path.display()));
+        }
+        Ok(())
+    });
+    let mut kit = TestKit::builder()
+        .services(["HubService"])
+        .mock(fs)
+        .client("Test", ServiceRole::Standard)
+        .start()
+        .await?;
+
+    let (tx, rx) = oneshot::channel();
+    kit.client("Test")?
+        .send(
+            "FsService",
+            FsServiceMessage::Read {
+                path: "test.txt".into(),
+                feedback: tx,
+            },
+        )
+        .await?;
+    assert_eq!(rx.await?, "mock: test.txt");
+    info!("mock reply success!");
+
+    kit.wait_for_message(
+        "Test",
+        "FsService",
+        "FsServiceMessage::Read",
+        Duration::from_secs(1),
+    )
+    .await?;
+    let traffic = kit.traffic();
+    traffic.assert_sent(
+        "FsService",
+        "KernelService",
+        "KernelServiceMessage::UploadStatus",
+    );
+    traffic.assert_not_sent("Test", "HubService", "HubServiceMessage");
+
+    let report = kit.shutdown().await?.expect("未收到关机报告");
+    info!("{}", report);
+    assert!(report.is_clean());
+
+    info!("test pass!");
+    Ok(())
+}